@@ -1,25 +1,128 @@
-use anyhow::{Context, Result};
-use clap::Parser as CliParser;
+use anyhow::{Context, Result, bail};
+use clap::{Parser as CliParser, ValueEnum};
+use nbt::Nbt;
 use oxc::{
     allocator::{Allocator, Box as OxcBox},
-    ast::ast::{
-        ArrowFunctionExpression, BindingIdentifier, BindingPattern, BindingPatternKind,
-        BindingRestElement, CallExpression, Expression, FormalParameters, Function, FunctionBody,
-        Program, Statement,
+    ast::{
+        AstKind,
+        ast::{
+            Argument, ArrayExpressionElement, ArrowFunctionExpression, AssignmentExpression,
+            AssignmentOperator, AssignmentTarget, AssignmentTargetMaybeDefault,
+            AssignmentTargetProperty, AwaitExpression, BinaryExpression, BinaryOperator,
+            BindingIdentifier, BindingPattern, BindingPatternKind, BindingRestElement,
+            BlockStatement, CallExpression, ComputedMemberExpression, Declaration,
+            ExportDefaultDeclarationKind, Expression, ForStatement, ForStatementInit,
+            FormalParameters, Function, FunctionBody, IdentifierReference, NewExpression,
+            ObjectExpression, ObjectPropertyKind, Program, PropertyKind, SimpleAssignmentTarget,
+            Statement, StaticMemberExpression, SwitchStatement, TryStatement, UnaryOperator,
+            UpdateExpression, UpdateOperator, VariableDeclaration,
+        },
     },
-    ast_visit::Visit,
+    ast_visit::{Visit, walk},
+    diagnostics::{OxcDiagnostic, Severity},
     parser::Parser,
     semantic::{ScopeFlags, SemanticBuilder},
     span::{GetSpan, SourceType, Span},
 };
 
+mod nbt;
+
 #[derive(CliParser)]
 struct CliArguments {
     path: std::path::PathBuf,
+    /// Annotate the generated functions with comments describing what each block was compiled from
+    #[arg(long)]
+    explain: bool,
+    /// Write NBT without optional whitespace, which makes the generated functions smaller
+    #[arg(long)]
+    compact_nbt: bool,
+    /// How to print parse errors, semantic errors and compile warnings
+    #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Human)]
+    diagnostics_format: DiagnosticsFormat,
+    /// Start the main function with comments saying which smelter version and pack format
+    /// generated it, and when
+    #[arg(long)]
+    emit_metadata: bool,
+    /// Leave the generation time out of the metadata comments so that the output is reproducible
+    #[arg(long)]
+    no_timestamp: bool,
+    /// Directory to write the data pack to, usually `<world>/datapacks/<name>` (defaults to
+    /// `smelter_prototype`)
+    #[arg(long)]
+    out_dir: Option<std::path::PathBuf>,
+    /// Fail instead of warning when the output directory doesn't look like a data pack root
+    #[arg(long)]
+    strict_layout: bool,
+    /// Write the data pack even if the output directory already has a `pack.mcmeta` that smelter
+    /// didn't write, overwriting it
+    #[arg(long)]
+    force: bool,
+    /// Exit with an error after writing the data pack if any compile or output warnings were
+    /// produced
+    #[arg(long)]
+    fail_on_warning: bool,
+    /// Replace the body of a generated core function (like `resolve` or `invoke`) with the
+    /// commands in a file, given as `<name>=<path>`; can be repeated
+    #[arg(long, value_name = "NAME=PATH")]
+    override_core_function: Vec<String>,
+    /// Make the output depend only on the source, for checking it into version control: implies
+    /// `--no-timestamp` and writes functions in name order
+    #[arg(long)]
+    reproducible: bool,
+    /// Delete every `.mcfunction` file in the output's `data/smelter/function` folder before
+    /// writing, so that functions left over from earlier compiles don't linger
+    #[arg(long)]
+    clean: bool,
+    /// Also write `STORAGE.md` to the output directory, describing the storage and scoreboard the
+    /// generated functions use, for debugging the data pack in game
+    #[arg(long)]
+    emit_runtime_docs: bool,
+    /// Record every function call, with its arguments, to the `trace` list in `smelter:smelter`
+    /// storage
+    #[arg(long)]
+    trace: bool,
+    /// How to name the functions compiled from JS functions: after their source (`<name>_<offset>`)
+    /// or with short hashes that don't reveal the source's structure
+    #[arg(long, value_enum, default_value_t = NameStrategy::Readable)]
+    function_names: NameStrategy,
+    /// Check that every NBT value written by the generated functions is well-formed SNBT, and warn
+    /// about any that isn't (always done in debug builds)
+    #[arg(long)]
+    validate_nbt: bool,
+    /// Also generate `smelter:uninstall`, which removes the storage and scoreboard objective the
+    /// data pack uses; run it before disabling the pack
+    #[arg(long)]
+    emit_uninstall: bool,
+    /// Also write `functions.json` to the output directory, listing every generated function with
+    /// its kind (`user`, `core`, `subfunction`, `wrapper` or `macro`), for build tools that refer
+    /// to the functions
+    #[arg(long)]
+    emit_function_list: bool,
+    /// Merge the fields of a JSON file into the generated `pack.mcmeta`, for sections like
+    /// `filter` and `overlays`; fields of its `pack` object are merged into `pack`, except the
+    /// description and formats
+    #[arg(long, value_name = "PATH")]
+    mcmeta_extra: Option<std::path::PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DiagnosticsFormat {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum NameStrategy {
+    Readable,
+    Hashed,
 }
 
 fn main() -> Result<()> {
-    let args = CliArguments::parse();
+    run(CliArguments::parse())
+}
+
+fn run(args: CliArguments) -> Result<()> {
+    // Any error returned here makes the process exit with a nonzero code
     let path = args.path;
 
     let content = std::fs::read_to_string(&path)
@@ -27,57 +130,476 @@ fn main() -> Result<()> {
 
     let source_type =
         SourceType::from_path(path).with_context(|| format!("Couldn't identify source type"))?;
-    let allocator = Allocator::default();
-    let parser_result = Parser::new(&allocator, &content, source_type).parse();
-    let program = parser_result.program;
+    let timestamp = if args.no_timestamp || args.reproducible {
+        None
+    } else {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()
+            .map(|duration| duration.as_secs())
+    };
+    let core_function_overrides = args
+        .override_core_function
+        .iter()
+        .map(|argument| {
+            let (name, override_path) = argument.split_once('=').with_context(|| {
+                format!("Core function override `{argument}` should be `<name>=<path>`")
+            })?;
+            let body = std::fs::read_to_string(override_path)
+                .with_context(|| format!("Couldn't read file `{override_path}`"))?;
+            Ok((
+                String::from(name),
+                body.lines().map(String::from).collect::<Vec<String>>(),
+            ))
+        })
+        .collect::<Result<Vec<(String, Vec<String>)>>>()?;
+    let options = CompileOptions {
+        compact_nbt: args.compact_nbt,
+        explain: args.explain,
+        emit_metadata: args.emit_metadata,
+        timestamp,
+        core_function_overrides,
+        trace: args.trace,
+        function_names: args.function_names,
+        emit_uninstall: args.emit_uninstall,
+        reproducible: args.reproducible,
+    };
+    let mut compiled_pack = compile(&content, source_type, options);
+    if let Some(extra_path) = &args.mcmeta_extra {
+        let extra = std::fs::read_to_string(extra_path)
+            .with_context(|| format!("Couldn't read file `{}`", extra_path.display()))?;
+        compiled_pack.pack_mcmeta = merge_pack_mcmeta(&compiled_pack.pack_mcmeta, &extra)
+            .with_context(|| format!("Couldn't merge `{}`", extra_path.display()))?;
+    }
+    let mut warning_count = compiled_pack.warnings.len();
+    if args.validate_nbt || cfg!(debug_assertions) {
+        let nbt_warnings = check_generated_nbt(&compiled_pack.functions);
+        if !nbt_warnings.is_empty() {
+            println!("NBT warnings:\n{}", nbt_warnings.join("\n"));
+            warning_count += nbt_warnings.len();
+        }
+    }
 
-    if !parser_result.errors.is_empty() {
-        let error_messages = parser_result
-            .errors
-            .into_iter()
-            .map(|error| format!("{:?}", error.with_source_code(content.clone())))
-            .collect::<Vec<String>>()
-            .join("\n");
-        println!("Parse errors:\n{error_messages}");
+    match args.diagnostics_format {
+        DiagnosticsFormat::Human => {
+            print_diagnostics(
+                "Parse errors",
+                std::mem::take(&mut compiled_pack.parse_errors),
+                &content,
+            );
+            print_diagnostics(
+                "Semantic errors",
+                std::mem::take(&mut compiled_pack.semantic_errors),
+                &content,
+            );
+            print_diagnostics(
+                "Compile warnings",
+                std::mem::take(&mut compiled_pack.warnings),
+                &content,
+            );
+        }
+        DiagnosticsFormat::Json => {
+            let diagnostics = compiled_pack
+                .parse_errors
+                .iter()
+                .chain(compiled_pack.semantic_errors.iter())
+                .chain(compiled_pack.warnings.iter())
+                .map(diagnostic_to_json)
+                .collect::<Vec<serde_json::Value>>();
+            println!("{}", serde_json::Value::from(diagnostics));
+        }
     }
 
-    let semantic_result = SemanticBuilder::new()
-        .with_check_syntax_error(true)
-        .build(&program);
+    let out_dir = match args.out_dir {
+        Some(out_dir) => {
+            let layout_warnings = check_output_layout(&out_dir);
+            if !layout_warnings.is_empty() {
+                let messages = layout_warnings.join("\n");
+                if args.strict_layout {
+                    bail!("Output directory doesn't look like a data pack root:\n{messages}");
+                }
+                println!("Output warnings:\n{messages}");
+                warning_count += layout_warnings.len();
+            }
+            out_dir
+        }
+        None => std::path::PathBuf::from("smelter_prototype"),
+    };
+    if let Some(message) = check_existing_pack(&out_dir) {
+        if !args.force {
+            bail!("{message}; pass `--force` to overwrite it");
+        }
+        println!("Output warnings:\n{message}, and it will be overwritten");
+        warning_count += 1;
+    }
+    write_pack(&compiled_pack, &out_dir, args.clean)?;
+    if args.emit_runtime_docs {
+        std::fs::write(out_dir.join("STORAGE.md"), runtime_docs())
+            .with_context(|| "Couldn't write file `STORAGE.md`")?;
+    }
+    if args.emit_function_list {
+        std::fs::write(
+            out_dir.join("functions.json"),
+            function_list_json(&compiled_pack),
+        )
+        .with_context(|| "Couldn't write file `functions.json`")?;
+    }
+    if args.fail_on_warning && warning_count > 0 {
+        bail!("{warning_count} warning(s) were produced");
+    }
+    Ok(())
+}
+
+fn check_output_layout(out_dir: &std::path::Path) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let display = out_dir.display();
+    let out_dir = std::path::absolute(out_dir).unwrap_or_else(|_| out_dir.to_path_buf());
+    let is_datapacks =
+        |path: &std::path::Path| path.file_name().is_some_and(|name| name == "datapacks");
+    if out_dir.join("level.dat").exists() {
+        warnings.push(format!(
+            "`{display}` looks like a world folder; data packs go in its `datapacks/<name>` folder"
+        ));
+    } else if is_datapacks(&out_dir) {
+        warnings.push(format!(
+            "`{display}` is a `datapacks` folder itself; data packs go in a folder inside it"
+        ));
+    } else if !out_dir.parent().is_some_and(is_datapacks) {
+        warnings.push(format!("`{display}` isn't inside a `datapacks` folder"));
+    }
+    warnings
+}
+
+fn check_existing_pack(out_dir: &std::path::Path) -> Option<String> {
+    // A `pack.mcmeta` that smelter didn't write means the directory holds some other data pack,
+    // which writing would clobber
+    let pack_mcmeta = std::fs::read_to_string(out_dir.join("pack.mcmeta")).ok()?;
+    (!pack_mcmeta.contains("\"smelter prototype\"")).then(|| {
+        format!(
+            "`{}` already has a `pack.mcmeta` that smelter didn't write",
+            out_dir.display()
+        )
+    })
+}
+
+fn check_generated_nbt(functions: &[Mcfunction]) -> Vec<String> {
+    // Only literal values are checked. Macro lines are skipped since their values aren't complete
+    // until the arguments are substituted in
+    let mut warnings = Vec::new();
+    for function in functions {
+        for (index, command) in function.body.iter().enumerate() {
+            if command.starts_with('$') || command.starts_with('#') {
+                continue;
+            }
+            let Some(modify_start) = command.find("data modify ") else {
+                continue;
+            };
+            let modify = &command[modify_start..];
+            let value = [" set value ", " append value "]
+                .iter()
+                .find_map(|operation| {
+                    modify
+                        .find(operation)
+                        .map(|start| &modify[start + operation.len()..])
+                });
+            if let Some(Err(message)) = value.map(nbt::check_snbt) {
+                warnings.push(format!(
+                    "smelter:{} line {}: {message}",
+                    function.name,
+                    index + 1
+                ));
+            }
+        }
+    }
+    warnings
+}
 
-    if !semantic_result.errors.is_empty() {
-        let error_messages = semantic_result
-            .errors
+fn print_diagnostics(heading: &str, diagnostics: Vec<OxcDiagnostic>, source: &str) {
+    if !diagnostics.is_empty() {
+        let messages = diagnostics
             .into_iter()
-            .map(|error| format!("{:?}", error.with_source_code(content.clone())))
+            .map(|diagnostic| format!("{:?}", diagnostic.with_source_code(source.to_string())))
             .collect::<Vec<String>>()
             .join("\n");
-        println!("Semantic errors:\n{error_messages}");
+        println!("{heading}:\n{messages}");
     }
+}
 
-    // println!("{:#?}", &program);
+fn diagnostic_to_json(diagnostic: &OxcDiagnostic) -> serde_json::Value {
+    let severity = match diagnostic.severity {
+        Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Advice => "advice",
+    };
+    let code = diagnostic
+        .code
+        .is_some()
+        .then(|| diagnostic.code.to_string());
+    let labels = diagnostic
+        .labels
+        .iter()
+        .flatten()
+        .map(|label| {
+            serde_json::json!({
+                "start": label.offset(),
+                "end": label.offset() + label.len(),
+                "message": label.label(),
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+    serde_json::json!({
+        "severity": severity,
+        "code": code,
+        "message": diagnostic.message,
+        "labels": labels,
+    })
+}
+
+fn function_list_json(pack: &CompiledPack) -> String {
+    // Listed in the order the functions are written, which is name order with `--reproducible`
+    let functions = pack
+        .functions
+        .iter()
+        .map(|function| {
+            let kind = pack
+                .function_kinds
+                .get(&function.name)
+                .map_or("user", |kind| kind.as_str());
+            serde_json::json!({
+                "name": format!("smelter:{}", function.name),
+                "kind": kind,
+            })
+        })
+        .collect::<Vec<serde_json::Value>>();
+    serde_json::json!({ "functions": functions }).to_string()
+}
+
+fn merge_pack_mcmeta(pack_mcmeta: &str, extra: &str) -> Result<String> {
+    // The description is kept so that `check_existing_pack` still recognizes the file, and the
+    // formats are kept since the generated commands are only valid for them
+    let serde_json::Value::Object(extra) = serde_json::from_str::<serde_json::Value>(extra)? else {
+        bail!("The file should contain a JSON object");
+    };
+    let mut merged: serde_json::Map<String, serde_json::Value> = serde_json::from_str(pack_mcmeta)?;
+    for (key, value) in extra {
+        if key != "pack" {
+            merged.insert(key, value);
+            continue;
+        }
+        let serde_json::Value::Object(extra_pack) = value else {
+            bail!("`pack` should be an object");
+        };
+        if let Some(serde_json::Value::Object(pack)) = merged.get_mut("pack") {
+            for (field, value) in extra_pack {
+                if !matches!(
+                    field.as_str(),
+                    "description"
+                        | "pack_format"
+                        | "min_format"
+                        | "max_format"
+                        | "supported_formats"
+                ) {
+                    pack.insert(field, value);
+                }
+            }
+        }
+    }
+    Ok(serde_json::to_string(&merged)?)
+}
 
-    let data_pack = compile_program(program);
-    std::fs::create_dir_all("smelter_prototype/data/smelter/function")
-        .with_context(|| "Couldn't create directories")?;
-    for function in data_pack {
+fn write_pack(pack: &CompiledPack, root: &std::path::Path, clean: bool) -> Result<()> {
+    let function_directory = root.join("data/smelter/function");
+    std::fs::create_dir_all(&function_directory).with_context(|| "Couldn't create directories")?;
+    if clean {
+        // Only the smelter namespace is cleared, since every function in it is generated
+        let entries = std::fs::read_dir(&function_directory)
+            .with_context(|| "Couldn't read the function directory")?;
+        for entry in entries {
+            let path = entry
+                .with_context(|| "Couldn't read the function directory")?
+                .path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "mcfunction")
+            {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("Couldn't remove file `{}`", path.display()))?;
+            }
+        }
+    }
+    for function in &pack.functions {
         std::fs::write(
-            format!(
-                "smelter_prototype/data/smelter/function/{}.mcfunction",
-                &function.name
-            ),
+            function_directory.join(format!("{}.mcfunction", &function.name)),
             function.body.join("\n"),
         )
         .with_context(|| format!("Couldn't write file `{}.mcfunction`", &function.name))?
     }
-    std::fs::write(
-        "smelter_prototype/pack.mcmeta",
-        "{\"pack\":{\"description\":\"smelter prototype\",\"min_format\":[94,1],\"max_format\":[94,1]}}",
-    ).with_context(|| "Couldn't write file: `pack.mcmeta`")?;
+    std::fs::write(root.join("pack.mcmeta"), &pack.pack_mcmeta)
+        .with_context(|| "Couldn't write file: `pack.mcmeta`")?;
 
     Ok(())
 }
 
+fn compile(source: &str, source_type: SourceType, options: CompileOptions) -> CompiledPack {
+    let allocator = Allocator::default();
+    let parser_result = Parser::new(&allocator, source, source_type).parse();
+    let program = parser_result.program;
+
+    let semantic_errors = SemanticBuilder::new()
+        .with_check_syntax_error(true)
+        .build(&program)
+        .errors;
+
+    // println!("{:#?}", &program);
+
+    let explain = options.explain;
+    let reproducible = options.reproducible;
+    let metadata = options.emit_metadata.then(|| {
+        compile_metadata_comments(if reproducible {
+            None
+        } else {
+            options.timestamp
+        })
+    });
+    let (kinded_functions, warnings) = compile_program(program, options);
+    let function_kinds = kinded_functions
+        .iter()
+        .map(|(kind, function)| (function.name.clone(), *kind))
+        .collect();
+    let mut functions = kinded_functions
+        .into_iter()
+        .map(|(_, function)| function)
+        .collect::<DataPack>();
+    if explain {
+        for function in functions.iter_mut() {
+            function.body = explain_commands(std::mem::take(&mut function.body));
+        }
+    }
+    if let Some(metadata) = metadata {
+        if let Some(main_function) = functions
+            .iter_mut()
+            .find(|function| function.name == "main")
+        {
+            let body = std::mem::take(&mut main_function.body);
+            main_function.body = metadata.into_iter().chain(body).collect();
+        }
+    }
+    if reproducible {
+        // Functions are otherwise in the order they were compiled, which shifts with the source
+        functions.sort_by(|left, right| left.name.cmp(&right.name));
+    }
+
+    CompiledPack {
+        functions,
+        function_kinds,
+        pack_mcmeta: format!(
+            "{{\"pack\":{{\"description\":\"smelter prototype\",\"min_format\":[{major},{minor}],\"max_format\":[{major},{minor}]}}}}",
+            major = PACK_FORMAT.0,
+            minor = PACK_FORMAT.1
+        ),
+        parse_errors: parser_result.errors,
+        semantic_errors,
+        warnings,
+    }
+}
+
+const PACK_FORMAT: (u32, u32) = (94, 1);
+
+fn compile_metadata_comments(timestamp: Option<u64>) -> Vec<String> {
+    let mut comments = vec![
+        format!(
+            "# Generated by smelter {} ({})",
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_NAME")
+        ),
+        format!("# Pack format {}.{}", PACK_FORMAT.0, PACK_FORMAT.1),
+    ];
+    if let Some(timestamp) = timestamp {
+        comments.push(format!("# Generated at Unix time {timestamp}"));
+    }
+    comments
+}
+
+// Paths in `smelter:smelter` storage, and what the generated functions keep there
+const STORAGE_LAYOUT: &[(&str, &str)] = &[
+    (
+        "environment_stack",
+        "Environments saved while functions are called. A function value's `environment_index` \
+         points at the environment it was created in. The one at index 0 is the top level's, \
+         which is also the global environment",
+    ),
+    (
+        "current_environment",
+        "The running function's environment: `bindings` holds its variables, `evaluations` holds \
+         the value of each expression by ID (`expr_<offset>`), and `parent` is the stack index of \
+         the enclosing environment (-1 at the top level). `returning` and `breaking` are set while \
+         a return or break unwinds",
+    ),
+    (
+        "current_arguments",
+        "The list of arguments being passed to the function that is about to be called",
+    ),
+    (
+        "current_this",
+        "The value of `this` for the function that is about to be called: the object a method \
+         was read from, or undefined",
+    ),
+    (
+        "current_return_value",
+        "The value returned by the function that was called last",
+    ),
+    (
+        "trace",
+        "With `--trace`, the name and arguments of every function called since `initialize` last ran",
+    ),
+    (
+        "internal",
+        "Arguments (`<helper>_args`) and results (`<helper>_result`) of the core functions, and \
+         lookup tables set up by `initialize`",
+    ),
+];
+
+fn runtime_docs() -> String {
+    let mut docs = vec![
+        String::from("# smelter runtime storage"),
+        String::new(),
+        String::from(
+            "Values are compounds tagged with their type, like `{number: 1.0d}`, `{string: 'a'}`, \
+             `{boolean: true}`, `{array: [...]}`, `{object: {...}}` or `{function: {name: \
+             '...', environment_index: 0}}`, and NaN is `{nan: true}`. Inspect them with `/data get storage \
+             smelter:smelter <path>`.",
+        ),
+        String::new(),
+        String::from(
+            "Strings are joined by substituting them into a quoted SNBT string, so joining strings \
+             that contain both `'` and `\"` gives undefined, and backslashes may be read as escapes.",
+        ),
+        String::new(),
+        String::from(
+            "Numbers are stored as doubles, but arithmetic (`+` on numbers, `-`, `*`, `/`, `%` and \
+             `**`) and bitwise operators compute on 32-bit scores. Operands are rounded down to \
+             integers (truncated toward zero for bitwise operators), `/` rounds the quotient down, \
+             and results wrap around on overflow. The compiler warns about divisions and fractional \
+             literal operands.",
+        ),
+        String::new(),
+    ];
+    docs.extend(
+        STORAGE_LAYOUT
+            .iter()
+            .map(|(path, description)| format!("- `{path}`: {description}.")),
+    );
+    docs.extend([
+        String::new(),
+        String::from(
+            "Scores on the `smelter_internal` objective are temporaries for the core functions, \
+             except `#debug`, which turns on debug logging when set to 1 or more.",
+        ),
+        String::new(),
+    ]);
+    docs.join("\n")
+}
+
 struct Mcfunction {
     name: String,
     body: Vec<String>,
@@ -85,77 +607,366 @@ struct Mcfunction {
 
 type DataPack = Vec<Mcfunction>;
 
-struct FunctionCompiler {
-    functions: Vec<Mcfunction>,
+#[derive(Clone, Copy)]
+enum FunctionKind {
+    // Compiled from a JS function, or `main` from the top level of the program
+    User,
+    Core,
+    // Split out of a user function or `main`, like a loop body or a branch
+    Subfunction,
+    // Compiled from a JS function with a `smelter <command>` directive
+    Wrapper,
+    Macro,
+}
+
+impl FunctionKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            FunctionKind::User => "user",
+            FunctionKind::Core => "core",
+            FunctionKind::Subfunction => "subfunction",
+            FunctionKind::Wrapper => "wrapper",
+            FunctionKind::Macro => "macro",
+        }
+    }
+}
+
+struct CompiledPack {
+    functions: DataPack,
+    function_kinds: std::collections::HashMap<String, FunctionKind>,
+    pack_mcmeta: String,
+    parse_errors: Vec<OxcDiagnostic>,
+    semantic_errors: Vec<OxcDiagnostic>,
+    warnings: Vec<OxcDiagnostic>,
+}
+
+struct CompileOptions {
+    compact_nbt: bool,
+    explain: bool,
+    emit_metadata: bool,
+    timestamp: Option<u64>,
+    core_function_overrides: Vec<(String, Vec<String>)>,
+    trace: bool,
+    function_names: NameStrategy,
+    emit_uninstall: bool,
+    reproducible: bool,
+}
+
+struct CompileContext {
+    options: CompileOptions,
+    diagnostics: Vec<OxcDiagnostic>,
+}
+
+impl CompileContext {
+    fn snbt(&self, value: &Nbt) -> String {
+        value.to_snbt(self.options.compact_nbt)
+    }
+
+    fn undefined_snbt(&self) -> String {
+        self.snbt(&Nbt::compound([("undefined", Nbt::Boolean(true))]))
+    }
+
+    fn warn(&mut self, code: &'static str, message: String, span: Span) {
+        self.diagnostics.push(
+            OxcDiagnostic::warn(message)
+                .with_error_code("smelter", code)
+                .with_label(span),
+        );
+    }
+
+    fn warn_unsupported(&mut self, what: String, span: Span) {
+        self.warn("unsupported-syntax", format!("Not supported: {what}"), span);
+    }
+
+    fn warn_class_syntax(&mut self, what: &str, span: Span) {
+        // The runtime has no objects with prototypes or constructors yet, so class syntax gets a
+        // dedicated message rather than the generic one
+        self.diagnostics.push(
+            OxcDiagnostic::warn(format!(
+                "Classes are not supported by smelter yet, so this {what} was not compiled"
+            ))
+            .with_error_code("smelter", "unsupported-class")
+            .with_help("Use functions and plain values instead")
+            .with_label(span),
+        );
+    }
+}
+
+struct FunctionCompiler<'c> {
+    context: &'c mut CompileContext,
+    functions: Vec<(FunctionKind, Mcfunction)>,
 }
 
-impl Visit<'_> for FunctionCompiler {
+impl Visit<'_> for FunctionCompiler<'_> {
     fn visit_function(&mut self, it: &Function<'_>, _: ScopeFlags) {
+        if it.generator {
+            // Compiling the body as a normal function would drop every `yield`, so generators aren't
+            // compiled at all
+            self.context.warn_unsupported(
+                String::from("generator functions"),
+                Span::new(it.span.start, it.params.span.end),
+            );
+            return;
+        }
+        if it.r#async {
+            // There's nothing to wait on in a tick, so async functions aren't compiled either, but
+            // their `await`s are still reported
+            self.context.warn_unsupported(
+                String::from("async functions"),
+                Span::new(it.span.start, it.params.span.end),
+            );
+            if let Some(body) = &it.body {
+                walk::walk_function_body(self, body);
+            }
+            return;
+        }
         if let Some(body) = &it.body {
-            self.functions
-                .extend(compile_function(&it.id, &it.params, body, &it.span));
+            self.functions.extend(compile_function(
+                self.context,
+                &it.id,
+                &it.params,
+                body,
+                &it.span,
+                uses_arguments_object(&it.params, body),
+                true,
+            ));
         }
     }
 
     fn visit_arrow_function_expression(&mut self, it: &ArrowFunctionExpression<'_>) {
-        self.functions
-            .extend(compile_function(&None, &it.params, &it.body, &it.span))
+        if it.r#async {
+            self.context.warn_unsupported(
+                String::from("async functions"),
+                Span::new(it.span.start, it.params.span.end),
+            );
+            walk::walk_function_body(self, &it.body);
+            return;
+        }
+        // Arrow functions don't have their own `arguments` or `this`, so references to them resolve
+        // to the enclosing function's bindings
+        self.functions.extend(compile_function(
+            self.context,
+            &None,
+            &it.params,
+            &it.body,
+            &it.span,
+            false,
+            false,
+        ))
+    }
+
+    fn visit_await_expression(&mut self, it: &AwaitExpression<'_>) {
+        // Reported here rather than in `compile_expression` so that `await`s in async functions,
+        // which aren't compiled, are covered too
+        self.context.warn_unsupported(
+            String::from("`await`"),
+            Span::new(it.span.start, it.span.start + 5),
+        );
+        walk::walk_await_expression(self, it);
+    }
+}
+
+struct ArgumentsObjectFinder {
+    found: bool,
+}
+
+impl Visit<'_> for ArgumentsObjectFinder {
+    fn visit_identifier_reference(&mut self, it: &IdentifierReference<'_>) {
+        if it.name == "arguments" {
+            self.found = true;
+        }
+    }
+
+    fn visit_function(&mut self, _: &Function<'_>, _: ScopeFlags) {
+        // Nested functions have their own `arguments`
     }
 }
 
+fn uses_arguments_object(parameters: &FormalParameters, body: &FunctionBody) -> bool {
+    let mut finder = ArgumentsObjectFinder { found: false };
+    walk::walk_formal_parameters(&mut finder, parameters);
+    walk::walk_function_body(&mut finder, body);
+    finder.found
+}
+
+const DEBUG_LOG_PREFIX: &str =
+    "execute if score #debug smelter_internal matches 1.. run tellraw @a '[smelter] ";
+
 fn debug_log(message: String) -> String {
-    format!(
-        "execute if score #debug smelter_internal matches 1.. run tellraw @a '[smelter] {message}'"
-    )
+    format!("{DEBUG_LOG_PREFIX}{message}'")
 }
 
-fn compile_program(program: Program) -> DataPack {
-    let core_functions: Vec<Mcfunction> = vec![
-        compile_init_function(),
+fn explain_commands(commands: Vec<String>) -> Vec<String> {
+    commands
+        .into_iter()
+        .flat_map(|command| {
+            // Precede each debug log with a comment carrying the same message (macro lines are
+            // skipped since their messages contain unexpanded arguments)
+            let comment = command
+                .strip_prefix(DEBUG_LOG_PREFIX)
+                .and_then(|rest| rest.strip_suffix('\''))
+                .map(|message| format!("# {message}"));
+            comment.into_iter().chain(std::iter::once(command))
+        })
+        .collect()
+}
+
+fn compile_program(
+    program: Program,
+    options: CompileOptions,
+) -> (Vec<(FunctionKind, Mcfunction)>, Vec<OxcDiagnostic>) {
+    let mut context = CompileContext {
+        options,
+        diagnostics: Vec::new(),
+    };
+    let mut core_functions: Vec<Mcfunction> = vec![
+        compile_init_function(&context),
         compile_identifier_resolution(),
-        compile_function_invocation(),
+        compile_binding_assignment(),
+        compile_function_invocation(&context),
         compile_stack_pop(),
+        compile_to_string(&context),
+        compile_is_truthy(&context),
+        compile_to_number(&context),
+        compile_number_parsing(),
     ];
+    core_functions.extend(compile_addition_functions(&context));
+    core_functions.extend(compile_arithmetic_functions(&context));
+    core_functions.extend(compile_bitwise_functions());
+    core_functions.extend(compile_to_array_functions(&context));
+    core_functions.extend(compile_equality_functions(&context));
+    core_functions.extend(compile_property_functions());
+    core_functions.extend(compile_string_functions(&context));
+    core_functions.extend(compile_parse_functions(&context));
+    core_functions.extend(compile_json_functions(&context));
+    core_functions.extend(compile_object_keys_functions(&context));
+    if context.options.emit_uninstall {
+        core_functions.push(compile_uninstall_function());
+    }
+    for (name, body) in std::mem::take(&mut context.options.core_function_overrides) {
+        // Overrides can only replace functions that smelter generates, since generated code calls
+        // them by name and relies on the storage they read and write
+        match core_functions
+            .iter()
+            .position(|function| function.name == name)
+        {
+            Some(index) => core_functions[index].body = body,
+            None => context.diagnostics.push(
+                OxcDiagnostic::warn(format!(
+                    "`{name}` isn't a core function, so its override was ignored"
+                ))
+                .with_error_code("smelter", "unknown-core-function")
+                .with_help(format!(
+                    "Core functions are {}",
+                    core_functions
+                        .iter()
+                        .map(|function| format!("`{}`", function.name))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )),
+            ),
+        }
+    }
     let mut function_compiler = FunctionCompiler {
+        context: &mut context,
         functions: Vec::new(),
     };
-    oxc::ast_visit::walk::walk_program(&mut function_compiler, &program);
+    walk::walk_program(&mut function_compiler, &program);
+    let user_functions = function_compiler.functions;
     let (mut main_function_body, subfunctions) = reduce_compiled(
         program
             .body
             .iter()
-            .map(|statement| compile_statement(statement))
+            .map(|statement| compile_statement(&mut context, statement))
             .collect::<Vec<(Vec<String>, Vec<Mcfunction>)>>(),
     );
     main_function_body.insert(0, debug_log(String::from("entering main")));
     main_function_body.push(debug_log(String::from("exiting main")));
-    function_compiler
-        .functions
+    let data_pack = user_functions
         .into_iter()
-        .chain(core_functions.into_iter())
-        .chain(subfunctions.into_iter())
-        .chain(std::iter::once(Mcfunction {
-            name: String::from("main"),
-            body: main_function_body,
-        }))
-        .collect()
+        .chain(
+            core_functions
+                .into_iter()
+                .map(|function| (FunctionKind::Core, function)),
+        )
+        .chain(
+            subfunctions
+                .into_iter()
+                .map(|function| (FunctionKind::Subfunction, function)),
+        )
+        .chain(std::iter::once((
+            FunctionKind::User,
+            Mcfunction {
+                name: String::from("main"),
+                body: main_function_body,
+            },
+        )))
+        .collect();
+    (data_pack, context.diagnostics)
 }
 
-fn make_function_name(id: &Option<BindingIdentifier>, span: &Span) -> String {
-    if let Some(identifier) = id {
-        format!("{}_{}", identifier.name.to_ascii_lowercase(), span.start)
+fn make_function_name(
+    context: &CompileContext,
+    id: &Option<BindingIdentifier>,
+    span: &Span,
+) -> String {
+    let readable_name = if let Some(identifier) = id {
+        sanitize_resource_path(&format!("{}_{}", identifier.name, span.start))
     } else {
         format!("anon_func_{}", span.start)
+    };
+    apply_name_strategy(context, readable_name)
+}
+
+fn apply_name_strategy(context: &CompileContext, readable_name: String) -> String {
+    match context.options.function_names {
+        NameStrategy::Readable => readable_name,
+        // Readable names are unique within a file, so hashing them keeps names stable across
+        // compiles of the same source. 64-bit FNV-1a is simple and doesn't depend on the Rust
+        // version like `DefaultHasher` may; 48 bits of it are kept to keep names short
+        NameStrategy::Hashed => format!("f_{}", short_hash(&readable_name)),
+    }
+}
+
+fn short_hash(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:012x}", hash >> 16)
+}
+
+fn make_command_macro_function_name(command: &str) -> String {
+    sanitize_resource_path(&format!("{command}_macro"))
+}
+
+fn sanitize_resource_path(path: &str) -> String {
+    // Function names may only contain `[a-z0-9/._-]`, so lowercase what we can and replace the rest
+    let sanitized: String = path
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            c @ ('a'..='z' | '0'..='9' | '/' | '.' | '_' | '-') => c,
+            _ => '_',
+        })
+        .collect();
+    // Names like `fooBar`, `foobar` and `foo$bar` would otherwise collide, so anything changed by
+    // sanitizing is suffixed with a hash of the original
+    if sanitized == path {
+        sanitized
+    } else {
+        format!("{sanitized}_{}", short_hash(path))
     }
 }
 
 fn compile_function(
+    context: &mut CompileContext,
     id: &Option<BindingIdentifier>,
     parameters: &OxcBox<FormalParameters>,
     body: &OxcBox<FunctionBody>,
     span: &Span,
-) -> Vec<Mcfunction> {
-    let function_name = make_function_name(id, span);
+    binds_arguments_object: bool,
+    binds_this: bool,
+) -> Vec<(FunctionKind, Mcfunction)> {
+    let function_name = make_function_name(context, id, span);
 
     let command_directive = body
         .directives
@@ -164,12 +975,24 @@ fn compile_function(
     if let Some(directive) = command_directive {
         let tokens = directive.directive.split(' ').collect::<Vec<&str>>();
         if let Some(command) = tokens.get(1) {
+            if let (Some(first), Some(last)) = (body.statements.first(), body.statements.last()) {
+                context.warn(
+                    "ignored-function-body",
+                    format!(
+                        "This function wraps the `{command}` command, so the statements in its body are ignored"
+                    ),
+                    Span::new(first.span().start, last.span().end),
+                );
+            }
             return vec![
-                Mcfunction {
-                    name: function_name,
-                    body: compile_command_wrapper_function_body(command),
-                },
-                compile_command_macro_function(command),
+                (
+                    FunctionKind::Wrapper,
+                    Mcfunction {
+                        name: function_name,
+                        body: compile_command_wrapper_function_body(context, command),
+                    },
+                ),
+                (FunctionKind::Macro, compile_command_macro_function(command)),
             ];
         }
     }
@@ -178,36 +1001,69 @@ fn compile_function(
     let mut subfunctions: Vec<Mcfunction> = Vec::new();
     compiled_body.push(debug_log(format!("entering function {function_name}")));
 
+    // `this` is a reserved word, so it can be bound like a variable without clashing with one
+    if binds_this {
+        compiled_body.push(String::from(
+            "data modify storage smelter:smelter current_environment.bindings.this set from storage smelter:smelter current_this",
+        ));
+    }
+
+    // Snapshot the arguments as an array value before the parameters consume them
+    if binds_arguments_object {
+        compiled_body.extend([
+            format!(
+                "data modify storage smelter:smelter current_environment.bindings.arguments set value {}",
+                context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+            ),
+            String::from(
+                "data modify storage smelter:smelter current_environment.bindings.arguments.array set from storage smelter:smelter current_arguments",
+            ),
+        ]);
+    }
+
     // Copy arguments into bindings
     for parameter in parameters.items.iter() {
-        compiled_body.extend(compile_bind_argument(&parameter.pattern));
+        let result = compile_bind_argument(context, &parameter.pattern);
+        compiled_body.extend(result.0);
+        subfunctions.extend(result.1);
     }
     if let Some(rest_parameter) = &parameters.rest {
-        compiled_body.extend(compile_bind_rest_argument(&rest_parameter));
+        compiled_body.extend(compile_bind_rest_argument(context, &rest_parameter));
     }
 
     // Evaluate body statements
     for statement in &body.statements {
-        let result = compile_statement(statement);
+        let result = compile_statement(context, statement);
         compiled_body.extend(result.0);
         subfunctions.extend(result.1);
     }
 
+    // Return undefined if the body completes without reaching a return statement
+    compiled_body.push(format!(
+        "data modify storage smelter:smelter current_return_value set value {}",
+        context.undefined_snbt()
+    ));
     compiled_body.push(debug_log(format!("exiting function {function_name}")));
     subfunctions
         .into_iter()
-        .chain(std::iter::once(Mcfunction {
-            name: function_name,
-            body: compiled_body,
-        }))
+        .map(|function| (FunctionKind::Subfunction, function))
+        .chain(std::iter::once((
+            FunctionKind::User,
+            Mcfunction {
+                name: function_name,
+                body: compiled_body,
+            },
+        )))
         .collect()
 }
 
-fn compile_command_wrapper_function_body(command: &str) -> Vec<String> {
+fn compile_command_wrapper_function_body(context: &CompileContext, command: &str) -> Vec<String> {
+    let macro_function_name = make_command_macro_function_name(command);
     vec![
         debug_log(format!("entering wrapper function {command}")),
-        String::from(
-            "execute unless data storage smelter:smelter current_arguments[0].string run data modify storage smelter:smelter current_return_value set value {throw: 'TypeError'}",
+        format!(
+            "execute unless data storage smelter:smelter current_arguments[0].string run data modify storage smelter:smelter current_return_value set value {}",
+            context.snbt(&Nbt::compound([("throw", Nbt::string("TypeError"))]))
         ),
         String::from(
             "execute unless data storage smelter:smelter current_arguments[0].string run return fail",
@@ -215,360 +1071,7899 @@ fn compile_command_wrapper_function_body(command: &str) -> Vec<String> {
         String::from(
             "data modify storage smelter:smelter internal.command_args.tail set from storage smelter:smelter current_arguments[0].string",
         ),
-        debug_log(format!("returning from wrapper function {command}")),
+        // The command's result becomes the return value, and is 0 if the command fails
+        format!(
+            "data modify storage smelter:smelter current_return_value set value {}",
+            context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+        ),
         format!(
-            "return run function smelter:{command}_macro with storage smelter:smelter internal.command_args"
+            "execute store result storage smelter:smelter current_return_value.number double 1 run function smelter:{macro_function_name} with storage smelter:smelter internal.command_args"
         ),
+        debug_log(format!("returning from wrapper function {command}")),
+        String::from("return run data get storage smelter:smelter current_return_value.number"),
     ]
 }
 
 fn compile_command_macro_function(command: &str) -> Mcfunction {
     Mcfunction {
-        name: format!("{command}_macro"),
+        name: make_command_macro_function_name(command),
         body: vec![
             format!(
                 "${}",
                 debug_log(format!("entering macro function {command}: tail=$(tail)"))
             ),
-            format!("${command} $(tail)"),
-            debug_log(format!("exiting macro function {command}")),
+            // Return the command's result so that the wrapper can store it
+            format!("$return run {command} $(tail)"),
         ],
     }
 }
 
-fn compile_init_function() -> Mcfunction {
+fn compile_init_function(context: &CompileContext) -> Mcfunction {
+    let mut body = vec![
+        String::from("data modify storage smelter:smelter environment_stack set value []"),
+        String::from("data modify storage smelter:smelter current_arguments set value []"),
+        format!(
+            "data modify storage smelter:smelter current_environment set value {}",
+            context.snbt(&Nbt::compound([
+                ("parent", Nbt::Int(-1)),
+                ("bindings", Nbt::compound([])),
+                ("evaluations", Nbt::compound([])),
+            ]))
+        ),
+        String::from("data modify storage smelter:smelter current_return_value set value {}"),
+        String::from("data modify storage smelter:smelter internal set value {}"),
+        String::from("scoreboard objectives add smelter_internal dummy"),
+        // Constant operands for scoreboard operations
+        String::from("scoreboard players set #2 smelter_internal 2"),
+        String::from("scoreboard players set #32 smelter_internal 32"),
+        // Case conversion tables for string methods
+        format!(
+            "data modify storage smelter:smelter internal.upper_case set value {}",
+            context.snbt(&Nbt::Compound(
+                ('a'..='z')
+                    .map(|c| (
+                        c.to_string(),
+                        Nbt::String(c.to_ascii_uppercase().to_string())
+                    ))
+                    .collect()
+            ))
+        ),
+        format!(
+            "data modify storage smelter:smelter internal.lower_case set value {}",
+            context.snbt(&Nbt::Compound(
+                ('A'..='Z')
+                    .map(|c| (
+                        c.to_string(),
+                        Nbt::String(c.to_ascii_lowercase().to_string())
+                    ))
+                    .collect()
+            ))
+        ),
+        // Digit values in bases up to 36 for `parseInt` and `parseFloat`
+        format!(
+            "data modify storage smelter:smelter internal.digit_values set value {}",
+            context.snbt(&Nbt::Compound(
+                ('0'..='9')
+                    .chain('a'..='z')
+                    .chain('A'..='Z')
+                    .map(|c| (
+                        c.to_string(),
+                        Nbt::Int(c.to_digit(36).unwrap_or_default() as i32)
+                    ))
+                    .collect()
+            ))
+        ),
+    ];
+    // `invoke` only appends to the trace when tracing is on
+    if context.options.trace {
+        body.push(String::from(
+            "data modify storage smelter:smelter trace set value []",
+        ));
+    }
     Mcfunction {
         name: String::from("initialize"),
-        body: vec![
-            String::from("data modify storage smelter:smelter environment_stack set value []"),
-            String::from("data modify storage smelter:smelter current_arguments set value []"),
-            String::from(
-                "data modify storage smelter:smelter current_environment set value {parent: -1, bindings: {}, evaluations: {}}",
-            ),
-            String::from("data modify storage smelter:smelter current_return_value set value {}"),
-            String::from("data modify storage smelter:smelter internal set value {}"),
-            String::from("scoreboard objectives add smelter_internal dummy"),
-        ],
+        body,
+    }
+}
+
+fn compile_uninstall_function() -> Mcfunction {
+    // Undo everything `initialize` sets up
+    let mut body = vec![debug_log(String::from("entering uninstall"))];
+    body.extend(
+        STORAGE_LAYOUT
+            .iter()
+            .map(|(path, _)| format!("data remove storage smelter:smelter {path}")),
+    );
+    body.push(String::from(
+        "scoreboard objectives remove smelter_internal",
+    ));
+    Mcfunction {
+        name: String::from("uninstall"),
+        body,
     }
 }
 
-fn compile_bind_argument(pattern: &BindingPattern) -> Vec<String> {
+fn compile_bind_argument(
+    context: &mut CompileContext,
+    pattern: &BindingPattern,
+) -> (Vec<String>, Vec<Mcfunction>) {
     match &pattern.kind {
         BindingPatternKind::BindingIdentifier(bi) => {
-            let name = bi.name.as_str();
-            vec![
-                debug_log(format!("binding argument {name}")),
-                format!(
-                    "execute unless data storage smelter:smelter current_arguments[0] run data modify storage smelter:smelter current_environment.bindings.{name} set value {{undefined: true}}",
-                ),
-                format!(
-                    "execute if data storage smelter:smelter current_arguments[0] run data modify storage smelter:smelter current_environment.bindings.{name} set from storage smelter:smelter current_arguments[0]",
-                ),
-                String::from("data remove storage smelter:smelter current_arguments[0]"),
-                debug_log(format!("done binding argument {name}")),
-            ]
+            (compile_take_argument(context, &bi.name), Vec::new())
+        }
+        _ => {
+            let (mut commands, subfunctions) =
+                compile_bind_pattern(context, pattern, "current_arguments[0]");
+            commands.push(String::from(
+                "data remove storage smelter:smelter current_arguments[0]",
+            ));
+            (commands, subfunctions)
         }
-        _ => Vec::new(),
     }
 }
 
-fn compile_bind_rest_argument(pattern: &BindingRestElement) -> Vec<String> {
-    let name = pattern
-        .argument
-        .get_identifier_name()
-        .map_or(String::from(""), |atom| atom.into_string());
-    vec![
-        debug_log(format!("binding rest argument {name}")),
-        format!(
-            "data modify storage smelter:smelter current_environment.bindings.{name} set from storage smelter:smelter current_arguments",
-        ),
-        debug_log(format!("done binding rest argument {name}")),
-    ]
-}
-
-fn compile_statement(statement: &Statement) -> (Vec<String>, Vec<Mcfunction>) {
-    match statement {
-        Statement::ExpressionStatement(expr_stmt) => compile_expression(&expr_stmt.expression),
-        Statement::FunctionDeclaration(function) => {
-            if let Some(_) = &function.body {
-                let function_name = make_function_name(&function.id, &function.span);
-                let function_identifier = function.id.as_ref().unwrap().name.to_string();
-                (
-                    vec![
-                        debug_log(format!("evaluating function declaration {function_name}")),
-                        format!(
-                            "data modify storage smelter:smelter current_environment.bindings.{function_identifier} set value {{function: {{name: '{function_name}'}}}}"
-                        ),
-                        format!(
-                            "execute store result storage smelter:smelter current_environment.bindings.{function_identifier}.function.environment_index int 1 run data get storage smelter:smelter environment_stack"
-                        ),
-                        debug_log(format!(
-                            "done evaluating function declaration {function_name}"
-                        )),
-                    ],
-                    Vec::new(),
-                )
-            } else {
-                (Vec::new(), Vec::new())
+fn compile_bind_pattern(
+    context: &mut CompileContext,
+    pattern: &BindingPattern,
+    source: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // Binds the names in `pattern` from the value at `source` in smelter:smelter storage, recursing
+    // into the parts of the value for destructuring patterns. Missing parts bind as undefined, and
+    // getters aren't invoked
+    match &pattern.kind {
+        BindingPatternKind::BindingIdentifier(bi) => {
+            let name = bi.name.as_str();
+            (
+                vec![
+                    debug_log(format!("binding {name}")),
+                    format!(
+                        "data modify storage smelter:smelter current_environment.bindings.{name} set value {}",
+                        context.undefined_snbt()
+                    ),
+                    format!(
+                        "execute if data storage smelter:smelter {source} run data modify storage smelter:smelter current_environment.bindings.{name} set from storage smelter:smelter {source}"
+                    ),
+                ],
+                Vec::new(),
+            )
+        }
+        BindingPatternKind::AssignmentPattern(assignment) => {
+            let (default, slot) =
+                compile_pattern_default(context, &assignment.right, &assignment.span, source);
+            reduce_compiled(vec![
+                default,
+                compile_bind_pattern(context, &assignment.left, &slot),
+            ])
+        }
+        BindingPatternKind::ObjectPattern(object) => {
+            let mut compiled = Vec::new();
+            for property in object.properties.iter() {
+                match property.key.static_name() {
+                    Some(key) => compiled.push(compile_bind_pattern(
+                        context,
+                        &property.value,
+                        &format!("{source}.object.{}", nbt::format_path_key(&key)),
+                    )),
+                    None => context.warn_unsupported(
+                        String::from("computed keys in destructuring patterns"),
+                        property.key.span(),
+                    ),
+                }
             }
+            if let Some(rest) = &object.rest {
+                let keys = object
+                    .properties
+                    .iter()
+                    .filter_map(|property| property.key.static_name())
+                    .map(|key| key.into_owned())
+                    .collect::<Vec<String>>();
+                let (commands, slot) = compile_object_rest(context, &keys, &rest.span, source);
+                compiled.push((commands, Vec::new()));
+                compiled.push(compile_bind_pattern(context, &rest.argument, &slot));
+            }
+            reduce_compiled(compiled)
         }
-        Statement::VariableDeclaration(declaration) => {
-            let mut commands: Vec<String> = Vec::new();
-            let mut subfunctions: Vec<Mcfunction> = Vec::new();
-            for declarator in &declaration.declarations {
-                if let Some(identifier) = declarator.id.get_identifier_name() {
-                    let name = identifier.as_str();
-                    commands.push(debug_log(format!("evaluating variable declaration {name}")));
-                    if let Some(initializer) = &declarator.init {
-                        // Compile initializer
-                        let expression_id = make_expression_id(initializer);
-                        let compiled = compile_expression(initializer);
-                        commands.extend(compiled.0);
-                        subfunctions.extend(compiled.1);
-                        commands.push(format!("data modify storage smelter:smelter current_environment.bindings.{name} set from storage smelter:smelter current_environment.evaluations.{expression_id}"));
-                    } else {
-                        // Initialize to undefined
-                        commands.push(format!("data modify storage smelter:smelter current_environment.bindings.{name} set value {{undefined: true}}"));
-                    }
-                    commands.push(debug_log(format!(
-                        "done evaluating variable declaration {name}"
-                    )));
+        BindingPatternKind::ArrayPattern(array) => {
+            let mut compiled = Vec::new();
+            for (index, element) in array.elements.iter().enumerate() {
+                if let Some(element) = element {
+                    compiled.push(compile_bind_pattern(
+                        context,
+                        element,
+                        &format!("{source}.array[{index}]"),
+                    ));
                 }
             }
-            (commands, subfunctions)
+            if let Some(rest) = &array.rest {
+                let (commands, slot) =
+                    compile_array_rest(context, array.elements.len(), &rest.span, source);
+                compiled.push((commands, Vec::new()));
+                compiled.push(compile_bind_pattern(context, &rest.argument, &slot));
+            }
+            reduce_compiled(compiled)
         }
-        _ => (Vec::new(), Vec::new()),
     }
 }
 
-fn make_expression_id(expression: &Expression) -> String {
-    format!("expr_{}", expression.span().start)
+fn compile_pattern_default(
+    context: &mut CompileContext,
+    default: &Expression,
+    span: &Span,
+    source: &str,
+) -> ((Vec<String>, Vec<Mcfunction>), String) {
+    // Copies the value at `source` into a slot, which is returned. The default is only evaluated
+    // (in its own subfunction) when the value is missing or undefined
+    let default_id = make_expression_id(default);
+    let default_name = make_subfunction_name(context, "default", span);
+    let slot = format!("current_environment.evaluations.{default_name}");
+    let (mut default_body, mut subfunctions) = compile_expression(context, default);
+    default_body.push(format!(
+        "data modify storage smelter:smelter {slot} set from storage smelter:smelter current_environment.evaluations.{default_id}"
+    ));
+    subfunctions.push(Mcfunction {
+        name: default_name.clone(),
+        body: default_body,
+    });
+    let commands = vec![
+        format!(
+            "data modify storage smelter:smelter {slot} set value {}",
+            context.undefined_snbt()
+        ),
+        format!(
+            "execute if data storage smelter:smelter {source} run data modify storage smelter:smelter {slot} set from storage smelter:smelter {source}"
+        ),
+        format!(
+            "execute if data storage smelter:smelter {slot}.undefined run function smelter:{default_name}"
+        ),
+    ];
+    ((commands, subfunctions), slot)
 }
 
-fn compile_expression(expression: &Expression) -> (Vec<String>, Vec<Mcfunction>) {
-    let expression_id = make_expression_id(expression);
-    match expression {
-        Expression::ArrowFunctionExpression(arrow_func) => {
-            let function_name = make_function_name(&None, &arrow_func.span);
+fn compile_object_rest(
+    context: &CompileContext,
+    keys: &[String],
+    span: &Span,
+    source: &str,
+) -> (Vec<String>, String) {
+    // Copy the object into a slot, which is returned, and remove the keys destructured before the
+    // rest. Getters aren't carried over
+    let slot = format!("current_environment.evaluations.rest_{}", span.start);
+    let mut commands = vec![
+        format!(
+            "data modify storage smelter:smelter {slot} set value {}",
+            context.snbt(&Nbt::compound([("object", Nbt::Compound(Vec::new()))]))
+        ),
+        format!(
+            "execute if data storage smelter:smelter {source}.object run data modify storage smelter:smelter {slot}.object set from storage smelter:smelter {source}.object"
+        ),
+    ];
+    for key in keys {
+        commands.push(format!(
+            "data remove storage smelter:smelter {slot}.object.{}",
+            nbt::format_path_key(key)
+        ));
+    }
+    (commands, slot)
+}
+
+fn compile_array_rest(
+    context: &CompileContext,
+    skipped: usize,
+    span: &Span,
+    source: &str,
+) -> (Vec<String>, String) {
+    // Copy the array into a slot, which is returned, and remove the elements destructured before
+    // the rest
+    let slot = format!("current_environment.evaluations.rest_{}", span.start);
+    let mut commands = vec![
+        format!(
+            "data modify storage smelter:smelter {slot} set value {}",
+            context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+        ),
+        format!(
+            "execute if data storage smelter:smelter {source}.array run data modify storage smelter:smelter {slot}.array set from storage smelter:smelter {source}.array"
+        ),
+    ];
+    commands.extend(std::iter::repeat_n(
+        format!("data remove storage smelter:smelter {slot}.array[0]"),
+        skipped,
+    ));
+    (commands, slot)
+}
+
+fn compile_take_argument(context: &CompileContext, name: &str) -> Vec<String> {
+    vec![
+        debug_log(format!("binding argument {name}")),
+        format!(
+            "execute unless data storage smelter:smelter current_arguments[0] run data modify storage smelter:smelter current_environment.bindings.{name} set value {}",
+            context.undefined_snbt()
+        ),
+        format!(
+            "execute if data storage smelter:smelter current_arguments[0] run data modify storage smelter:smelter current_environment.bindings.{name} set from storage smelter:smelter current_arguments[0]",
+        ),
+        String::from("data remove storage smelter:smelter current_arguments[0]"),
+        debug_log(format!("done binding argument {name}")),
+    ]
+}
+
+fn compile_bind_rest_argument(
+    context: &CompileContext,
+    pattern: &BindingRestElement,
+) -> Vec<String> {
+    let name = pattern
+        .argument
+        .get_identifier_name()
+        .map_or(String::from(""), |atom| atom.into_string());
+    vec![
+        debug_log(format!("binding rest argument {name}")),
+        // The remaining arguments are collected into an array value
+        format!(
+            "data modify storage smelter:smelter current_environment.bindings.{name} set value {}",
+            context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+        ),
+        format!(
+            "data modify storage smelter:smelter current_environment.bindings.{name}.array set from storage smelter:smelter current_arguments",
+        ),
+        String::from("data modify storage smelter:smelter current_arguments set value []"),
+        debug_log(format!("done binding rest argument {name}")),
+    ]
+}
+
+fn compile_statement(
+    context: &mut CompileContext,
+    statement: &Statement,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let compiled = compile_statement_node(context, statement);
+    explain_node(
+        context,
+        statement_kind(statement),
+        statement.span(),
+        compiled,
+    )
+}
+
+fn statement_kind(statement: &Statement) -> &'static str {
+    match statement {
+        Statement::BlockStatement(_) => "BlockStatement",
+        Statement::BreakStatement(_) => "BreakStatement",
+        Statement::ContinueStatement(_) => "ContinueStatement",
+        Statement::DebuggerStatement(_) => "DebuggerStatement",
+        Statement::DoWhileStatement(_) => "DoWhileStatement",
+        Statement::EmptyStatement(_) => "EmptyStatement",
+        Statement::ExpressionStatement(_) => "ExpressionStatement",
+        Statement::ForInStatement(_) => "ForInStatement",
+        Statement::ForOfStatement(_) => "ForOfStatement",
+        Statement::ForStatement(_) => "ForStatement",
+        Statement::IfStatement(_) => "IfStatement",
+        Statement::LabeledStatement(_) => "LabeledStatement",
+        Statement::ReturnStatement(_) => "ReturnStatement",
+        Statement::SwitchStatement(_) => "SwitchStatement",
+        Statement::ThrowStatement(_) => "ThrowStatement",
+        Statement::TryStatement(_) => "TryStatement",
+        Statement::WhileStatement(_) => "WhileStatement",
+        Statement::WithStatement(_) => "WithStatement",
+        Statement::VariableDeclaration(_) => "VariableDeclaration",
+        Statement::FunctionDeclaration(_) => "FunctionDeclaration",
+        Statement::ClassDeclaration(_) => "ClassDeclaration",
+        Statement::ImportDeclaration(_) => "ImportDeclaration",
+        Statement::ExportAllDeclaration(_) => "ExportAllDeclaration",
+        Statement::ExportDefaultDeclaration(_) => "ExportDefaultDeclaration",
+        Statement::ExportNamedDeclaration(_) => "ExportNamedDeclaration",
+        _ => "TypeScript declaration",
+    }
+}
+
+// With `--explain`, head each compiled block with the kind of node that produced it
+fn explain_node(
+    context: &CompileContext,
+    kind: &str,
+    span: Span,
+    (mut commands, subfunctions): (Vec<String>, Vec<Mcfunction>),
+) -> (Vec<String>, Vec<Mcfunction>) {
+    if context.options.explain {
+        commands.insert(
+            0,
+            format!("# compiled from {kind} at offset {}", span.start),
+        );
+    }
+    (commands, subfunctions)
+}
+
+fn compile_statement_node(
+    context: &mut CompileContext,
+    statement: &Statement,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    match statement {
+        Statement::BlockStatement(block) => reduce_compiled(
+            block
+                .body
+                .iter()
+                .map(|statement| compile_statement(context, statement))
+                .collect(),
+        ),
+        Statement::ExpressionStatement(expr_stmt) => {
+            compile_expression(context, &expr_stmt.expression)
+        }
+        Statement::ClassDeclaration(class) => {
+            context.warn_class_syntax("class declaration", class.span);
+            (Vec::new(), Vec::new())
+        }
+        Statement::BreakStatement(break_stmt) => {
+            if break_stmt.label.is_some() {
+                context.warn_unsupported(String::from("labeled `break`"), break_stmt.span);
+                return (Vec::new(), Vec::new());
+            }
             (
                 vec![
-                    debug_log(format!("evaluating arrow function {function_name}")),
-                    // Set function object
-                    format!(
-                        "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {{function: {{name: '{function_name}'}}}}"
-                    ),
-                    // Store pointer to end of environment stack (where current environment will be pushed if this function gets called by the current function)
-                    format!(
-                        "execute store result storage smelter:smelter current_environment.evaluations.{expression_id}.function.environment_index int 1 run data get storage smelter:smelter environment_stack"
+                    // Flag the environment so that enclosing subfunctions stop executing up to the
+                    // innermost loop or switch, which clears the flag
+                    String::from(
+                        "data modify storage smelter:smelter current_environment.breaking set value true",
                     ),
-                    debug_log(format!("done evaluating arrow function {function_name}")),
+                    String::from("return 1"),
                 ],
                 Vec::new(),
             )
         }
-        Expression::BigIntLiteral(literal) => {
-            let str_value = literal.value.as_str();
-            let value = str_value.parse::<i64>().unwrap_or(0);
-            (
+        Statement::ForStatement(for_stmt) => compile_for_statement(context, for_stmt),
+        Statement::TryStatement(try_stmt) => compile_try_statement(context, try_stmt),
+        Statement::SwitchStatement(switch_stmt) => compile_switch_statement(context, switch_stmt),
+        Statement::IfStatement(if_stmt) => {
+            let test_id = make_expression_id(&if_stmt.test);
+            let consequent_name = make_subfunction_name(context, "if_consequent", &if_stmt.span);
+            let alternate_name = make_subfunction_name(context, "if_alternate", &if_stmt.span);
+            let truthy_key = format!("{test_id}_truthy");
+            let truthy_filter = |truthy: bool| {
+                context.snbt(&Nbt::compound([(
+                    truthy_key.as_str(),
+                    Nbt::Boolean(truthy),
+                )]))
+            };
+            let (if_truthy, if_falsy) = (truthy_filter(true), truthy_filter(false));
+            let mut compiled = vec![
+                (
+                    vec![debug_log(String::from("evaluating if statement"))],
+                    Vec::new(),
+                ),
+                compile_expression(context, &if_stmt.test),
+                (compile_truthiness(&test_id), Vec::new()),
+                (
+                    vec![format!(
+                        "execute if data storage smelter:smelter current_environment.evaluations{if_truthy} run function smelter:{consequent_name}"
+                    )],
+                    Vec::new(),
+                ),
+                compile_subfunction(context, consequent_name, &if_stmt.consequent),
+            ];
+            if let Some(alternate) = &if_stmt.alternate {
+                compiled.push((
+                    vec![format!(
+                        "execute if data storage smelter:smelter current_environment.evaluations{if_falsy} run function smelter:{alternate_name}"
+                    )],
+                    Vec::new(),
+                ));
+                compiled.push(compile_subfunction(context, alternate_name, alternate));
+            }
+            compiled.push((
                 vec![
-                    debug_log(format!("evaluating bigint literal {str_value}")),
-                    format!(
-                        "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {{bigint: {value}l}}"
+                    // Keep unwinding if a branch returned from the enclosing function or broke
+                    // out of the enclosing loop or switch
+                    String::from(PROPAGATE_RETURN),
+                    String::from(PROPAGATE_BREAK),
+                    debug_log(String::from("done evaluating if statement")),
+                ],
+                Vec::new(),
+            ));
+            reduce_compiled(compiled)
+        }
+        Statement::ReturnStatement(return_stmt) => {
+            let mut compiled = vec![(
+                vec![debug_log(String::from("evaluating return statement"))],
+                Vec::new(),
+            )];
+            match &return_stmt.argument {
+                Some(argument) => {
+                    let argument_id = make_expression_id(argument);
+                    compiled.push(compile_expression(context, argument));
+                    compiled.push((
+                        vec![format!(
+                            "data modify storage smelter:smelter current_return_value set from storage smelter:smelter current_environment.evaluations.{argument_id}"
+                        )],
+                        Vec::new(),
+                    ));
+                }
+                None => {
+                    compiled.push((
+                        vec![format!(
+                            "data modify storage smelter:smelter current_return_value set value {}",
+                            context.undefined_snbt()
+                        )],
+                        Vec::new(),
+                    ));
+                }
+            }
+            compiled.push((
+                vec![
+                    // Flag the environment so that enclosing subfunctions stop executing too
+                    String::from(
+                        "data modify storage smelter:smelter current_environment.returning set value true",
                     ),
-                    debug_log(format!("done evaluating bigint literal {str_value}")),
+                    debug_log(String::from("done evaluating return statement")),
+                    String::from("return 1"),
                 ],
                 Vec::new(),
-            )
+            ));
+            reduce_compiled(compiled)
         }
-        Expression::BooleanLiteral(literal) => (
+        Statement::ExportAllDeclaration(export) => {
+            context.warn_unsupported(
+                String::from("re-exports (each file is compiled on its own)"),
+                export.span,
+            );
+            (Vec::new(), Vec::new())
+        }
+        Statement::ExportDefaultDeclaration(export) => match &export.declaration {
+            ExportDefaultDeclarationKind::ClassDeclaration(class) => {
+                context.warn_class_syntax("class declaration", class.span);
+                (Vec::new(), Vec::new())
+            }
+            ExportDefaultDeclarationKind::FunctionDeclaration(function) => {
+                // Anonymous default-exported functions are bound under `default`
+                let binding_name = function
+                    .id
+                    .as_ref()
+                    .map_or(String::from("default"), |id| id.name.to_string());
+                compile_function_declaration(context, function, &binding_name)
+            }
+            declaration => match declaration.as_expression() {
+                Some(expression) => {
+                    let expression_id = make_expression_id(expression);
+                    let (mut commands, subfunctions) = compile_expression(context, expression);
+                    commands.push(format!(
+                        "data modify storage smelter:smelter current_environment.bindings.default set from storage smelter:smelter current_environment.evaluations.{expression_id}"
+                    ));
+                    (commands, subfunctions)
+                }
+                None => (Vec::new(), Vec::new()),
+            },
+        },
+        Statement::ExportNamedDeclaration(export) => {
+            if export.source.is_some() {
+                context.warn_unsupported(
+                    String::from("re-exports (each file is compiled on its own)"),
+                    export.span,
+                );
+                return (Vec::new(), Vec::new());
+            }
+            // Exported bindings are already in scope, so only the declaration needs compiling
+            match &export.declaration {
+                Some(Declaration::FunctionDeclaration(function)) => match &function.id {
+                    Some(id) => compile_function_declaration(context, function, id.name.as_str()),
+                    None => (Vec::new(), Vec::new()),
+                },
+                Some(Declaration::ClassDeclaration(class)) => {
+                    context.warn_class_syntax("class declaration", class.span);
+                    (Vec::new(), Vec::new())
+                }
+                Some(Declaration::VariableDeclaration(declaration)) => {
+                    compile_variable_declaration(context, declaration)
+                }
+                _ => (Vec::new(), Vec::new()),
+            }
+        }
+        Statement::FunctionDeclaration(function) => match &function.id {
+            Some(id) => compile_function_declaration(context, function, id.name.as_str()),
+            None => (Vec::new(), Vec::new()),
+        },
+        Statement::VariableDeclaration(declaration) => {
+            compile_variable_declaration(context, declaration)
+        }
+        // These have no effect at runtime
+        Statement::EmptyStatement(_)
+        | Statement::DebuggerStatement(_)
+        | Statement::TSTypeAliasDeclaration(_)
+        | Statement::TSInterfaceDeclaration(_) => (Vec::new(), Vec::new()),
+        _ => {
+            context.warn_unsupported(
+                format!("statement `{}`", statement_kind(statement)),
+                statement.span(),
+            );
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+fn compile_for_statement(
+    context: &mut CompileContext,
+    statement: &ForStatement,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let loop_name = make_subfunction_name(context, "for", &statement.span);
+    let body_name = make_subfunction_name(context, "for_body", &statement.span);
+    let mut compiled = vec![(
+        vec![debug_log(String::from("evaluating for statement"))],
+        Vec::new(),
+    )];
+    // Run the initializer once in the current environment
+    match &statement.init {
+        Some(ForStatementInit::VariableDeclaration(declaration)) => {
+            compiled.push(compile_variable_declaration(context, declaration));
+        }
+        Some(init) => {
+            if let Some(init_expr) = init.as_expression() {
+                compiled.push(compile_expression(context, init_expr));
+            }
+        }
+        None => {}
+    }
+
+    // Each iteration is a call to the loop function, which checks the test, runs the body and the
+    // update, and then calls itself again
+    let mut loop_compiled = Vec::new();
+    if let Some(test) = &statement.test {
+        let test_id = make_expression_id(test);
+        let mut test_commands = compile_truthiness(&test_id);
+        test_commands.push(format!(
+            "execute if data storage smelter:smelter current_environment.evaluations{} run return 0",
+            context.snbt(&Nbt::compound([(
+                format!("{test_id}_truthy").as_str(),
+                Nbt::Boolean(false),
+            )]))
+        ));
+        loop_compiled.push(compile_expression(context, test));
+        loop_compiled.push((test_commands, Vec::new()));
+    }
+    // Without a test, the loop only ends by returning from the enclosing function
+    loop_compiled.push((
+        vec![
+            format!("function smelter:{body_name}"),
+            String::from(PROPAGATE_RETURN),
+            String::from(CONSUME_BREAK),
+        ],
+        Vec::new(),
+    ));
+    loop_compiled.push(compile_subfunction(context, body_name, &statement.body));
+    // A comma-separated update like `i++, j--` is a sequence expression, so every part of it runs
+    if let Some(update) = &statement.update {
+        loop_compiled.push(compile_expression(context, update));
+    }
+    loop_compiled.push((vec![format!("function smelter:{loop_name}")], Vec::new()));
+    let (loop_body, mut subfunctions) = reduce_compiled(loop_compiled);
+    subfunctions.push(Mcfunction {
+        name: loop_name.clone(),
+        body: loop_body,
+    });
+
+    compiled.push((
+        vec![
+            format!("function smelter:{loop_name}"),
+            String::from(PROPAGATE_RETURN),
+            debug_log(String::from("done evaluating for statement")),
+        ],
+        subfunctions,
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_try_statement(
+    context: &mut CompileContext,
+    statement: &TryStatement,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let try_name = make_subfunction_name(context, "try_block", &statement.span);
+    let mut compiled = vec![
+        (
             vec![
-                debug_log(format!("evaluating boolean literal {}", literal.value)),
-                format!(
-                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {{boolean: {}}}",
-                    literal.value
-                ),
-                debug_log(format!("done evaluating boolean literal {}", literal.value)),
+                debug_log(String::from("evaluating try statement")),
+                format!("function smelter:{try_name}"),
             ],
             Vec::new(),
         ),
-        Expression::Identifier(ident_ref) => {
-            let identifier = ident_ref.name.to_string();
-            (
-                vec![
-                    debug_log(format!("evaluating identifier {identifier}")),
-                    // If binding exists in current environment, then copy value to evaluation
-                    format!(
-                        "execute if data storage smelter:smelter current_environment.bindings.{identifier} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.bindings.{identifier}"
-                    ),
-                    // Else, run `resolve`
-                    format!(
-                        "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter internal.resolve_args set value {{identifier: '{identifier}', expression_id: '{expression_id}'}}"
-                    ),
-                    format!(
-                        "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter internal.resolve_args.stack_index set from storage smelter:smelter current_environment.parent"
-                    ),
-                    format!(
-                        "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run function smelter:resolve with storage smelter:smelter internal.resolve_args"
-                    ),
-                    debug_log(format!("done evaluating identifier {identifier}")),
-                ],
-                vec![],
-            )
-        }
-        Expression::NullLiteral(_) => (
+        compile_block_subfunction(context, try_name, &statement.block),
+    ];
+    if let Some(handler) = &statement.handler {
+        // Nothing can throw yet, so the catch clause would never run
+        context.warn_unsupported(
+            String::from("`catch` clauses (there are no exceptions to catch yet)"),
+            handler.span,
+        );
+    }
+    if let Some(finalizer) = &statement.finalizer {
+        // The finally block runs however the try block completed. A pending return or break is
+        // set aside while it runs, and resumed afterwards unless the finally block returned or
+        // broke itself
+        let finally_name = make_subfunction_name(context, "finally_block", &statement.span);
+        let pending = format!(
+            "storage smelter:smelter current_environment.evaluations.{finally_name}_pending"
+        );
+        let environment = "storage smelter:smelter current_environment";
+        compiled.push((
             vec![
-                debug_log(format!("evaluating null literal")),
+                format!("data modify {pending} set value {{}}"),
                 format!(
-                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {{null: true}}"
+                    "execute if data {environment}.returning run data modify {pending}.return_value set from storage smelter:smelter current_return_value"
+                ),
+                format!(
+                    "execute if data {environment}.breaking run data modify {pending}.breaking set value true"
+                ),
+                format!("data remove {environment}.returning"),
+                format!("data remove {environment}.breaking"),
+                format!("function smelter:{finally_name}"),
+                String::from(PROPAGATE_RETURN),
+                String::from(PROPAGATE_BREAK),
+                format!(
+                    "execute if data {pending}.return_value run data modify storage smelter:smelter current_return_value set from {pending}.return_value"
+                ),
+                format!(
+                    "execute if data {pending}.return_value run data modify {environment}.returning set value true"
+                ),
+                format!(
+                    "execute if data {pending}.breaking run data modify {environment}.breaking set value true"
                 ),
-                debug_log(format!("done evaluating null literal")),
             ],
             Vec::new(),
+        ));
+        compiled.push(compile_block_subfunction(context, finally_name, finalizer));
+    }
+    compiled.push((
+        vec![
+            String::from(PROPAGATE_RETURN),
+            String::from(PROPAGATE_BREAK),
+            debug_log(String::from("done evaluating try statement")),
+        ],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_switch_statement(
+    context: &mut CompileContext,
+    statement: &SwitchStatement,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let discriminant_id = make_expression_id(&statement.discriminant);
+    let switch_name = make_subfunction_name(context, "switch", &statement.span);
+    let case_key = format!("{switch_name}_case");
+    let falling_key = format!("{switch_name}_falling");
+    let mut compiled = vec![
+        (
+            vec![debug_log(String::from("evaluating switch statement"))],
+            Vec::new(),
         ),
-        Expression::NumericLiteral(literal) => (
+        compile_expression(context, &statement.discriminant),
+        (
             vec![
-                debug_log(format!("evaluating numeric literal {}", literal.value)),
                 format!(
-                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {{number: {}d}}",
-                    literal.value
+                    "data remove storage smelter:smelter current_environment.evaluations.{case_key}"
+                ),
+                format!(
+                    "data remove storage smelter:smelter current_environment.evaluations.{falling_key}"
                 ),
-                debug_log(format!("done evaluating numeric literal {}", literal.value)),
             ],
             Vec::new(),
         ),
-        Expression::StringLiteral(literal) => {
-            let string_value = literal.value.as_str();
-            (
-                vec![
-                    debug_log(format!("evaluating string literal {string_value}")),
-                    format!(
-                        "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {{string: '{string_value}'}}"
-                    ),
-                    debug_log(format!("done evaluating string literal {string_value}")),
-                ],
-                Vec::new(),
-            )
-        }
-        Expression::CallExpression(call_expr) => compile_call_expression(&call_expr),
-        _ => (Vec::new(), Vec::new()),
-    }
-}
-
-fn compile_identifier_resolution() -> Mcfunction {
-    Mcfunction {
-        name: String::from("resolve"),
-        body: vec![
+    ];
+    // Find the index of the first case whose value is strictly equal to the discriminant. Each case
+    // value is evaluated in its own subfunction, which is skipped once a case has matched
+    for (index, case) in statement.cases.iter().enumerate() {
+        let Some(test) = &case.test else {
+            continue;
+        };
+        let test_name = make_subfunction_name(context, "switch_test", &case.span);
+        let test_id = make_expression_id(test);
+        let (mut test_body, mut subfunctions) = compile_expression(context, test);
+        test_body.extend([
+            String::from("data modify storage smelter:smelter internal.equals_args set value {}"),
             format!(
-                "${}",
-                debug_log(format!(
-                    "entering resolve: identifier=$(identifier) stack_index=$(stack_index) expression_id=$(expression_id)"
-                ))
-            ),
-            // If binding exists at this index in environment stack, then copy value to target location and return
-            String::from(
-                "$execute if data storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier) run data modify storage smelter:smelter current_environment.evaluations.$(expression_id) set from storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier)",
-            ),
-            debug_log(String::from("resolve: checking if binding exists here")),
-            String::from(
-                "$execute if data storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier) run return 1",
-            ),
-            // Else if no parent, return fail
-            String::from(
-                "$execute store result score #resolve__parent_index smelter_internal run data get storage smelter:smelter environment_stack[$(stack_index)].parent",
+                "data modify storage smelter:smelter internal.equals_args.left set from storage smelter:smelter current_environment.evaluations.{discriminant_id}"
             ),
-            debug_log(String::from("resolve: checking if parent exists")),
-            String::from(
-                "execute if score #resolve__parent_index smelter_internal matches ..-1 run return fail",
+            format!(
+                "data modify storage smelter:smelter internal.equals_args.right set from storage smelter:smelter current_environment.evaluations.{test_id}"
             ),
-            // Else, recurse on parent
             String::from(
-                "execute store result storage smelter:smelter internal.resolve_args.stack_index int 1 run scoreboard players get #resolve__parent_index smelter_internal",
+                "execute store result score #equals smelter_internal run function smelter:strict_equals",
             ),
-            debug_log(String::from("resolve: calling with parent")),
-            String::from(
-                "return run function smelter_resolve with storage smelter:smelter internal.resolve_args",
+            format!(
+                "execute if score #equals smelter_internal matches 1 run data modify storage smelter:smelter current_environment.evaluations.{case_key} set value {index}"
             ),
-        ],
+        ]);
+        subfunctions.push(Mcfunction {
+            name: test_name.clone(),
+            body: test_body,
+        });
+        compiled.push((
+            vec![format!(
+                "execute unless data storage smelter:smelter current_environment.evaluations.{case_key} run function smelter:{test_name}"
+            )],
+            subfunctions,
+        ));
+    }
+    if let Some(default_index) = statement.cases.iter().position(|case| case.test.is_none()) {
+        compiled.push((
+            vec![format!(
+                "execute unless data storage smelter:smelter current_environment.evaluations.{case_key} run data modify storage smelter:smelter current_environment.evaluations.{case_key} set value {default_index}"
+            )],
+            Vec::new(),
+        ));
     }
-}
 
-fn compile_call_expression(expression: &CallExpression) -> (Vec<String>, Vec<Mcfunction>) {
-    let callee_expr_id = make_expression_id(&expression.callee);
-    // Evaluate callee first
-    let mut compiled = vec![compile_expression(&expression.callee)];
-    // Evaluate each argument
-    compiled.extend(expression.arguments.iter().filter_map(|argument| {
-        argument
-            .as_expression()
-            .map(|arg_expr| compile_expression(arg_expr))
-    }));
-    compiled.extend(expression.arguments.iter().map(|argument| (
+    // The switch function runs the matched case and falls through to the ones after it, until a
+    // `break` returns from it
+    let mut switch_compiled = Vec::new();
+    for (index, case) in statement.cases.iter().enumerate() {
+        let case_name = make_subfunction_name(context, "switch_case", &case.span);
+        let (case_body, mut case_subfunctions) = reduce_compiled(
+            case.consequent
+                .iter()
+                .map(|statement| compile_statement(context, statement))
+                .collect(),
+        );
+        case_subfunctions.push(Mcfunction {
+            name: case_name.clone(),
+            body: case_body,
+        });
+        switch_compiled.push((
+            vec![
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations{} run data modify storage smelter:smelter current_environment.evaluations.{falling_key} set value true",
+                    context.snbt(&Nbt::compound([(case_key.as_str(), Nbt::Int(index as i32))]))
+                ),
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{falling_key} run function smelter:{case_name}"
+                ),
+                String::from(PROPAGATE_RETURN),
+                String::from(CONSUME_BREAK),
+            ],
+            case_subfunctions,
+        ));
+    }
+    let (switch_body, mut subfunctions) = reduce_compiled(switch_compiled);
+    subfunctions.push(Mcfunction {
+        name: switch_name.clone(),
+        body: switch_body,
+    });
+    compiled.push((
         vec![
-            // Copy arguments into register
-            format!("data modify storage smelter:smelter current_arguments append from storage smelter:smelter current_environment.evaluations.expr_{}", argument.span().start)
+            format!("function smelter:{switch_name}"),
+            String::from(PROPAGATE_RETURN),
+            debug_log(String::from("done evaluating switch statement")),
         ],
-        Vec::new(),
-    )));
-    compiled.push((vec![
-        debug_log(format!("invoking function {callee_expr_id}")),
-        // Push current environment onto stack
-        String::from("data modify storage smelter:smelter environment_stack append from storage smelter:smelter current_environment"),
-        // Invoke callee function
-        format!("function smelter:invoke with storage smelter:smelter current_environment.evaluations.{callee_expr_id}.function"),
-        // Pop environment
-        String::from("data modify storage smelter:smelter internal.pop_stack_args set value {stack_size: 0}"),
-        String::from("execute store result storage smelter:smelter internal.pop_stack_args.stack_size int 1 run data get storage smelter:smelter environment_stack"),
-        String::from("function smelter:pop_stack with storage smelter:smelter internal.pop_stack_args"),
-        // Clear arguments
-        String::from("data modify storage smelter:smelter current_arguments set value []"),
-        debug_log(format!("done invoking function {callee_expr_id}")),
-    ], Vec::new()));
+        subfunctions,
+    ));
     reduce_compiled(compiled)
 }
 
-fn compile_function_invocation() -> Mcfunction {
-    Mcfunction {
-        name: String::from("invoke"),
-        body: vec![
+fn compile_function_declaration(
+    context: &CompileContext,
+    function: &Function,
+    function_identifier: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    if function.body.is_none() {
+        return (Vec::new(), Vec::new());
+    }
+    let function_name = make_function_name(context, &function.id, &function.span);
+    (
+        vec![
+            debug_log(format!("evaluating function declaration {function_name}")),
             format!(
-                "${}",
-                debug_log(String::from(
-                    "entering invoke: name=$(name) environment_index=$(environment_index)"
-                ))
+                "data modify storage smelter:smelter current_environment.bindings.{function_identifier} set value {}",
+                context.snbt(&make_function_object(&function_name))
             ),
-            String::from(
-                "$data modify storage smelter:smelter current_environment set from storage smelter:smelter environment_stack[$(environment_index)]",
+            format!(
+                "execute store result storage smelter:smelter current_environment.bindings.{function_identifier}.function.environment_index int 1 run data get storage smelter:smelter environment_stack"
             ),
-            String::from("$function smelter:$(name)"),
-            debug_log(String::from("exiting invoke")),
+            debug_log(format!(
+                "done evaluating function declaration {function_name}"
+            )),
         ],
+        Vec::new(),
+    )
+}
+
+fn compile_variable_declaration(
+    context: &mut CompileContext,
+    declaration: &VariableDeclaration,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let mut commands: Vec<String> = Vec::new();
+    let mut subfunctions: Vec<Mcfunction> = Vec::new();
+    for declarator in &declaration.declarations {
+        if let Some(identifier) = declarator.id.get_identifier_name() {
+            let name = identifier.as_str();
+            commands.push(debug_log(format!("evaluating variable declaration {name}")));
+            if let Some(initializer) = &declarator.init {
+                // Compile initializer
+                let expression_id = make_expression_id(initializer);
+                let compiled = compile_expression(context, initializer);
+                commands.extend(compiled.0);
+                subfunctions.extend(compiled.1);
+                commands.push(format!("data modify storage smelter:smelter current_environment.bindings.{name} set from storage smelter:smelter current_environment.evaluations.{expression_id}"));
+            } else {
+                // Initialize to undefined
+                commands.push(format!("data modify storage smelter:smelter current_environment.bindings.{name} set value {}", context.undefined_snbt()));
+            }
+            commands.push(debug_log(format!(
+                "done evaluating variable declaration {name}"
+            )));
+        } else if let Some(initializer) = &declarator.init {
+            // Destructure the initializer's evaluation
+            commands.push(debug_log(String::from(
+                "evaluating destructuring declaration",
+            )));
+            let expression_id = make_expression_id(initializer);
+            for compiled in [
+                compile_expression(context, initializer),
+                compile_bind_pattern(
+                    context,
+                    &declarator.id,
+                    &format!("current_environment.evaluations.{expression_id}"),
+                ),
+            ] {
+                commands.extend(compiled.0);
+                subfunctions.extend(compiled.1);
+            }
+            commands.push(debug_log(String::from(
+                "done evaluating destructuring declaration",
+            )));
+        }
     }
+    (commands, subfunctions)
 }
 
-fn compile_stack_pop() -> Mcfunction {
-    Mcfunction {
-        name: String::from("pop_stack"),
-        body: vec![
+// Stops the current (sub)function if a return statement ran in a function it called
+const PROPAGATE_RETURN: &str =
+    "execute if data storage smelter:smelter current_environment.returning run return 1";
+
+const PROPAGATE_BREAK: &str =
+    "execute if data storage smelter:smelter current_environment.breaking run return 1";
+
+// Used by loops and switches to stop at a `break` from their body
+const CONSUME_BREAK: &str = "execute if data storage smelter:smelter current_environment.breaking run return run data remove storage smelter:smelter current_environment.breaking";
+
+fn compile_truthiness(expression_id: &str) -> Vec<String> {
+    // The value is cleared first so that a missing evaluation (e.g. of an unsupported expression)
+    // is falsy instead of reusing the previous value
+    vec![
+        String::from("data remove storage smelter:smelter internal.is_truthy_args.value"),
+        format!(
+            "data modify storage smelter:smelter internal.is_truthy_args.value set from storage smelter:smelter current_environment.evaluations.{expression_id}"
+        ),
+        format!(
+            "execute store result storage smelter:smelter current_environment.evaluations.{expression_id}_truthy byte 1 run function smelter:is_truthy"
+        ),
+    ]
+}
+
+fn make_subfunction_name(context: &CompileContext, kind: &str, span: &Span) -> String {
+    apply_name_strategy(context, format!("{kind}_{}", span.start))
+}
+
+fn compile_subfunction(
+    context: &mut CompileContext,
+    name: String,
+    statement: &Statement,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // Compile the statement into its own function and return it as a subfunction; the statements of
+    // a block are inlined into the subfunction rather than nested any further
+    let (body, mut subfunctions) = match statement {
+        Statement::BlockStatement(block) => reduce_compiled(
+            block
+                .body
+                .iter()
+                .map(|statement| compile_statement(context, statement))
+                .collect(),
+        ),
+        _ => compile_statement(context, statement),
+    };
+    subfunctions.push(Mcfunction { name, body });
+    (Vec::new(), subfunctions)
+}
+
+fn compile_block_subfunction(
+    context: &mut CompileContext,
+    name: String,
+    block: &BlockStatement,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let (body, mut subfunctions) = reduce_compiled(
+        block
+            .body
+            .iter()
+            .map(|statement| compile_statement(context, statement))
+            .collect(),
+    );
+    subfunctions.push(Mcfunction { name, body });
+    (Vec::new(), subfunctions)
+}
+
+fn make_expression_id(expression: &Expression) -> String {
+    format!("expr_{}", expression.span().start)
+}
+
+fn compile_expression(
+    context: &mut CompileContext,
+    expression: &Expression,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let compiled = compile_expression_node(context, expression);
+    let kind = format!("{:?}", AstKind::from_expression(expression).ty());
+    explain_node(context, &kind, expression.span(), compiled)
+}
+
+fn compile_expression_node(
+    context: &mut CompileContext,
+    expression: &Expression,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let expression_id = make_expression_id(expression);
+    match expression {
+        Expression::ArrayExpression(array_expr) => {
+            let mut compiled = vec![(
+                vec![
+                    debug_log(String::from("evaluating array literal")),
+                    format!(
+                        "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                        context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+                    ),
+                ],
+                Vec::new(),
+            )];
+            for element in array_expr.elements.iter() {
+                match element {
+                    ArrayExpressionElement::SpreadElement(spread) => {
+                        let argument_id = make_expression_id(&spread.argument);
+                        warn_if_not_iterable(context, &spread.argument);
+                        compiled.push(compile_expression(context, &spread.argument));
+                        let mut commands = compile_to_array(&argument_id);
+                        commands.push(format!(
+                            "data modify storage smelter:smelter current_environment.evaluations.{expression_id}.array append from storage smelter:smelter internal.to_array_result.array[]"
+                        ));
+                        compiled.push((commands, Vec::new()));
+                    }
+                    ArrayExpressionElement::Elision(_) => {
+                        compiled.push((
+                            vec![format!(
+                                "data modify storage smelter:smelter current_environment.evaluations.{expression_id}.array append value {}",
+                                context.undefined_snbt()
+                            )],
+                            Vec::new(),
+                        ));
+                    }
+                    _ => {
+                        if let Some(element_expr) = element.as_expression() {
+                            let element_id = make_expression_id(element_expr);
+                            compiled.push(compile_expression(context, element_expr));
+                            compiled.push((
+                                vec![format!(
+                                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id}.array append from storage smelter:smelter current_environment.evaluations.{element_id}"
+                                )],
+                                Vec::new(),
+                            ));
+                        }
+                    }
+                }
+            }
+            compiled.push((
+                vec![debug_log(String::from("done evaluating array literal"))],
+                Vec::new(),
+            ));
+            reduce_compiled(compiled)
+        }
+        Expression::ClassExpression(class) => {
+            context.warn_class_syntax("class expression", class.span);
+            (Vec::new(), Vec::new())
+        }
+        Expression::ArrowFunctionExpression(arrow_func) => {
+            let function_name = make_function_name(context, &None, &arrow_func.span);
+            let mut commands = vec![debug_log(format!(
+                "evaluating arrow function {function_name}"
+            ))];
+            commands.extend(compile_function_value(
+                context,
+                &expression_id,
+                &function_name,
+            ));
+            commands.push(debug_log(format!(
+                "done evaluating arrow function {function_name}"
+            )));
+            (commands, Vec::new())
+        }
+        Expression::FunctionExpression(func) => {
+            let function_name = make_function_name(context, &func.id, &func.span);
+            let mut commands = vec![debug_log(format!(
+                "evaluating function expression {function_name}"
+            ))];
+            commands.extend(compile_function_value(
+                context,
+                &expression_id,
+                &function_name,
+            ));
+            commands.push(debug_log(format!(
+                "done evaluating function expression {function_name}"
+            )));
+            (commands, Vec::new())
+        }
+        Expression::BigIntLiteral(literal) => {
+            compile_bigint_literal(context, &expression_id, &literal.value, false, literal.span)
+        }
+        Expression::BooleanLiteral(literal) => (
+            vec![
+                debug_log(format!("evaluating boolean literal {}", literal.value)),
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(literal.value))]))
+                ),
+                debug_log(format!("done evaluating boolean literal {}", literal.value)),
+            ],
+            Vec::new(),
+        ),
+        Expression::AssignmentExpression(assignment_expr) => {
+            compile_assignment_expression(context, assignment_expr, &expression_id)
+        }
+        Expression::Identifier(ident_ref) if ident_ref.name == "globalThis" => {
+            let mut commands = vec![
+                debug_log(String::from("evaluating globalThis")),
+                // A snapshot of the global bindings as an object, so properties can be read from it
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("object", Nbt::compound([]))]))
+                ),
+            ];
+            commands.extend(on_global_bindings("", |global_bindings| {
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id}.object set from {global_bindings}"
+                )
+            }));
+            commands.push(debug_log(String::from("done evaluating globalThis")));
+            (commands, Vec::new())
+        }
+        Expression::Identifier(ident_ref) => {
+            compile_identifier_reference(context, &ident_ref.name, &expression_id)
+        }
+        Expression::ThisExpression(_) => {
+            // Resolved like a variable, so arrow functions find the enclosing function's binding.
+            // There is none at the top level, where `this` is undefined
+            let (mut commands, subfunctions) =
+                compile_identifier_reference(context, "this", &expression_id);
+            commands.push(format!(
+                "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                context.undefined_snbt()
+            ));
+            (commands, subfunctions)
+        }
+        Expression::NullLiteral(_) => (
+            vec![
+                debug_log(format!("evaluating null literal")),
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("null", Nbt::Boolean(true))]))
+                ),
+                debug_log(format!("done evaluating null literal")),
+            ],
+            Vec::new(),
+        ),
+        Expression::NumericLiteral(literal) => {
+            compile_numeric_literal(context, &expression_id, literal.value)
+        }
+        Expression::ObjectExpression(object_expr) => {
+            compile_object_expression(context, object_expr, &expression_id)
+        }
+        Expression::StaticMemberExpression(member_expr) => compile_property_access(
+            context,
+            &member_expr.object,
+            member_expr.property.name.as_str(),
+            &member_expr.property.span,
+            &expression_id,
+        ),
+        Expression::ComputedMemberExpression(member_expr) => {
+            compile_computed_member_expression(context, member_expr, &expression_id)
+        }
+        Expression::StringLiteral(literal) => {
+            let string_value = literal.value.as_str();
+            (
+                vec![
+                    debug_log(format!("evaluating string literal {string_value}")),
+                    format!(
+                        "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                        context.snbt(&Nbt::compound([("string", Nbt::string(string_value))]))
+                    ),
+                    debug_log(format!("done evaluating string literal {string_value}")),
+                ],
+                Vec::new(),
+            )
+        }
+        Expression::TaggedTemplateExpression(tagged_template) => {
+            // Tags receive the template's string parts as an array along with the substitutions,
+            // which plain template literals don't compile to yet, so no tags are supported
+            context.warn_unsupported(
+                String::from("tagged template literals"),
+                tagged_template.span,
+            );
+            (Vec::new(), Vec::new())
+        }
+        Expression::UnaryExpression(unary_expr) => {
+            match (&unary_expr.operator, &unary_expr.argument) {
+                // Fold negative numeric literals so that `-5` is emitted as a single NBT value
+                (UnaryOperator::UnaryNegation, Expression::NumericLiteral(literal)) => {
+                    compile_numeric_literal(context, &expression_id, -literal.value)
+                }
+                // Likewise for bigints, which lets `-9223372036854775808n` fit in a long
+                (UnaryOperator::UnaryNegation, Expression::BigIntLiteral(literal)) => {
+                    compile_bigint_literal(
+                        context,
+                        &expression_id,
+                        &literal.value,
+                        true,
+                        unary_expr.span,
+                    )
+                }
+                (UnaryOperator::Delete, argument) => {
+                    compile_delete_expression(context, argument, &expression_id)
+                }
+                (UnaryOperator::Typeof, argument) => {
+                    compile_typeof_expression(context, argument, &expression_id)
+                }
+                (operator, _) => {
+                    context.warn_unsupported(
+                        format!("operator `{}`", operator.as_str()),
+                        unary_expr.span,
+                    );
+                    (Vec::new(), Vec::new())
+                }
+            }
+        }
+        Expression::BinaryExpression(binary_expr) => {
+            compile_binary_expression(context, &binary_expr, &expression_id)
+        }
+        Expression::CallExpression(call_expr) => {
+            compile_call_expression(context, &call_expr, &expression_id)
+        }
+        Expression::ConditionalExpression(conditional_expr) => {
+            let test_id = make_expression_id(&conditional_expr.test);
+            let truthy_key = format!("{test_id}_truthy");
+            let mut compiled = vec![
+                (
+                    vec![debug_log(String::from("evaluating conditional expression"))],
+                    Vec::new(),
+                ),
+                compile_expression(context, &conditional_expr.test),
+                (compile_truthiness(&test_id), Vec::new()),
+            ];
+            // Only the chosen branch is evaluated, each in its own subfunction
+            for (kind, branch, truthy) in [
+                ("conditional_consequent", &conditional_expr.consequent, true),
+                ("conditional_alternate", &conditional_expr.alternate, false),
+            ] {
+                let branch_name = make_subfunction_name(context, kind, &conditional_expr.span);
+                let branch_id = make_expression_id(branch);
+                let (mut branch_body, mut subfunctions) = compile_expression(context, branch);
+                branch_body.push(format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.evaluations.{branch_id}"
+                ));
+                subfunctions.push(Mcfunction {
+                    name: branch_name.clone(),
+                    body: branch_body,
+                });
+                compiled.push((
+                    vec![format!(
+                        "execute if data storage smelter:smelter current_environment.evaluations{} run function smelter:{branch_name}",
+                        context.snbt(&Nbt::compound([(
+                            truthy_key.as_str(),
+                            Nbt::Boolean(truthy)
+                        )]))
+                    )],
+                    subfunctions,
+                ));
+            }
+            compiled.push((
+                vec![debug_log(String::from(
+                    "done evaluating conditional expression",
+                ))],
+                Vec::new(),
+            ));
+            reduce_compiled(compiled)
+        }
+        Expression::NewExpression(new_expr) => {
+            compile_new_expression(context, &new_expr, &expression_id)
+        }
+        Expression::SequenceExpression(sequence_expr) => {
+            let mut compiled = vec![(
+                vec![debug_log(String::from("evaluating sequence expression"))],
+                Vec::new(),
+            )];
+            // Every expression is evaluated in order for its side effects, and the last one is the
+            // result
+            for (index, inner) in sequence_expr.expressions.iter().enumerate() {
+                compiled.push(compile_expression(context, inner));
+                if index + 1 == sequence_expr.expressions.len() {
+                    let inner_id = make_expression_id(inner);
+                    compiled.push((
+                        vec![format!(
+                            "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.evaluations.{inner_id}"
+                        )],
+                        Vec::new(),
+                    ));
+                }
+            }
+            compiled.push((
+                vec![debug_log(String::from(
+                    "done evaluating sequence expression",
+                ))],
+                Vec::new(),
+            ));
+            reduce_compiled(compiled)
+        }
+        Expression::ParenthesizedExpression(parenthesized_expr) => {
+            // Parentheses only group, so the value is the inner expression's
+            let inner_id = make_expression_id(&parenthesized_expr.expression);
+            let (mut commands, subfunctions) =
+                compile_expression(context, &parenthesized_expr.expression);
+            commands.push(format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.evaluations.{inner_id}"
+            ));
+            (commands, subfunctions)
+        }
+        Expression::UpdateExpression(update_expr) => {
+            compile_update_expression(context, update_expr, &expression_id)
+        }
+        // Already reported by `FunctionCompiler::visit_await_expression`
+        Expression::AwaitExpression(_) => (Vec::new(), Vec::new()),
+        _ => {
+            context.warn_unsupported(
+                format!(
+                    "expression `{:?}`",
+                    AstKind::from_expression(expression).ty()
+                ),
+                expression.span(),
+            );
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+fn compile_numeric_literal(
+    context: &CompileContext,
+    expression_id: &str,
+    value: f64,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let nbt_value = nbt::format_double(value);
+    (
+        vec![
+            debug_log(format!("evaluating numeric literal {nbt_value}")),
             format!(
-                "${}",
-                debug_log(String::from("entering pop_stack: stack_size=$(stack_size)"))
-            ),
-            String::from(
-                "$data modify storage smelter:smelter current_environment set from storage smelter:smelter environment_stack[$(stack_size)]",
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                context.snbt(&Nbt::compound([("number", Nbt::Double(value))]))
             ),
-            String::from("$data remove storage smelter:smelter environment_stack[$(stack_size)]"),
-            debug_log(String::from("exiting pop_stack")),
+            debug_log(format!("done evaluating numeric literal {nbt_value}")),
         ],
+        Vec::new(),
+    )
+}
+
+fn compile_typeof_expression(
+    context: &mut CompileContext,
+    argument: &Expression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let evaluation =
+        format!("storage smelter:smelter current_environment.evaluations.{expression_id}");
+    let argument_evaluation = format!(
+        "storage smelter:smelter current_environment.evaluations.{}",
+        make_expression_id(argument)
+    );
+    let type_snbt = |name: &str| context.snbt(&Nbt::compound([("string", Nbt::string(name))]));
+    // An undeclared variable leaves no evaluation behind, and is "undefined" rather than an error
+    let mut commands = vec![format!(
+        "data modify {evaluation} set value {}",
+        type_snbt("undefined")
+    )];
+    // Like in JS, null is an object, and so are arrays
+    for (shape, type_name) in [
+        ("null", "object"),
+        ("array", "object"),
+        ("object", "object"),
+        ("boolean", "boolean"),
+        ("number", "number"),
+        ("nan", "number"),
+        ("bigint", "bigint"),
+        ("string", "string"),
+        ("function", "function"),
+    ] {
+        commands.push(format!(
+            "execute if data {argument_evaluation}.{shape} run data modify {evaluation} set value {}",
+            type_snbt(type_name)
+        ));
     }
+    reduce_compiled(vec![
+        (
+            vec![debug_log(String::from("evaluating typeof"))],
+            Vec::new(),
+        ),
+        compile_expression(context, argument),
+        (commands, Vec::new()),
+        (
+            vec![debug_log(String::from("done evaluating typeof"))],
+            Vec::new(),
+        ),
+    ])
 }
 
-fn reduce_compiled(v: Vec<(Vec<String>, Vec<Mcfunction>)>) -> (Vec<String>, Vec<Mcfunction>) {
-    v.into_iter().fold(
-        (Vec::new(), Vec::new()),
-        |(mut commands_acc, mut subfunctions_acc), (commands, subfunctions)| {
-            commands_acc.extend(commands);
-            subfunctions_acc.extend(subfunctions);
-            (commands_acc, subfunctions_acc)
+fn compile_delete_expression(
+    context: &mut CompileContext,
+    argument: &Expression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    enum Removal<'a> {
+        Element(i32),
+        Property(&'a str),
+    }
+    // `delete` works on the evaluated copy of the object, which is then written back to its
+    // variable, so the object has to be named by a variable
+    let (object, removal) = match argument {
+        Expression::StaticMemberExpression(member) => (
+            &member.object,
+            Removal::Property(member.property.name.as_str()),
+        ),
+        Expression::ComputedMemberExpression(member) => match &member.expression {
+            Expression::StringLiteral(literal) => {
+                (&member.object, Removal::Property(literal.value.as_str()))
+            }
+            Expression::NumericLiteral(literal)
+                if literal.value >= 0.0
+                    && literal.value.fract() == 0.0
+                    && literal.value <= f64::from(i32::MAX) =>
+            {
+                (&member.object, Removal::Element(literal.value as i32))
+            }
+            _ => {
+                context.warn_unsupported(
+                    String::from("`delete` with a non-literal key"),
+                    member.expression.span(),
+                );
+                return (Vec::new(), Vec::new());
+            }
         },
+        _ => {
+            context.warn_unsupported(String::from("`delete` of a non-property"), argument.span());
+            return (Vec::new(), Vec::new());
+        }
+    };
+    let Expression::Identifier(variable) = object else {
+        context.warn_unsupported(
+            String::from("`delete` of a property of a nested object"),
+            object.span(),
+        );
+        return (Vec::new(), Vec::new());
+    };
+    let object_id = make_expression_id(object);
+    let object_path =
+        format!("storage smelter:smelter current_environment.evaluations.{object_id}");
+    let (mut commands, subfunctions) = compile_expression(context, object);
+    commands.push(debug_log(String::from("evaluating delete expression")));
+    match removal {
+        // Removing an element would shift the ones after it, so it's replaced with undefined to
+        // leave a hole instead
+        Removal::Element(index) => commands.push(format!(
+            "execute if data {object_path}.array[{index}] run data modify {object_path}.array[{index}] set value {}",
+            context.undefined_snbt()
+        )),
+        Removal::Property(key) => {
+            let path_key = nbt::format_path_key(key);
+            commands.extend([
+                format!("data remove {object_path}.object.{path_key}"),
+                format!("data remove {object_path}.getters.{path_key}"),
+            ]);
+        }
+    }
+    commands.extend(compile_binding_update(context, &variable.name, &object_id));
+    commands.extend([
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+            context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(true))]))
+        ),
+        debug_log(String::from("done evaluating delete expression")),
+    ]);
+    (commands, subfunctions)
+}
+
+fn compile_bigint_literal(
+    context: &mut CompileContext,
+    expression_id: &str,
+    digits: &str,
+    negative: bool,
+    span: Span,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // oxc gives the value in base 10 without separators. Bigints are stored as NBT longs, so
+    // values outside the long range saturate
+    let magnitude = digits.parse::<i128>().unwrap_or(i128::MAX);
+    let signed = if negative { -magnitude } else { magnitude };
+    let value = i64::try_from(signed).unwrap_or_else(|_| {
+        context.warn(
+            "bigint-out-of-range",
+            String::from("This bigint doesn't fit in 64 bits, so it was clamped"),
+            span,
+        );
+        if negative { i64::MIN } else { i64::MAX }
+    });
+    (
+        vec![
+            debug_log(format!("evaluating bigint literal {value}")),
+            format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                context.snbt(&Nbt::compound([("bigint", Nbt::Long(value))]))
+            ),
+            debug_log(format!("done evaluating bigint literal {value}")),
+        ],
+        Vec::new(),
     )
 }
+
+fn make_function_object(function_name: &str) -> Nbt {
+    Nbt::compound([(
+        "function",
+        Nbt::compound([("name", Nbt::string(function_name))]),
+    )])
+}
+
+fn compile_function_value(
+    context: &CompileContext,
+    target_path: &str,
+    function_name: &str,
+) -> Vec<String> {
+    // `target_path` is relative to the current environment's evaluations
+    vec![
+        // Set function object
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{target_path} set value {}",
+            context.snbt(&make_function_object(function_name))
+        ),
+        // Store pointer to end of environment stack (where current environment will be pushed if this function gets called by the current function)
+        format!(
+            "execute store result storage smelter:smelter current_environment.evaluations.{target_path}.function.environment_index int 1 run data get storage smelter:smelter environment_stack"
+        ),
+    ]
+}
+
+fn compile_object_expression(
+    context: &mut CompileContext,
+    expression: &ObjectExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // Objects are stored as `{object: {...}}`, with getters recorded by key as function objects
+    // under `getters` so that member access can invoke them instead of reading a value
+    let mut compiled = vec![(
+        vec![
+            debug_log(String::from("evaluating object literal")),
+            format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                context.snbt(&Nbt::compound([("object", Nbt::Compound(Vec::new()))]))
+            ),
+        ],
+        Vec::new(),
+    )];
+    for property in expression.properties.iter() {
+        let property = match property {
+            ObjectPropertyKind::ObjectProperty(property) => property,
+            ObjectPropertyKind::SpreadProperty(spread) => {
+                context.warn_unsupported(String::from("spread properties"), spread.span);
+                continue;
+            }
+        };
+        let Some(key) = property.key.static_name() else {
+            match (&property.kind, property.key.as_expression()) {
+                (PropertyKind::Init, Some(key_expr)) => {
+                    compiled.push(compile_computed_property(
+                        context,
+                        key_expr,
+                        &property.value,
+                        expression_id,
+                    ));
+                }
+                _ => {
+                    context.warn_unsupported(
+                        String::from("computed accessor and private keys"),
+                        property.key.span(),
+                    );
+                }
+            }
+            continue;
+        };
+        let path_key = nbt::format_path_key(&key);
+        match (&property.kind, &property.value) {
+            (PropertyKind::Init, value) => {
+                let value_id = make_expression_id(value);
+                compiled.push(compile_expression(context, value));
+                compiled.push((
+                    vec![format!(
+                        "data modify storage smelter:smelter current_environment.evaluations.{expression_id}.object.{path_key} set from storage smelter:smelter current_environment.evaluations.{value_id}"
+                    )],
+                    Vec::new(),
+                ));
+            }
+            (PropertyKind::Get, Expression::FunctionExpression(getter)) => {
+                let function_name = make_function_name(context, &None, &getter.span);
+                compiled.push((
+                    compile_function_value(
+                        context,
+                        &format!("{expression_id}.getters.{path_key}"),
+                        &function_name,
+                    ),
+                    Vec::new(),
+                ));
+            }
+            _ => {
+                context.warn_unsupported(String::from("setters"), property.span);
+            }
+        }
+    }
+    compiled.push((
+        vec![debug_log(String::from("done evaluating object literal"))],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_computed_member_expression(
+    context: &mut CompileContext,
+    expression: &ComputedMemberExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // Only literal keys are supported, since they can be written into the NBT path directly
+    let index = match &expression.expression {
+        Expression::StringLiteral(literal) => {
+            return compile_property_access(
+                context,
+                &expression.object,
+                literal.value.as_str(),
+                &literal.span,
+                expression_id,
+            );
+        }
+        Expression::NumericLiteral(literal)
+            if literal.value >= 0.0
+                && literal.value.fract() == 0.0
+                && literal.value <= f64::from(i32::MAX) =>
+        {
+            literal.value as i32
+        }
+        _ => {
+            context.warn_unsupported(
+                String::from("computed member access with a non-literal key"),
+                expression.expression.span(),
+            );
+            return (Vec::new(), Vec::new());
+        }
+    };
+    // Moved aside like in `compile_property_read`, since the IDs are the same
+    let object_id = format!("{expression_id}_object");
+    let (mut commands, subfunctions) = compile_expression(context, &expression.object);
+    commands.extend([
+        debug_log(format!("evaluating element access [{index}]")),
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{object_id} set from storage smelter:smelter current_environment.evaluations.{}",
+            make_expression_id(&expression.object)
+        ),
+        format!(
+            "data remove storage smelter:smelter current_environment.evaluations.{expression_id}"
+        ),
+    ]);
+    commands.extend(compile_nullish_base_check(
+        context,
+        &object_id,
+        expression_id,
+        &format!("[{index}]"),
+    ));
+    commands.extend([
+        format!(
+            "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.evaluations.{object_id}.array[{index}]"
+        ),
+        // Reading past the end of an array (or indexing something else) gives undefined
+        format!(
+            "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+            context.undefined_snbt()
+        ),
+        debug_log(format!("done evaluating element access [{index}]")),
+    ]);
+    (commands, subfunctions)
+}
+
+fn compile_nullish_base_check(
+    context: &CompileContext,
+    object_id: &str,
+    expression_id: &str,
+    access: &str,
+) -> Vec<String> {
+    // JS throws a TypeError when reading a property of null or undefined, but there are no
+    // exceptions yet, so the access gives undefined instead (and says so in the debug log). The
+    // rest of the access is skipped since the result is already set
+    ["undefined", "null"]
+        .into_iter()
+        .flat_map(|shape| {
+            [
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{object_id}.{shape} run {}",
+                    debug_log(format!("TypeError: cannot read {access} of {shape}"))
+                ),
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{object_id}.{shape} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.undefined_snbt()
+                ),
+            ]
+        })
+        .collect()
+}
+
+fn compile_computed_property(
+    context: &mut CompileContext,
+    key: &Expression,
+    value: &Expression,
+    object_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // The key is converted to a string and substituted into the property's path by a macro
+    let key_id = make_expression_id(key);
+    let value_id = make_expression_id(value);
+    reduce_compiled(vec![
+        compile_expression(context, key),
+        compile_expression(context, value),
+        (
+            vec![
+                format!(
+                    "data modify storage smelter:smelter internal.to_string_args.value set from storage smelter:smelter current_environment.evaluations.{key_id}"
+                ),
+                String::from("function smelter:to_string"),
+                format!(
+                    "data modify storage smelter:smelter internal.set_property_args set value {}",
+                    context.snbt(&Nbt::compound([("target", Nbt::string(object_id))]))
+                ),
+                String::from(
+                    "data modify storage smelter:smelter internal.set_property_args.key set from storage smelter:smelter internal.to_string_result",
+                ),
+                format!(
+                    "data modify storage smelter:smelter internal.set_property_args.value set from storage smelter:smelter current_environment.evaluations.{value_id}"
+                ),
+                String::from(
+                    "function smelter:set_property with storage smelter:smelter internal.set_property_args",
+                ),
+            ],
+            Vec::new(),
+        ),
+    ])
+}
+
+fn compile_property_access(
+    context: &mut CompileContext,
+    object: &Expression,
+    property_name: &str,
+    property_span: &Span,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    reduce_compiled(vec![
+        compile_expression(context, object),
+        compile_property_read(
+            context,
+            &make_expression_id(object),
+            property_name,
+            property_span,
+            expression_id,
+        ),
+    ])
+}
+
+fn compile_property_read(
+    context: &CompileContext,
+    source_id: &str,
+    property_name: &str,
+    property_span: &Span,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // The object's evaluation shares its ID with the member expression (both start at the same
+    // offset), so it's moved aside before the result is written
+    let object_id = format!("{expression_id}_object");
+    let path_key = nbt::format_path_key(property_name);
+    let object_path = format!("current_environment.evaluations.{object_id}");
+    // Accesses in a chain like `a.b.c` all start at the same offset, so the getter is named after
+    // the property's position instead
+    let getter_function_name = make_subfunction_name(context, "getter", property_span);
+    // If the object recorded a getter for this property, invoke it and use its return value
+    let mut getter_body = vec![format!(
+        "data modify storage smelter:smelter current_environment.evaluations.{expression_id}_getter set from storage smelter:smelter {object_path}.getters.{path_key}"
+    )];
+    getter_body.extend(compile_invocation(
+        context,
+        &format!("{expression_id}_getter"),
+        Some(&object_id),
+    ));
+    getter_body.push(format!(
+        "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_return_value"
+    ));
+    let mut commands = vec![
+        debug_log(format!("evaluating member access .{property_name}")),
+        format!(
+            "data modify storage smelter:smelter {object_path} set from storage smelter:smelter current_environment.evaluations.{source_id}"
+        ),
+        format!(
+            "data remove storage smelter:smelter current_environment.evaluations.{expression_id}"
+        ),
+    ];
+    commands.extend(compile_nullish_base_check(
+        context,
+        &object_id,
+        expression_id,
+        &format!(".{property_name}"),
+    ));
+    commands.extend([
+        format!(
+            "execute if data storage smelter:smelter {object_path}.getters.{path_key} run function smelter:{getter_function_name}"
+        ),
+        // Otherwise read the property, which is undefined if it doesn't exist
+        format!(
+            "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter {object_path}.object.{path_key}"
+        ),
+        format!(
+            "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+            context.undefined_snbt()
+        ),
+        debug_log(format!("done evaluating member access .{property_name}")),
+    ]);
+    (
+        commands,
+        vec![Mcfunction {
+            name: getter_function_name,
+            body: getter_body,
+        }],
+    )
+}
+
+fn compile_binary_expression(
+    context: &mut CompileContext,
+    expression: &BinaryExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let helper_name = match expression.operator {
+        BinaryOperator::Addition => {
+            return compile_addition_expression(context, expression, expression_id);
+        }
+        BinaryOperator::Subtraction
+        | BinaryOperator::Multiplication
+        | BinaryOperator::Division
+        | BinaryOperator::Remainder
+        | BinaryOperator::Exponential => {
+            return compile_arithmetic_expression(context, expression, expression_id);
+        }
+        BinaryOperator::In => {
+            return compile_in_expression(context, expression, expression_id);
+        }
+        BinaryOperator::Equality
+        | BinaryOperator::Inequality
+        | BinaryOperator::StrictEquality
+        | BinaryOperator::StrictInequality => {
+            return compile_equality_expression(context, expression, expression_id);
+        }
+        BinaryOperator::ShiftLeft => "shift_left",
+        BinaryOperator::ShiftRight => "shift_right",
+        BinaryOperator::BitwiseAnd => "bitwise_and",
+        BinaryOperator::BitwiseOR => "bitwise_or",
+        BinaryOperator::BitwiseXOR => "bitwise_xor",
+        BinaryOperator::Instanceof => {
+            context.warn_class_syntax("`instanceof` check", expression.span);
+            return (Vec::new(), Vec::new());
+        }
+        BinaryOperator::ShiftRightZeroFill => {
+            context.warn_unsupported(
+                String::from("operator `>>>` (scores can't hold unsigned 32-bit results)"),
+                expression.span,
+            );
+            return (Vec::new(), Vec::new());
+        }
+        operator => {
+            context.warn_unsupported(format!("operator `{}`", operator.as_str()), expression.span);
+            return (Vec::new(), Vec::new());
+        }
+    };
+    compile_bitwise_expression(context, expression, expression_id, helper_name)
+}
+
+fn compile_addition_expression(
+    context: &mut CompileContext,
+    expression: &BinaryExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // A string literal operand makes this a concatenation, where fractions are kept
+    let is_string = |operand: &Expression| {
+        matches!(
+            operand,
+            Expression::StringLiteral(_) | Expression::TemplateLiteral(_)
+        )
+    };
+    if !is_string(&expression.left) && !is_string(&expression.right) {
+        warn_if_inexact_arithmetic(
+            context,
+            expression.operator,
+            &[&expression.left, &expression.right],
+            expression.span,
+        );
+    }
+    let left_id = make_expression_id(&expression.left);
+    let right_id = make_expression_id(&expression.right);
+    reduce_compiled(vec![
+        (
+            vec![debug_log(String::from("evaluating binary expression +"))],
+            Vec::new(),
+        ),
+        compile_expression(context, &expression.left),
+        compile_expression(context, &expression.right),
+        (
+            vec![
+                String::from("data modify storage smelter:smelter internal.add_args set value {}"),
+                format!(
+                    "data modify storage smelter:smelter internal.add_args.left set from storage smelter:smelter current_environment.evaluations.{left_id}"
+                ),
+                format!(
+                    "data modify storage smelter:smelter internal.add_args.right set from storage smelter:smelter current_environment.evaluations.{right_id}"
+                ),
+                String::from("function smelter:add"),
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter internal.add_result"
+                ),
+                debug_log(String::from("done evaluating binary expression +")),
+            ],
+            Vec::new(),
+        ),
+    ])
+}
+
+fn compile_arithmetic_expression(
+    context: &mut CompileContext,
+    expression: &BinaryExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let operator = expression.operator.as_str();
+    if expression.operator == BinaryOperator::Exponential {
+        warn_if_unsupported_exponent(context, &expression.right);
+        warn_if_inexact_arithmetic(
+            context,
+            expression.operator,
+            &[&expression.left],
+            expression.span,
+        );
+    } else {
+        warn_if_inexact_arithmetic(
+            context,
+            expression.operator,
+            &[&expression.left, &expression.right],
+            expression.span,
+        );
+    }
+    let left_id = make_expression_id(&expression.left);
+    let right_id = make_expression_id(&expression.right);
+    let mut commands = compile_arithmetic_operation(
+        arithmetic_helper_name(expression.operator),
+        &left_id,
+        &right_id,
+        expression_id,
+    );
+    commands.push(debug_log(format!(
+        "done evaluating binary expression {operator}"
+    )));
+    reduce_compiled(vec![
+        (
+            vec![debug_log(format!(
+                "evaluating binary expression {operator}"
+            ))],
+            Vec::new(),
+        ),
+        compile_expression(context, &expression.left),
+        compile_expression(context, &expression.right),
+        (commands, Vec::new()),
+    ])
+}
+
+fn arithmetic_helper_name(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Subtraction => "subtract",
+        BinaryOperator::Multiplication => "multiply",
+        BinaryOperator::Division => "divide",
+        BinaryOperator::Exponential => "exponentiate",
+        _ => "remainder",
+    }
+}
+
+fn numeric_literal_value(expression: &Expression) -> Option<f64> {
+    match expression {
+        Expression::NumericLiteral(literal) => Some(literal.value),
+        Expression::UnaryExpression(unary) if unary.operator == UnaryOperator::UnaryNegation => {
+            match &unary.argument {
+                Expression::NumericLiteral(literal) => Some(-literal.value),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn warn_if_inexact_arithmetic(
+    context: &mut CompileContext,
+    operator: BinaryOperator,
+    operands: &[&Expression],
+    span: Span,
+) {
+    // Arithmetic runs on scores, which only hold integers. Quotients can't be checked at compile
+    // time so every division is reported, and operands only when they're fractional literals
+    if operator == BinaryOperator::Division {
+        context.warn_unsupported(
+            String::from("fractional quotients (`/` rounds the quotient down to an integer)"),
+            span,
+        );
+    }
+    for operand in operands {
+        if numeric_literal_value(operand).is_some_and(|value| value.fract() != 0.0) {
+            context.warn_unsupported(
+                String::from(
+                    "fractional operands in arithmetic (they're rounded down to integers)",
+                ),
+                operand.span(),
+            );
+        }
+    }
+}
+
+fn warn_if_unsupported_exponent(context: &mut CompileContext, exponent: &Expression) {
+    // Results are integers, so only whole, non-negative exponents are computed. Literal ones that
+    // aren't are reported here, and others are handled by `exponentiate`
+    if numeric_literal_value(exponent).is_some_and(|value| value < 0.0 || value.fract() != 0.0) {
+        context.warn_unsupported(
+            String::from("negative or fractional exponents"),
+            exponent.span(),
+        );
+    }
+}
+
+fn compile_arithmetic_operation(
+    helper_name: &str,
+    left_id: &str,
+    right_id: &str,
+    expression_id: &str,
+) -> Vec<String> {
+    vec![
+        format!(
+            "data modify storage smelter:smelter internal.arithmetic_args.left set from storage smelter:smelter current_environment.evaluations.{left_id}"
+        ),
+        format!(
+            "data modify storage smelter:smelter internal.arithmetic_args.right set from storage smelter:smelter current_environment.evaluations.{right_id}"
+        ),
+        format!("function smelter:{helper_name}"),
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter internal.arithmetic_result"
+        ),
+    ]
+}
+
+fn compile_equality_expression(
+    context: &mut CompileContext,
+    expression: &BinaryExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let operator = expression.operator.as_str();
+    let left_id = make_expression_id(&expression.left);
+    let right_id = make_expression_id(&expression.right);
+    let (helper_name, negated) = match expression.operator {
+        BinaryOperator::Equality => ("loose_equals", false),
+        BinaryOperator::Inequality => ("loose_equals", true),
+        BinaryOperator::StrictEquality => ("strict_equals", false),
+        _ => ("strict_equals", true),
+    };
+    reduce_compiled(vec![
+        (
+            vec![debug_log(format!(
+                "evaluating binary expression {operator}"
+            ))],
+            Vec::new(),
+        ),
+        compile_expression(context, &expression.left),
+        compile_expression(context, &expression.right),
+        (
+            vec![
+                String::from(
+                    "data modify storage smelter:smelter internal.equals_args set value {}",
+                ),
+                format!(
+                    "data modify storage smelter:smelter internal.equals_args.left set from storage smelter:smelter current_environment.evaluations.{left_id}"
+                ),
+                format!(
+                    "data modify storage smelter:smelter internal.equals_args.right set from storage smelter:smelter current_environment.evaluations.{right_id}"
+                ),
+                format!(
+                    "execute store result score #equals smelter_internal run function smelter:{helper_name}"
+                ),
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(negated))]))
+                ),
+                format!(
+                    "execute if score #equals smelter_internal matches 1 run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(!negated))]))
+                ),
+                debug_log(format!("done evaluating binary expression {operator}")),
+            ],
+            Vec::new(),
+        ),
+    ])
+}
+
+fn compile_in_expression(
+    context: &mut CompileContext,
+    expression: &BinaryExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let left_id = make_expression_id(&expression.left);
+    let right_id = make_expression_id(&expression.right);
+    reduce_compiled(vec![
+        (
+            vec![debug_log(String::from("evaluating binary expression in"))],
+            Vec::new(),
+        ),
+        compile_expression(context, &expression.left),
+        compile_expression(context, &expression.right),
+        (
+            vec![
+                String::from("data modify storage smelter:smelter internal.in_args set value {}"),
+                format!(
+                    "data modify storage smelter:smelter internal.in_args.key set from storage smelter:smelter current_environment.evaluations.{left_id}"
+                ),
+                format!(
+                    "data modify storage smelter:smelter internal.in_args.value set from storage smelter:smelter current_environment.evaluations.{right_id}"
+                ),
+                String::from(
+                    "execute store result score #in smelter_internal run function smelter:has_property",
+                ),
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(false))]))
+                ),
+                format!(
+                    "execute if score #in smelter_internal matches 1 run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(true))]))
+                ),
+                debug_log(String::from("done evaluating binary expression in")),
+            ],
+            Vec::new(),
+        ),
+    ])
+}
+
+fn compile_bitwise_expression(
+    context: &mut CompileContext,
+    expression: &BinaryExpression,
+    expression_id: &str,
+    helper_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let operator = expression.operator.as_str();
+    let left_id = make_expression_id(&expression.left);
+    let right_id = make_expression_id(&expression.right);
+    let mut commands =
+        compile_bitwise_operation(context, helper_name, &left_id, &right_id, expression_id);
+    commands.push(debug_log(format!(
+        "done evaluating binary expression {operator}"
+    )));
+    reduce_compiled(vec![
+        (
+            vec![debug_log(format!(
+                "evaluating binary expression {operator}"
+            ))],
+            Vec::new(),
+        ),
+        compile_expression(context, &expression.left),
+        compile_expression(context, &expression.right),
+        (commands, Vec::new()),
+    ])
+}
+
+fn compile_bitwise_operation(
+    context: &CompileContext,
+    helper_name: &str,
+    left_id: &str,
+    right_id: &str,
+    expression_id: &str,
+) -> Vec<String> {
+    // Convert both operands to 32-bit integers in scores, truncating toward zero like ToInt32 does,
+    // except that out-of-range values saturate instead of wrapping. NaN has no number to get, so the
+    // failed commands store 0
+    let number = "storage smelter:smelter internal.to_number_result.number";
+    let mut commands = Vec::new();
+    for (operand_id, score) in [(left_id, "#bitwise_left"), (right_id, "#bitwise_right")] {
+        commands.extend([
+            format!(
+                "data modify storage smelter:smelter internal.to_number_args.value set from storage smelter:smelter current_environment.evaluations.{operand_id}"
+            ),
+            String::from("function smelter:to_number"),
+            format!("execute store result score {score} smelter_internal run data get {number}"),
+            // `data get` rounds down, so a negative number with a fractional part is moved back up
+            // by one. Only then is floor(x) + floor(-x) -1 rather than 0
+            format!(
+                "execute store result score #bitwise_fraction smelter_internal run data get {number} -1"
+            ),
+            format!(
+                "scoreboard players operation #bitwise_fraction smelter_internal += {score} smelter_internal"
+            ),
+            format!(
+                "execute if score {score} smelter_internal matches ..-1 if score #bitwise_fraction smelter_internal matches -1 run scoreboard players add {score} smelter_internal 1"
+            ),
+        ]);
+    }
+    commands.extend([
+        format!("function smelter:{helper_name}"),
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+            context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+        ),
+        format!(
+            "execute store result storage smelter:smelter current_environment.evaluations.{expression_id}.number double 1 run scoreboard players get #bitwise_result smelter_internal"
+        ),
+    ]);
+    commands
+}
+
+fn compile_identifier_reference(
+    context: &CompileContext,
+    identifier: &str,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    (
+        vec![
+            debug_log(format!("evaluating identifier {identifier}")),
+            // Clear any value left from an earlier run of this expression (e.g. in a loop)
+            format!(
+                "data remove storage smelter:smelter current_environment.evaluations.{expression_id}"
+            ),
+            // If binding exists in current environment, then copy value to evaluation
+            format!(
+                "execute if data storage smelter:smelter current_environment.bindings.{identifier} run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.bindings.{identifier}"
+            ),
+            // Else, run `resolve`
+            format!(
+                "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter internal.resolve_args set value {}",
+                context.snbt(&Nbt::compound([
+                    ("identifier", Nbt::string(identifier)),
+                    ("expression_id", Nbt::string(expression_id)),
+                ]))
+            ),
+            format!(
+                "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run data modify storage smelter:smelter internal.resolve_args.stack_index set from storage smelter:smelter current_environment.parent"
+            ),
+            format!(
+                "execute unless data storage smelter:smelter current_environment.evaluations.{expression_id} run function smelter:resolve with storage smelter:smelter internal.resolve_args"
+            ),
+            debug_log(format!("done evaluating identifier {identifier}")),
+        ],
+        vec![],
+    )
+}
+
+fn compile_update_expression(
+    context: &mut CompileContext,
+    expression: &UpdateExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let SimpleAssignmentTarget::AssignmentTargetIdentifier(target) = &expression.argument else {
+        context.warn_unsupported(
+            String::from("`++` or `--` on anything but a variable"),
+            expression.argument.span(),
+        );
+        return (Vec::new(), Vec::new());
+    };
+    let name = target.name.as_str();
+    let operator = expression.operator.as_str();
+    let (step, new_id) = match expression.operator {
+        UpdateOperator::Increment => (1.0, format!("{expression_id}_incremented")),
+        UpdateOperator::Decrement => (-1.0, format!("{expression_id}_decremented")),
+    };
+    let old_id = format!("{expression_id}_target");
+    let (mut commands, subfunctions) = compile_identifier_reference(context, name, &old_id);
+    commands.insert(0, debug_log(format!("evaluating update {name} {operator}")));
+    commands.extend([
+        // The old value is converted to a number first, so that strings are incremented rather
+        // than concatenated
+        format!(
+            "data modify storage smelter:smelter internal.to_number_args.value set from storage smelter:smelter current_environment.evaluations.{old_id}"
+        ),
+        String::from("function smelter:to_number"),
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{old_id} set from storage smelter:smelter internal.to_number_result"
+        ),
+        String::from("data modify storage smelter:smelter internal.add_args set value {}"),
+        format!(
+            "data modify storage smelter:smelter internal.add_args.left set from storage smelter:smelter current_environment.evaluations.{old_id}"
+        ),
+        format!(
+            "data modify storage smelter:smelter internal.add_args.right set value {}",
+            context.snbt(&Nbt::compound([("number", Nbt::Double(step))]))
+        ),
+        String::from("function smelter:add"),
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{new_id} set from storage smelter:smelter internal.add_result"
+        ),
+    ]);
+    commands.extend(compile_binding_update(context, name, &new_id));
+    // Prefix updates give the new value and postfix updates give the old one
+    let result_id = if expression.prefix { &new_id } else { &old_id };
+    commands.extend([
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.evaluations.{result_id}"
+        ),
+        debug_log(format!("done evaluating update {name} {operator}")),
+    ]);
+    (commands, subfunctions)
+}
+
+fn compile_assignment_expression(
+    context: &mut CompileContext,
+    expression: &AssignmentExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    if matches!(
+        expression.left,
+        AssignmentTarget::ArrayAssignmentTarget(_) | AssignmentTarget::ObjectAssignmentTarget(_)
+    ) {
+        return compile_destructuring_assignment(context, expression, expression_id);
+    }
+    let AssignmentTarget::AssignmentTargetIdentifier(target) = &expression.left else {
+        context.warn_unsupported(
+            String::from("assignment to anything but a variable"),
+            expression.left.span(),
+        );
+        return (Vec::new(), Vec::new());
+    };
+    let name = target.name.as_str();
+    let operator = expression.operator.as_str();
+    let evaluation =
+        format!("storage smelter:smelter current_environment.evaluations.{expression_id}");
+    let right_id = make_expression_id(&expression.right);
+    let mut compiled = vec![(
+        vec![debug_log(format!(
+            "evaluating assignment {name} {operator}"
+        ))],
+        Vec::new(),
+    )];
+    match expression.operator {
+        AssignmentOperator::Assign => {
+            compiled.push(compile_expression(context, &expression.right));
+            let mut commands = vec![format!(
+                "data modify {evaluation} set from storage smelter:smelter current_environment.evaluations.{right_id}"
+            )];
+            commands.extend(compile_binding_update(context, name, expression_id));
+            compiled.push((commands, Vec::new()));
+        }
+        AssignmentOperator::Addition => {
+            if !matches!(
+                expression.right,
+                Expression::StringLiteral(_) | Expression::TemplateLiteral(_)
+            ) {
+                warn_if_inexact_arithmetic(
+                    context,
+                    BinaryOperator::Addition,
+                    &[&expression.right],
+                    expression.span,
+                );
+            }
+            let target_id = format!("{expression_id}_target");
+            compiled.push(compile_identifier_reference(context, name, &target_id));
+            compiled.push(compile_expression(context, &expression.right));
+            let mut commands = vec![
+                String::from("data modify storage smelter:smelter internal.add_args set value {}"),
+                format!(
+                    "data modify storage smelter:smelter internal.add_args.left set from storage smelter:smelter current_environment.evaluations.{target_id}"
+                ),
+                format!(
+                    "data modify storage smelter:smelter internal.add_args.right set from storage smelter:smelter current_environment.evaluations.{right_id}"
+                ),
+                String::from("function smelter:add"),
+                format!(
+                    "data modify {evaluation} set from storage smelter:smelter internal.add_result"
+                ),
+            ];
+            commands.extend(compile_binding_update(context, name, expression_id));
+            compiled.push((commands, Vec::new()));
+        }
+        AssignmentOperator::Subtraction
+        | AssignmentOperator::Multiplication
+        | AssignmentOperator::Division
+        | AssignmentOperator::Remainder
+        | AssignmentOperator::Exponential
+        | AssignmentOperator::ShiftLeft
+        | AssignmentOperator::ShiftRight
+        | AssignmentOperator::BitwiseOR
+        | AssignmentOperator::BitwiseXOR
+        | AssignmentOperator::BitwiseAnd => {
+            // Like `+=`, the current value and the right side are combined with the binary
+            // operator's helper, and the result is assigned
+            let target_id = format!("{expression_id}_target");
+            compiled.push(compile_identifier_reference(context, name, &target_id));
+            compiled.push(compile_expression(context, &expression.right));
+            let bitwise_helper_name = match expression.operator {
+                AssignmentOperator::ShiftLeft => Some("shift_left"),
+                AssignmentOperator::ShiftRight => Some("shift_right"),
+                AssignmentOperator::BitwiseOR => Some("bitwise_or"),
+                AssignmentOperator::BitwiseXOR => Some("bitwise_xor"),
+                AssignmentOperator::BitwiseAnd => Some("bitwise_and"),
+                _ => None,
+            };
+            let mut commands = match (
+                bitwise_helper_name,
+                expression.operator.to_binary_operator(),
+            ) {
+                (Some(helper_name), _) => compile_bitwise_operation(
+                    context,
+                    helper_name,
+                    &target_id,
+                    &right_id,
+                    expression_id,
+                ),
+                (None, Some(binary_operator)) => {
+                    if binary_operator == BinaryOperator::Exponential {
+                        warn_if_unsupported_exponent(context, &expression.right);
+                    } else {
+                        warn_if_inexact_arithmetic(
+                            context,
+                            binary_operator,
+                            &[&expression.right],
+                            expression.span,
+                        );
+                    }
+                    compile_arithmetic_operation(
+                        arithmetic_helper_name(binary_operator),
+                        &target_id,
+                        &right_id,
+                        expression_id,
+                    )
+                }
+                (None, None) => Vec::new(),
+            };
+            commands.extend(compile_binding_update(context, name, expression_id));
+            compiled.push((commands, Vec::new()));
+        }
+        AssignmentOperator::LogicalOr
+        | AssignmentOperator::LogicalAnd
+        | AssignmentOperator::LogicalNullish => {
+            // Short-circuit by evaluating and assigning the right side in a subfunction that only
+            // runs under the operator's condition; otherwise the result is the current value
+            let assign_name = make_subfunction_name(context, "logical_assign", &expression.span);
+            compiled.push(compile_identifier_reference(context, name, expression_id));
+            let condition = match expression.operator {
+                AssignmentOperator::LogicalOr => {
+                    let mut commands = compile_truthiness(expression_id);
+                    commands.push(format!(
+                        "execute if data storage smelter:smelter current_environment.evaluations{} run function smelter:{assign_name}",
+                        context.snbt(&Nbt::compound([(
+                            format!("{expression_id}_truthy").as_str(),
+                            Nbt::Boolean(false),
+                        )]))
+                    ));
+                    commands
+                }
+                AssignmentOperator::LogicalAnd => {
+                    let mut commands = compile_truthiness(expression_id);
+                    commands.push(format!(
+                        "execute if data storage smelter:smelter current_environment.evaluations{} run function smelter:{assign_name}",
+                        context.snbt(&Nbt::compound([(
+                            format!("{expression_id}_truthy").as_str(),
+                            Nbt::Boolean(true),
+                        )]))
+                    ));
+                    commands
+                }
+                _ => vec![
+                    format!(
+                        "execute store success score #nullish smelter_internal if data {evaluation}.undefined"
+                    ),
+                    format!(
+                        "execute if data {evaluation}.null run scoreboard players set #nullish smelter_internal 1"
+                    ),
+                    format!(
+                        "execute if score #nullish smelter_internal matches 1 run function smelter:{assign_name}"
+                    ),
+                ],
+            };
+            let (mut assign_body, mut subfunctions) =
+                compile_expression(context, &expression.right);
+            assign_body.push(format!(
+                "data modify {evaluation} set from storage smelter:smelter current_environment.evaluations.{right_id}"
+            ));
+            assign_body.extend(compile_binding_update(context, name, expression_id));
+            subfunctions.push(Mcfunction {
+                name: assign_name,
+                body: assign_body,
+            });
+            compiled.push((condition, subfunctions));
+        }
+        _ => {
+            context.warn_unsupported(format!("operator `{operator}`"), expression.span);
+            return (Vec::new(), Vec::new());
+        }
+    }
+    compiled.push((
+        vec![debug_log(format!(
+            "done evaluating assignment {name} {operator}"
+        ))],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_destructuring_assignment(
+    context: &mut CompileContext,
+    expression: &AssignmentExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // The right side is the result, and it's destructured from this expression's own slot
+    let right_id = make_expression_id(&expression.right);
+    reduce_compiled(vec![
+        (
+            vec![debug_log(String::from(
+                "evaluating destructuring assignment",
+            ))],
+            Vec::new(),
+        ),
+        compile_expression(context, &expression.right),
+        (
+            vec![format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_environment.evaluations.{right_id}"
+            )],
+            Vec::new(),
+        ),
+        compile_assign_pattern(
+            context,
+            &expression.left,
+            &format!("current_environment.evaluations.{expression_id}"),
+        ),
+        (
+            vec![debug_log(String::from(
+                "done evaluating destructuring assignment",
+            ))],
+            Vec::new(),
+        ),
+    ])
+}
+
+fn compile_assign_pattern(
+    context: &mut CompileContext,
+    target: &AssignmentTarget,
+    source: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // Like `compile_bind_pattern`, but writes to the existing bindings that the names resolve to
+    // instead of declaring new ones
+    match target {
+        AssignmentTarget::AssignmentTargetIdentifier(identifier) => {
+            compile_assign_identifier(context, identifier, source)
+        }
+        AssignmentTarget::ObjectAssignmentTarget(object) => {
+            let mut compiled = Vec::new();
+            let mut keys = Vec::new();
+            for property in object.properties.iter() {
+                match property {
+                    AssignmentTargetProperty::AssignmentTargetPropertyIdentifier(property) => {
+                        let key = property.binding.name.as_str();
+                        let property_source =
+                            format!("{source}.object.{}", nbt::format_path_key(key));
+                        match &property.init {
+                            Some(init) => {
+                                let (default, slot) = compile_pattern_default(
+                                    context,
+                                    init,
+                                    &property.span,
+                                    &property_source,
+                                );
+                                compiled.push(default);
+                                compiled.push(compile_assign_identifier(
+                                    context,
+                                    &property.binding,
+                                    &slot,
+                                ));
+                            }
+                            None => compiled.push(compile_assign_identifier(
+                                context,
+                                &property.binding,
+                                &property_source,
+                            )),
+                        }
+                        keys.push(String::from(key));
+                    }
+                    AssignmentTargetProperty::AssignmentTargetPropertyProperty(property) => {
+                        match property.name.static_name() {
+                            Some(key) => {
+                                compiled.push(compile_assign_pattern_maybe_default(
+                                    context,
+                                    &property.binding,
+                                    &format!("{source}.object.{}", nbt::format_path_key(&key)),
+                                ));
+                                keys.push(key.into_owned());
+                            }
+                            None => context.warn_unsupported(
+                                String::from("computed keys in destructuring patterns"),
+                                property.name.span(),
+                            ),
+                        }
+                    }
+                }
+            }
+            if let Some(rest) = &object.rest {
+                let (commands, slot) = compile_object_rest(context, &keys, &rest.span, source);
+                compiled.push((commands, Vec::new()));
+                compiled.push(compile_assign_pattern(context, &rest.target, &slot));
+            }
+            reduce_compiled(compiled)
+        }
+        AssignmentTarget::ArrayAssignmentTarget(array) => {
+            let mut compiled = Vec::new();
+            for (index, element) in array.elements.iter().enumerate() {
+                if let Some(element) = element {
+                    compiled.push(compile_assign_pattern_maybe_default(
+                        context,
+                        element,
+                        &format!("{source}.array[{index}]"),
+                    ));
+                }
+            }
+            if let Some(rest) = &array.rest {
+                let (commands, slot) =
+                    compile_array_rest(context, array.elements.len(), &rest.span, source);
+                compiled.push((commands, Vec::new()));
+                compiled.push(compile_assign_pattern(context, &rest.target, &slot));
+            }
+            reduce_compiled(compiled)
+        }
+        _ => {
+            context.warn_unsupported(
+                String::from("assignment to anything but a variable"),
+                target.span(),
+            );
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+fn compile_assign_pattern_maybe_default(
+    context: &mut CompileContext,
+    target: &AssignmentTargetMaybeDefault,
+    source: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    match target {
+        AssignmentTargetMaybeDefault::AssignmentTargetWithDefault(with_default) => {
+            let (default, slot) =
+                compile_pattern_default(context, &with_default.init, &with_default.span, source);
+            reduce_compiled(vec![
+                default,
+                compile_assign_pattern(context, &with_default.binding, &slot),
+            ])
+        }
+        _ => match target.as_assignment_target() {
+            Some(target) => compile_assign_pattern(context, target, source),
+            None => (Vec::new(), Vec::new()),
+        },
+    }
+}
+
+fn compile_assign_identifier(
+    context: &CompileContext,
+    identifier: &IdentifierReference,
+    source: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // The value is copied into a slot first, since `compile_binding_update` reads an evaluation.
+    // A missing value assigns undefined
+    let name = identifier.name.as_str();
+    let slot_id = format!("destructured_{}", identifier.span.start);
+    let slot = format!("storage smelter:smelter current_environment.evaluations.{slot_id}");
+    let mut commands = vec![
+        debug_log(format!("assigning {name}")),
+        format!("data modify {slot} set value {}", context.undefined_snbt()),
+        format!(
+            "execute if data storage smelter:smelter {source} run data modify {slot} set from storage smelter:smelter {source}"
+        ),
+    ];
+    commands.extend(compile_binding_update(context, name, &slot_id));
+    (commands, Vec::new())
+}
+
+fn compile_binding_update(context: &CompileContext, name: &str, value_id: &str) -> Vec<String> {
+    // Write to the nearest environment that has the binding, like `resolve` reads from it. A
+    // variable that isn't declared anywhere is bound in the global environment
+    let bindings = "storage smelter:smelter current_environment.bindings";
+    let mut commands = vec![
+        String::from("scoreboard players set #assigned smelter_internal 1"),
+        format!(
+            "execute if data {bindings}.{name} run data modify {bindings}.{name} set from storage smelter:smelter current_environment.evaluations.{value_id}"
+        ),
+        format!(
+            "execute unless data {bindings}.{name} run data modify storage smelter:smelter internal.assign_args set value {}",
+            context.snbt(&Nbt::compound([
+                ("identifier", Nbt::string(name)),
+                ("expression_id", Nbt::string(value_id)),
+            ]))
+        ),
+        format!(
+            "execute unless data {bindings}.{name} run data modify storage smelter:smelter internal.assign_args.stack_index set from storage smelter:smelter current_environment.parent"
+        ),
+        format!(
+            "execute unless data {bindings}.{name} store result score #assigned smelter_internal run function smelter:assign with storage smelter:smelter internal.assign_args"
+        ),
+    ];
+    commands.extend(on_global_bindings(
+        "if score #assigned smelter_internal matches 0 ",
+        |global_bindings| {
+            format!(
+                "data modify {global_bindings}.{name} set from storage smelter:smelter current_environment.evaluations.{value_id}"
+            )
+        },
+    ));
+    commands
+}
+
+fn on_global_bindings(condition: &str, command: impl Fn(&str) -> String) -> [String; 2] {
+    // The top level's environment is the global one. Every function is called from it, so it's at
+    // the bottom of the environment stack while one runs, and is the current environment otherwise
+    let bottom = "storage smelter:smelter environment_stack[0]";
+    [
+        format!(
+            "execute {condition}if data {bottom} run {}",
+            command(&format!("{bottom}.bindings"))
+        ),
+        format!(
+            "execute {condition}unless data {bottom} run {}",
+            command("storage smelter:smelter current_environment.bindings")
+        ),
+    ]
+}
+
+fn compile_binding_assignment() -> Mcfunction {
+    Mcfunction {
+        name: String::from("assign"),
+        body: vec![
+            format!(
+                "${}",
+                debug_log(format!(
+                    "entering assign: identifier=$(identifier) stack_index=$(stack_index) expression_id=$(expression_id)"
+                ))
+            ),
+            // Fail if there is no environment at this index (negative indices would count from the
+            // end of the stack)
+            String::from(
+                "$scoreboard players set #assign__stack_index smelter_internal $(stack_index)",
+            ),
+            String::from(
+                "execute if score #assign__stack_index smelter_internal matches ..-1 run return fail",
+            ),
+            String::from(
+                "$execute unless data storage smelter:smelter environment_stack[$(stack_index)] run return fail",
+            ),
+            // If binding exists at this index in environment stack, then copy value into it and return
+            String::from(
+                "$execute if data storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier) run data modify storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier) set from storage smelter:smelter current_environment.evaluations.$(expression_id)",
+            ),
+            String::from(
+                "$execute if data storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier) run return 1",
+            ),
+            // Else, recurse on parent
+            String::from(
+                "$execute store result score #assign__parent_index smelter_internal run data get storage smelter:smelter environment_stack[$(stack_index)].parent",
+            ),
+            String::from(
+                "execute store result storage smelter:smelter internal.assign_args.stack_index int 1 run scoreboard players get #assign__parent_index smelter_internal",
+            ),
+            String::from(
+                "return run function smelter:assign with storage smelter:smelter internal.assign_args",
+            ),
+        ],
+    }
+}
+
+fn compile_identifier_resolution() -> Mcfunction {
+    Mcfunction {
+        name: String::from("resolve"),
+        body: vec![
+            format!(
+                "${}",
+                debug_log(format!(
+                    "entering resolve: identifier=$(identifier) stack_index=$(stack_index) expression_id=$(expression_id)"
+                ))
+            ),
+            // Fail if there is no environment at this index (negative indices would count from the
+            // end of the stack)
+            String::from(
+                "$scoreboard players set #resolve__stack_index smelter_internal $(stack_index)",
+            ),
+            String::from(
+                "execute if score #resolve__stack_index smelter_internal matches ..-1 run return fail",
+            ),
+            String::from(
+                "$execute unless data storage smelter:smelter environment_stack[$(stack_index)] run return fail",
+            ),
+            // If binding exists at this index in environment stack, then copy value to target location and return
+            String::from(
+                "$execute if data storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier) run data modify storage smelter:smelter current_environment.evaluations.$(expression_id) set from storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier)",
+            ),
+            debug_log(String::from("resolve: checking if binding exists here")),
+            String::from(
+                "$execute if data storage smelter:smelter environment_stack[$(stack_index)].bindings.$(identifier) run return 1",
+            ),
+            // Else if no parent, return fail
+            String::from(
+                "$execute store result score #resolve__parent_index smelter_internal run data get storage smelter:smelter environment_stack[$(stack_index)].parent",
+            ),
+            debug_log(String::from("resolve: checking if parent exists")),
+            String::from(
+                "execute if score #resolve__parent_index smelter_internal matches ..-1 run return fail",
+            ),
+            // Else, recurse on parent
+            String::from(
+                "execute store result storage smelter:smelter internal.resolve_args.stack_index int 1 run scoreboard players get #resolve__parent_index smelter_internal",
+            ),
+            debug_log(String::from("resolve: calling with parent")),
+            String::from(
+                "return run function smelter:resolve with storage smelter:smelter internal.resolve_args",
+            ),
+        ],
+    }
+}
+
+fn compile_call_expression(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    if let Expression::Super(_) = &expression.callee {
+        context.warn_class_syntax("`super` call", expression.span);
+        return (Vec::new(), Vec::new());
+    }
+    if let Some(compiled) = compile_builtin_call(context, expression, expression_id) {
+        return compiled;
+    }
+    let callee_expr_id = make_expression_id(&expression.callee);
+    // Evaluate callee first
+    let mut compiled = vec![compile_expression(context, &expression.callee)];
+    // The list is staged in the current environment rather than the register so that calls nested
+    // in later arguments can't clobber it
+    compiled.push((
+        vec![format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{expression_id}_arguments set value []"
+        )],
+        Vec::new(),
+    ));
+    compiled.extend(compile_argument_list(
+        context,
+        &expression.arguments,
+        &format!("{expression_id}_arguments"),
+    ));
+    // Calling a property binds `this` to the object it was read from
+    let this_id = match &expression.callee {
+        Expression::StaticMemberExpression(_) | Expression::ComputedMemberExpression(_) => {
+            Some(format!("{callee_expr_id}_object"))
+        }
+        _ => None,
+    };
+    compiled.push((
+        compile_call_invocation(context, expression_id, &callee_expr_id, this_id.as_deref()),
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_call_invocation(
+    context: &CompileContext,
+    expression_id: &str,
+    callee_expr_id: &str,
+    this_id: Option<&str>,
+) -> Vec<String> {
+    // Calls the evaluated callee with the evaluated `{expression_id}_arguments` list
+    let mut commands = vec![
+        // Copy arguments into register
+        format!(
+            "data modify storage smelter:smelter current_arguments set from storage smelter:smelter current_environment.evaluations.{expression_id}_arguments"
+        ),
+        debug_log(format!("invoking function {callee_expr_id}")),
+    ];
+    commands.extend(compile_invocation(context, callee_expr_id, this_id));
+    commands.extend([
+        // Copy return value into evaluation
+        format!("data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter current_return_value"),
+        debug_log(format!("done invoking function {callee_expr_id}")),
+    ]);
+    commands
+}
+
+fn compile_argument_list(
+    context: &mut CompileContext,
+    arguments: &[Argument],
+    list_path: &str,
+) -> Vec<(Vec<String>, Vec<Mcfunction>)> {
+    // Evaluate each argument and append it in source order to the list at `list_path` (relative to
+    // the current environment's evaluations), with spread arguments appending each of their elements
+    let mut compiled = Vec::new();
+    for argument in arguments {
+        match argument {
+            Argument::SpreadElement(spread) => {
+                let argument_id = make_expression_id(&spread.argument);
+                warn_if_not_iterable(context, &spread.argument);
+                compiled.push(compile_expression(context, &spread.argument));
+                let mut commands = compile_to_array(&argument_id);
+                commands.push(format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{list_path} append from storage smelter:smelter internal.to_array_result.array[]"
+                ));
+                compiled.push((commands, Vec::new()));
+            }
+            _ => {
+                if let Some(arg_expr) = argument.as_expression() {
+                    let arg_expr_id = make_expression_id(arg_expr);
+                    compiled.push(compile_expression(context, arg_expr));
+                    compiled.push((
+                        vec![format!(
+                            "data modify storage smelter:smelter current_environment.evaluations.{list_path} append from storage smelter:smelter current_environment.evaluations.{arg_expr_id}"
+                        )],
+                        Vec::new(),
+                    ));
+                }
+            }
+        }
+    }
+    compiled
+}
+
+fn compile_new_expression(
+    context: &mut CompileContext,
+    expression: &NewExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    // `Array` is the only constructor, since there are no classes or prototypes; spread arguments
+    // to anything else are covered by the class diagnostic
+    let Expression::Identifier(callee) = &expression.callee else {
+        context.warn_class_syntax("`new` expression", expression.span);
+        return (Vec::new(), Vec::new());
+    };
+    if callee.name != "Array" {
+        context.warn_class_syntax("`new` expression", expression.span);
+        return (Vec::new(), Vec::new());
+    }
+    // A single number argument is a length rather than an element
+    if let [argument] = &expression.arguments[..] {
+        if !matches!(
+            argument,
+            Argument::SpreadElement(_)
+                | Argument::StringLiteral(_)
+                | Argument::ArrayExpression(_)
+                | Argument::ObjectExpression(_)
+                | Argument::BooleanLiteral(_)
+                | Argument::NullLiteral(_)
+        ) {
+            context.warn_unsupported(
+                String::from("`new Array` with a single argument (it could be a length)"),
+                expression.span,
+            );
+            return (Vec::new(), Vec::new());
+        }
+    }
+    let mut compiled = vec![(
+        vec![
+            debug_log(String::from("evaluating built-in new Array")),
+            format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+            ),
+        ],
+        Vec::new(),
+    )];
+    compiled.extend(compile_argument_list(
+        context,
+        &expression.arguments,
+        &format!("{expression_id}.array"),
+    ));
+    compiled.push((
+        vec![debug_log(String::from(
+            "done evaluating built-in new Array",
+        ))],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_invocation(
+    context: &CompileContext,
+    callee_expr_id: &str,
+    this_id: Option<&str>,
+) -> Vec<String> {
+    // Expects the arguments to be in the register already, and leaves the result in
+    // `current_return_value`
+    let set_this = match this_id {
+        Some(this_id) => format!(
+            "data modify storage smelter:smelter current_this set from storage smelter:smelter current_environment.evaluations.{this_id}"
+        ),
+        None => format!(
+            "data modify storage smelter:smelter current_this set value {}",
+            context.undefined_snbt()
+        ),
+    };
+    vec![
+        set_this,
+        // Push current environment onto stack
+        String::from(
+            "data modify storage smelter:smelter environment_stack append from storage smelter:smelter current_environment",
+        ),
+        // Invoke callee function
+        format!(
+            "function smelter:invoke with storage smelter:smelter current_environment.evaluations.{callee_expr_id}.function"
+        ),
+        // Pop environment
+        String::from("function smelter:pop_stack"),
+        // Clear arguments
+        String::from("data modify storage smelter:smelter current_arguments set value []"),
+    ]
+}
+
+fn compile_builtin_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+) -> Option<(Vec<String>, Vec<Mcfunction>)> {
+    if let Expression::Identifier(callee) = &expression.callee {
+        return match callee.name.as_str() {
+            function_name @ ("Boolean" | "Number" | "String") => Some(compile_conversion_call(
+                context,
+                expression,
+                expression_id,
+                function_name,
+            )),
+            function_name @ ("parseInt" | "parseFloat") => Some(compile_parse_call(
+                context,
+                expression,
+                expression_id,
+                function_name,
+            )),
+            _ => None,
+        };
+    }
+    let Expression::StaticMemberExpression(callee) = &expression.callee else {
+        return None;
+    };
+    let property_name = callee.property.name.as_str();
+    if let Expression::Identifier(object) = &callee.object {
+        let object_name = object.name.as_str();
+        match object_name {
+            "console" => {
+                return Some(compile_console_call(
+                    context,
+                    expression,
+                    expression_id,
+                    property_name,
+                ));
+            }
+            "Array" if property_name == "from" => {
+                return Some(compile_array_from_call(context, expression, expression_id));
+            }
+            "Number" if matches!(property_name, "parseInt" | "parseFloat") => {
+                return Some(compile_parse_call(
+                    context,
+                    expression,
+                    expression_id,
+                    property_name,
+                ));
+            }
+            "JSON" => {
+                return Some(compile_json_call(
+                    context,
+                    expression,
+                    expression_id,
+                    property_name,
+                ));
+            }
+            "Array" | "Number" => {
+                return Some(compile_type_check_call(
+                    context,
+                    expression,
+                    expression_id,
+                    object_name,
+                    property_name,
+                ));
+            }
+            _ => {}
+        }
+    }
+    // Strings and arrays have these methods built in, so which one runs is decided by the
+    // receiver's type at runtime, and anything else (like an object with its own `map`) gets a
+    // normal method call
+    let receiver_type = match property_name {
+        "toUpperCase" | "toLowerCase" | "includes" | "startsWith" | "endsWith" | "slice" => {
+            "string"
+        }
+        "charAt" | "charCodeAt" | "padEnd" | "padStart" | "repeat" | "replace" | "replaceAll"
+        | "split" | "substring" | "toLocaleLowerCase" | "toLocaleUpperCase" | "trim"
+        | "trimEnd" | "trimStart" => {
+            context.warn_unsupported(
+                format!("string method `{property_name}` (gives undefined on strings)"),
+                callee.span,
+            );
+            "string"
+        }
+        "map" | "filter" | "forEach" => "array",
+        "push" | "pop" | "shift" | "unshift" => {
+            // Like `delete`, these methods change a copy of the array which is then written back
+            // to its variable, so the array has to be named by a variable
+            if !matches!(callee.object, Expression::Identifier(_)) {
+                context.warn_unsupported(
+                    format!("`{property_name}` on an array that isn't a variable"),
+                    callee.object.span(),
+                );
+                return None;
+            }
+            "array"
+        }
+        _ => return None,
+    };
+    Some(compile_method_call(
+        context,
+        expression,
+        expression_id,
+        callee,
+        receiver_type,
+    ))
+}
+
+fn compile_method_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+    callee: &StaticMemberExpression,
+    receiver_type: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let method_name = callee.property.name.as_str();
+    let evaluations = "storage smelter:smelter current_environment.evaluations";
+    // The receiver's evaluation shares its ID with the call, so it's copied aside. The arguments
+    // are evaluated once up front, for whichever kind of call ends up using them
+    let receiver_id = format!("{expression_id}_receiver");
+    let arguments_id = format!("{expression_id}_arguments");
+    // Chained calls like `a.b().c()` all start at the same offset, so the subfunctions are named
+    // after the method name's position instead
+    let method_span = callee.property.span;
+    let builtin_name = make_subfunction_name(context, "builtin_method", &method_span);
+    let method_function_name = make_subfunction_name(context, "method", &method_span);
+    let mut compiled = vec![
+        (
+            vec![debug_log(format!("evaluating method call .{method_name}"))],
+            Vec::new(),
+        ),
+        compile_expression(context, &callee.object),
+        (
+            vec![
+                format!(
+                    "data modify {evaluations}.{receiver_id} set from {evaluations}.{}",
+                    make_expression_id(&callee.object)
+                ),
+                format!("data modify {evaluations}.{arguments_id} set value []"),
+            ],
+            Vec::new(),
+        ),
+    ];
+    compiled.extend(compile_argument_list(
+        context,
+        &expression.arguments,
+        &arguments_id,
+    ));
+
+    let (builtin_body, mut subfunctions) = match (receiver_type, &callee.object) {
+        ("array", Expression::Identifier(variable))
+            if matches!(method_name, "push" | "pop" | "shift" | "unshift") =>
+        {
+            compile_array_mutation_call(
+                context,
+                expression_id,
+                &receiver_id,
+                &variable.name,
+                method_name,
+            )
+        }
+        ("array", _) => compile_array_iteration_call(
+            context,
+            expression_id,
+            &receiver_id,
+            method_name,
+            &method_span,
+        ),
+        _ => compile_string_method_call(context, expression_id, &receiver_id, method_name),
+    };
+    let (mut method_body, method_subfunctions) = compile_property_read(
+        context,
+        &receiver_id,
+        method_name,
+        &method_span,
+        expression_id,
+    );
+    method_body.extend(compile_call_invocation(
+        context,
+        expression_id,
+        &make_expression_id(&expression.callee),
+        Some(&format!("{expression_id}_object")),
+    ));
+    subfunctions.extend(method_subfunctions);
+    subfunctions.extend([
+        Mcfunction {
+            name: builtin_name.clone(),
+            body: builtin_body,
+        },
+        Mcfunction {
+            name: method_function_name.clone(),
+            body: method_body,
+        },
+    ]);
+    compiled.push((
+        vec![
+            format!(
+                "execute if data {evaluations}.{receiver_id}.{receiver_type} run function smelter:{builtin_name}"
+            ),
+            format!(
+                "execute unless data {evaluations}.{receiver_id}.{receiver_type} run function smelter:{method_function_name}"
+            ),
+            debug_log(format!("done evaluating method call .{method_name}")),
+        ],
+        subfunctions,
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_string_method_call(
+    context: &CompileContext,
+    expression_id: &str,
+    receiver_id: &str,
+    method_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let evaluations = "storage smelter:smelter current_environment.evaluations";
+    let helper_name = match method_name {
+        "toUpperCase" => "string_to_upper_case",
+        "toLowerCase" => "string_to_lower_case",
+        "includes" => "string_includes",
+        "startsWith" => "string_starts_with",
+        "endsWith" => "string_ends_with",
+        "slice" => "string_slice",
+        // Reported when compiling the call
+        _ => {
+            return (
+                vec![
+                    debug_log(format!("string method {method_name} is not supported")),
+                    format!(
+                        "data modify {evaluations}.{expression_id} set value {}",
+                        context.undefined_snbt()
+                    ),
+                ],
+                Vec::new(),
+            );
+        }
+    };
+    let mut commands = vec![
+        debug_log(format!("evaluating string method {method_name}")),
+        String::from(
+            "data modify storage smelter:smelter internal.string_method_args set value {}",
+        ),
+        format!(
+            "data modify storage smelter:smelter internal.string_method_args.string set from {evaluations}.{receiver_id}.string"
+        ),
+    ];
+    // The helpers take up to two arguments, and missing ones are left out
+    for index in 0..2 {
+        commands.push(format!(
+            "data modify storage smelter:smelter internal.string_method_args.argument{index} set from {evaluations}.{expression_id}_arguments[{index}]"
+        ));
+    }
+    commands.extend([
+        format!(
+            "data modify storage smelter:smelter internal.string_method_result set value {}",
+            context.undefined_snbt()
+        ),
+        format!("function smelter:{helper_name}"),
+        format!(
+            "data modify {evaluations}.{expression_id} set from storage smelter:smelter internal.string_method_result"
+        ),
+        debug_log(format!("done evaluating string method {method_name}")),
+    ]);
+    (commands, Vec::new())
+}
+
+fn compile_array_iteration_call(
+    context: &CompileContext,
+    expression_id: &str,
+    array_id: &str,
+    method_name: &str,
+    span: &Span,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let evaluations = "storage smelter:smelter current_environment.evaluations";
+    let callback_id = format!("{expression_id}_callback");
+    let step_name = make_subfunction_name(
+        context,
+        &format!("array_{}", method_name.to_lowercase()),
+        span,
+    );
+    let mut commands = vec![
+        debug_log(format!("evaluating array method {method_name}")),
+        // Calling without a callback would be a TypeError, which gives undefined until there are
+        // exceptions
+        format!(
+            "data modify {evaluations}.{expression_id} set value {}",
+            context.undefined_snbt()
+        ),
+        format!(
+            "execute unless data {evaluations}.{expression_id}_arguments[0].function run return 0"
+        ),
+        format!(
+            "data modify {evaluations}.{callback_id} set from {evaluations}.{expression_id}_arguments[0]"
+        ),
+    ];
+    // The elements still to be visited are consumed from the front of a copy of the array, and the
+    // step function calls itself until the copy is empty
+    commands.extend([
+        format!(
+            "data modify {evaluations}.{expression_id} set value {}",
+            context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+        ),
+        format!("data modify {evaluations}.{expression_id}_remaining set value []"),
+        format!(
+            "data modify {evaluations}.{expression_id}_remaining set from {evaluations}.{array_id}.array"
+        ),
+        format!(
+            "data modify {evaluations}.{expression_id}_index set value {}",
+            context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+        ),
+        format!("function smelter:{step_name}"),
+    ]);
+    if method_name == "forEach" {
+        commands.push(format!(
+            "data modify {evaluations}.{expression_id} set value {}",
+            context.undefined_snbt()
+        ));
+    }
+    commands.push(debug_log(format!(
+        "done evaluating array method {method_name}"
+    )));
+
+    // Call the callback with the element, its index and the array
+    let mut step_body = vec![
+        format!("execute unless data {evaluations}.{expression_id}_remaining[0] run return 0"),
+        String::from("data modify storage smelter:smelter current_arguments set value []"),
+        format!(
+            "data modify storage smelter:smelter current_arguments append from {evaluations}.{expression_id}_remaining[0]"
+        ),
+        format!(
+            "data modify storage smelter:smelter current_arguments append from {evaluations}.{expression_id}_index"
+        ),
+        format!(
+            "data modify storage smelter:smelter current_arguments append from {evaluations}.{array_id}"
+        ),
+    ];
+    step_body.extend(compile_invocation(context, &callback_id, None));
+    match method_name {
+        "map" => step_body.push(format!(
+            "data modify {evaluations}.{expression_id}.array append from storage smelter:smelter current_return_value"
+        )),
+        "filter" => {
+            step_body.extend([
+                String::from("data remove storage smelter:smelter internal.is_truthy_args.value"),
+                String::from(
+                    "data modify storage smelter:smelter internal.is_truthy_args.value set from storage smelter:smelter current_return_value",
+                ),
+            ]);
+            step_body.push(format!(
+                "execute if function smelter:is_truthy run data modify {evaluations}.{expression_id}.array append from {evaluations}.{expression_id}_remaining[0]"
+            ));
+        }
+        _ => {}
+    }
+    step_body.extend([
+        format!("data remove {evaluations}.{expression_id}_remaining[0]"),
+        format!(
+            "execute store result score #array_index smelter_internal run data get {evaluations}.{expression_id}_index.number"
+        ),
+        String::from("scoreboard players add #array_index smelter_internal 1"),
+        format!(
+            "execute store result {evaluations}.{expression_id}_index.number double 1 run scoreboard players get #array_index smelter_internal"
+        ),
+        format!("function smelter:{step_name}"),
+    ]);
+    (
+        commands,
+        vec![Mcfunction {
+            name: step_name,
+            body: step_body,
+        }],
+    )
+}
+
+fn compile_array_mutation_call(
+    context: &CompileContext,
+    expression_id: &str,
+    array_id: &str,
+    variable_name: &str,
+    method_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let evaluations = "storage smelter:smelter current_environment.evaluations";
+    let arguments_id = format!("{expression_id}_arguments");
+    let mut commands = vec![debug_log(format!("evaluating array method {method_name}"))];
+    // `push` and `unshift` add the arguments at either end and give the new length, and `pop` and
+    // `shift` remove an element from either end and give it, or undefined if the array is empty
+    match method_name {
+        "push" | "unshift" => {
+            if method_name == "push" {
+                commands.push(format!(
+                    "data modify {evaluations}.{array_id}.array append from {evaluations}.{arguments_id}[]"
+                ));
+            } else {
+                commands.extend([
+                    format!(
+                        "data modify {evaluations}.{arguments_id} append from {evaluations}.{array_id}.array[]"
+                    ),
+                    format!(
+                        "data modify {evaluations}.{array_id}.array set from {evaluations}.{arguments_id}"
+                    ),
+                ]);
+            }
+            commands.extend([
+                format!(
+                    "data modify {evaluations}.{expression_id} set value {}",
+                    context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+                ),
+                format!(
+                    "execute store result {evaluations}.{expression_id}.number double 1 run data get {evaluations}.{array_id}.array"
+                ),
+            ]);
+        }
+        _ => {
+            let index = if method_name == "pop" { -1 } else { 0 };
+            commands.extend([
+                format!(
+                    "data modify {evaluations}.{expression_id} set value {}",
+                    context.undefined_snbt()
+                ),
+                format!(
+                    "data modify {evaluations}.{expression_id} set from {evaluations}.{array_id}.array[{index}]"
+                ),
+                format!("data remove {evaluations}.{array_id}.array[{index}]"),
+            ]);
+        }
+    }
+    commands.extend(compile_binding_update(context, variable_name, array_id));
+    commands.push(debug_log(format!(
+        "done evaluating array method {method_name}"
+    )));
+    (commands, Vec::new())
+}
+
+fn compile_console_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+    method_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let color = match method_name {
+        "log" | "info" | "debug" => "",
+        "warn" => ",color:'yellow'",
+        "error" => ",color:'red'",
+        _ => {
+            context.warn_unsupported(format!("built-in `console.{method_name}`"), expression.span);
+            return (Vec::new(), Vec::new());
+        }
+    };
+    let mut compiled = vec![(
+        vec![
+            debug_log(format!("evaluating console.{method_name}")),
+            String::from("data modify storage smelter:smelter internal.console_text set value []"),
+        ],
+        Vec::new(),
+    )];
+    // Stringify each argument into a list which the text component joins with spaces
+    for argument in expression.arguments.iter() {
+        match argument.as_expression() {
+            Some(arg_expr) => {
+                let arg_expr_id = make_expression_id(arg_expr);
+                compiled.push(compile_expression(context, arg_expr));
+                compiled.push((
+                    vec![
+                        format!(
+                            "data modify storage smelter:smelter internal.to_string_args.value set from storage smelter:smelter current_environment.evaluations.{arg_expr_id}"
+                        ),
+                        String::from("function smelter:to_string"),
+                        String::from(
+                            "data modify storage smelter:smelter internal.console_text append from storage smelter:smelter internal.to_string_result",
+                        ),
+                    ],
+                    Vec::new(),
+                ));
+            }
+            None => {
+                context.warn_unsupported(String::from("spread arguments"), argument.span());
+            }
+        }
+    }
+    compiled.push((
+        vec![
+            format!(
+                "tellraw @a {{type:'nbt',source:'storage',storage:'smelter:smelter',nbt:'internal.console_text[]',separator:' '{color}}}"
+            ),
+            format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {}",
+                context.undefined_snbt()
+            ),
+            debug_log(format!("done evaluating console.{method_name}")),
+        ],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_parse_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+    function_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let helper_name = match function_name {
+        "parseInt" => "parse_int",
+        _ => "parse_float",
+    };
+    if expression
+        .arguments
+        .iter()
+        .any(|argument| argument.is_spread())
+    {
+        context.warn_unsupported(
+            format!("spread arguments to `{function_name}`"),
+            expression.span,
+        );
+    }
+    // The argument is staged in the current environment since evaluating the radix could call
+    // functions that use the registers. A missing argument is undefined, which parses as NaN
+    let argument_evaluation =
+        format!("storage smelter:smelter current_environment.evaluations.{expression_id}_argument");
+    let mut compiled = vec![(
+        vec![
+            debug_log(format!("evaluating built-in {function_name}")),
+            format!(
+                "data modify {argument_evaluation} set value {}",
+                context.undefined_snbt()
+            ),
+        ],
+        Vec::new(),
+    )];
+    let mut arguments = expression
+        .arguments
+        .iter()
+        .map(|argument| argument.as_expression());
+    if let Some(Some(arg_expr)) = arguments.next() {
+        compiled.push(compile_expression(context, arg_expr));
+        compiled.push((
+            vec![format!(
+                "data modify {argument_evaluation} set from storage smelter:smelter current_environment.evaluations.{}",
+                make_expression_id(arg_expr)
+            )],
+            Vec::new(),
+        ));
+    }
+    // The radix is truncated to an integer, and a NaN or missing one is 0 (the default)
+    let mut radix_commands = vec![String::from(
+        "scoreboard players set #parse_radix smelter_internal 0",
+    )];
+    if let (Some(Some(radix_expr)), "parse_int") = (arguments.next(), helper_name) {
+        compiled.push(compile_expression(context, radix_expr));
+        radix_commands.extend([
+            format!(
+                "data modify storage smelter:smelter internal.to_number_args.value set from storage smelter:smelter current_environment.evaluations.{}",
+                make_expression_id(radix_expr)
+            ),
+            String::from("function smelter:to_number"),
+            String::from(
+                "execute store result score #parse_radix smelter_internal run data get storage smelter:smelter internal.to_number_result.number",
+            ),
+        ]);
+    }
+    compiled.push((radix_commands, Vec::new()));
+    compiled.push((
+        vec![
+            // The argument is parsed as a string, like in JS
+            String::from("data remove storage smelter:smelter internal.to_string_args.value"),
+            format!(
+                "data modify storage smelter:smelter internal.to_string_args.value set from {argument_evaluation}"
+            ),
+            String::from("function smelter:to_string"),
+            String::from("data modify storage smelter:smelter internal.parse_args set value {}"),
+            String::from(
+                "data modify storage smelter:smelter internal.parse_args.string set from storage smelter:smelter internal.to_string_result",
+            ),
+            format!("function smelter:{helper_name}"),
+            format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter internal.parse_result"
+            ),
+            debug_log(format!("done evaluating built-in {function_name}")),
+        ],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_json_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+    property_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let (helper_name, argument_key) = match property_name {
+        "stringify" => ("json_stringify", "value"),
+        "parse" => ("json_parse", "string"),
+        _ => {
+            context.warn_unsupported(format!("built-in `JSON.{property_name}`"), expression.span);
+            return (Vec::new(), Vec::new());
+        }
+    };
+    if expression.arguments.len() > 1 {
+        // Replacers, revivers and indentation would need callbacks into compiled functions
+        context.warn_unsupported(
+            format!("extra arguments to `JSON.{property_name}`"),
+            expression.span,
+        );
+    }
+    let args = format!("storage smelter:smelter internal.{helper_name}_args");
+    let mut compiled = vec![(
+        vec![
+            debug_log(format!("evaluating built-in JSON.{property_name}")),
+            format!("data modify {args} set value {{}}"),
+        ],
+        Vec::new(),
+    )];
+    let argument_commands = match expression
+        .arguments
+        .first()
+        .and_then(|argument| argument.as_expression())
+    {
+        Some(arg_expr) => {
+            compiled.push(compile_expression(context, arg_expr));
+            let argument_evaluation = format!(
+                "storage smelter:smelter current_environment.evaluations.{}",
+                make_expression_id(arg_expr)
+            );
+            if helper_name == "json_parse" {
+                // The argument is parsed as a string, like in JS
+                vec![
+                    String::from(
+                        "data remove storage smelter:smelter internal.to_string_args.value",
+                    ),
+                    format!(
+                        "data modify storage smelter:smelter internal.to_string_args.value set from {argument_evaluation}"
+                    ),
+                    String::from("function smelter:to_string"),
+                    format!(
+                        "data modify {args}.string set from storage smelter:smelter internal.to_string_result"
+                    ),
+                ]
+            } else {
+                vec![format!(
+                    "data modify {args}.value set from {argument_evaluation}"
+                )]
+            }
+        }
+        None => vec![format!(
+            "data modify {args}.{argument_key} set value {}",
+            if helper_name == "json_parse" {
+                context.snbt(&Nbt::string("undefined"))
+            } else {
+                context.undefined_snbt()
+            }
+        )],
+    };
+    compiled.push((argument_commands, Vec::new()));
+    compiled.push((
+        vec![
+            format!("function smelter:{helper_name}"),
+            format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter internal.{helper_name}_result"
+            ),
+            debug_log(format!("done evaluating built-in JSON.{property_name}")),
+        ],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_conversion_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+    function_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let evaluation =
+        format!("storage smelter:smelter current_environment.evaluations.{expression_id}");
+    let mut compiled = vec![(
+        vec![debug_log(format!("evaluating built-in {function_name}"))],
+        Vec::new(),
+    )];
+    let argument = expression
+        .arguments
+        .first()
+        .and_then(|argument| argument.as_expression());
+    let commands = match argument {
+        Some(arg_expr) => {
+            let argument_evaluation = format!(
+                "storage smelter:smelter current_environment.evaluations.{}",
+                make_expression_id(arg_expr)
+            );
+            compiled.push(compile_expression(context, arg_expr));
+            match function_name {
+                "Boolean" => vec![
+                    // Cleared first so that a missing evaluation is falsy instead of reusing the
+                    // previous value
+                    String::from(
+                        "data modify storage smelter:smelter internal.is_truthy_args set value {}",
+                    ),
+                    format!(
+                        "data modify storage smelter:smelter internal.is_truthy_args.value set from {argument_evaluation}"
+                    ),
+                    format!(
+                        "data modify {evaluation} set value {}",
+                        context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(false))]))
+                    ),
+                    format!(
+                        "execute store result {evaluation}.boolean byte 1 run function smelter:is_truthy"
+                    ),
+                ],
+                "Number" => vec![
+                    format!(
+                        "data modify storage smelter:smelter internal.to_number_args.value set from {argument_evaluation}"
+                    ),
+                    String::from("function smelter:to_number"),
+                    format!(
+                        "data modify {evaluation} set from storage smelter:smelter internal.to_number_result"
+                    ),
+                ],
+                _ => vec![
+                    format!(
+                        "data modify storage smelter:smelter internal.to_string_args.value set from {argument_evaluation}"
+                    ),
+                    String::from("function smelter:to_string"),
+                    format!(
+                        "data modify {evaluation} set value {}",
+                        context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+                    ),
+                    format!(
+                        "data modify {evaluation}.string set from storage smelter:smelter internal.to_string_result"
+                    ),
+                ],
+            }
+        }
+        // Without an argument the conversions return false, 0 and '' respectively
+        None => {
+            let value = match function_name {
+                "Boolean" => Nbt::compound([("boolean", Nbt::Boolean(false))]),
+                "Number" => Nbt::compound([("number", Nbt::Double(0.0))]),
+                _ => Nbt::compound([("string", Nbt::string(""))]),
+            };
+            vec![format!(
+                "data modify {evaluation} set value {}",
+                context.snbt(&value)
+            )]
+        }
+    };
+    compiled.push((commands, Vec::new()));
+    compiled.push((
+        vec![debug_log(format!(
+            "done evaluating built-in {function_name}"
+        ))],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_array_from_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let Some(argument) = expression
+        .arguments
+        .first()
+        .and_then(|argument| argument.as_expression())
+    else {
+        context.warn(
+            "not-iterable",
+            String::from("`Array.from` needs an iterable argument"),
+            expression.span,
+        );
+        return (Vec::new(), Vec::new());
+    };
+    if expression.arguments.len() > 1 {
+        context.warn_unsupported(
+            String::from("`Array.from` with a mapping function"),
+            expression.arguments[1].span(),
+        );
+    }
+    let argument_id = make_expression_id(argument);
+    warn_if_not_iterable(context, argument);
+    let (mut commands, subfunctions) = compile_expression(context, argument);
+    commands.push(debug_log(String::from("evaluating built-in Array.from")));
+    commands.extend(compile_to_array(&argument_id));
+    commands.extend([
+        format!(
+            "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set from storage smelter:smelter internal.to_array_result"
+        ),
+        debug_log(String::from("done evaluating built-in Array.from")),
+    ]);
+    (commands, subfunctions)
+}
+
+fn warn_if_not_iterable(context: &mut CompileContext, expression: &Expression) {
+    // Only literals are checked; anything else is converted at runtime, where values other than
+    // arrays and strings become empty arrays
+    let what = match expression {
+        Expression::BigIntLiteral(_) => "a bigint",
+        Expression::BooleanLiteral(_) => "a boolean",
+        Expression::NullLiteral(_) => "null",
+        Expression::NumericLiteral(_) => "a number",
+        Expression::ObjectExpression(_) => "an object",
+        Expression::ArrowFunctionExpression(_) | Expression::FunctionExpression(_) => "a function",
+        _ => return,
+    };
+    context.warn(
+        "not-iterable",
+        format!("Only arrays and strings can be iterated, but this is {what}"),
+        expression.span(),
+    );
+}
+
+fn compile_to_array(expression_id: &str) -> Vec<String> {
+    // Leaves an array value in `internal.to_array_result`
+    vec![
+        String::from("data remove storage smelter:smelter internal.to_array_args.value"),
+        format!(
+            "data modify storage smelter:smelter internal.to_array_args.value set from storage smelter:smelter current_environment.evaluations.{expression_id}"
+        ),
+        String::from("function smelter:to_array"),
+    ]
+}
+
+fn compile_type_check_call(
+    context: &mut CompileContext,
+    expression: &CallExpression,
+    expression_id: &str,
+    object_name: &str,
+    property_name: &str,
+) -> (Vec<String>, Vec<Mcfunction>) {
+    let argument = expression
+        .arguments
+        .first()
+        .and_then(|argument| argument.as_expression());
+    let mut compiled = vec![(
+        vec![debug_log(format!(
+            "evaluating built-in {object_name}.{property_name}"
+        ))],
+        Vec::new(),
+    )];
+    if let Some(arg_expr) = argument {
+        compiled.push(compile_expression(context, arg_expr));
+    }
+    let argument_id = argument.map(|arg_expr| make_expression_id(arg_expr));
+    let true_snbt = context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(true))]));
+    let false_snbt = context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(false))]));
+    match (object_name, property_name, argument_id) {
+        ("Array", "isArray", Some(argument_id)) => compiled.push((
+            vec![
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {false_snbt}"
+                ),
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{argument_id}.array run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {true_snbt}"
+                ),
+            ],
+            Vec::new(),
+        )),
+        ("Number", "isInteger", Some(argument_id)) => compiled.push((
+            vec![
+                format!(
+                    "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {false_snbt}"
+                ),
+                // Round-trip the number through a score (which truncates it) and check whether it
+                // changed; numbers outside the score range are reported as non-integers
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{argument_id}.number store result score #is_integer smelter_internal run data get storage smelter:smelter current_environment.evaluations.{argument_id}.number"
+                ),
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{argument_id}.number store result storage smelter:smelter internal.is_integer double 1 run scoreboard players get #is_integer smelter_internal"
+                ),
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{argument_id}.number store success score #is_integer smelter_internal run data modify storage smelter:smelter internal.is_integer set from storage smelter:smelter current_environment.evaluations.{argument_id}.number"
+                ),
+                format!(
+                    "execute if data storage smelter:smelter current_environment.evaluations.{argument_id}.number if score #is_integer smelter_internal matches 0 run data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {true_snbt}"
+                ),
+            ],
+            Vec::new(),
+        )),
+        // Type checks are false without an argument
+        ("Array", "isArray", None) | ("Number", "isInteger", None) => compiled.push((
+            vec![format!(
+                "data modify storage smelter:smelter current_environment.evaluations.{expression_id} set value {false_snbt}"
+            )],
+            Vec::new(),
+        )),
+        _ => {
+            context.warn_unsupported(
+                format!("built-in `{object_name}.{property_name}`"),
+                expression.span,
+            );
+            return (Vec::new(), Vec::new());
+        }
+    }
+    compiled.push((
+        vec![debug_log(format!(
+            "done evaluating built-in {object_name}.{property_name}"
+        ))],
+        Vec::new(),
+    ));
+    reduce_compiled(compiled)
+}
+
+fn compile_function_invocation(context: &CompileContext) -> Mcfunction {
+    let mut body = vec![format!(
+        "${}",
+        debug_log(String::from(
+            "entering invoke: name=$(name) environment_index=$(environment_index)"
+        ))
+    )];
+    if context.options.trace {
+        body.extend([
+            format!(
+                "$data modify storage smelter:smelter trace append value {}",
+                context.snbt(&Nbt::compound([("name", Nbt::string("$(name)"))]))
+            ),
+            String::from(
+                "data modify storage smelter:smelter trace[-1].arguments set from storage smelter:smelter current_arguments",
+            ),
+        ]);
+    }
+    body.extend([
+        String::from(
+            "$data modify storage smelter:smelter current_environment set from storage smelter:smelter environment_stack[$(environment_index)]",
+        ),
+        String::from("$function smelter:$(name)"),
+        debug_log(String::from("exiting invoke")),
+    ]);
+    Mcfunction {
+        name: String::from("invoke"),
+        body,
+    }
+}
+
+fn compile_stack_pop() -> Mcfunction {
+    Mcfunction {
+        name: String::from("pop_stack"),
+        body: vec![
+            debug_log(String::from("entering pop_stack")),
+            String::from(
+                "data modify storage smelter:smelter current_environment set from storage smelter:smelter environment_stack[-1]",
+            ),
+            String::from("data remove storage smelter:smelter environment_stack[-1]"),
+            debug_log(String::from("exiting pop_stack")),
+        ],
+    }
+}
+
+fn compile_to_string(context: &CompileContext) -> Mcfunction {
+    let value = "storage smelter:smelter internal.to_string_args.value";
+    let result = "storage smelter:smelter internal.to_string_result";
+    let true_snbt = context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(true))]));
+    let false_snbt = context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(false))]));
+    Mcfunction {
+        name: String::from("to_string"),
+        body: vec![
+            debug_log(String::from("entering to_string")),
+            format!(
+                "execute if data {value}.string run return run data modify {result} set from {value}.string"
+            ),
+            format!(
+                "execute if data {value}{true_snbt} run return run data modify {result} set value 'true'"
+            ),
+            format!(
+                "execute if data {value}{false_snbt} run return run data modify {result} set value 'false'"
+            ),
+            format!(
+                "execute if data {value}.null run return run data modify {result} set value 'null'"
+            ),
+            format!(
+                "execute if data {value}.undefined run return run data modify {result} set value 'undefined'"
+            ),
+            format!(
+                "execute if data {value}.nan run return run data modify {result} set value 'NaN'"
+            ),
+            // Whole numbers are printed through an int so that they don't get a fractional part
+            format!(
+                "execute if data {value}.number store result score #to_string smelter_internal run data get {value}.number"
+            ),
+            format!(
+                "execute if data {value}.number store result storage smelter:smelter internal.to_string_integer int 1 run scoreboard players get #to_string smelter_internal"
+            ),
+            format!(
+                "execute if data {value}.number store result storage smelter:smelter internal.to_string_double double 1 run scoreboard players get #to_string smelter_internal"
+            ),
+            format!(
+                "execute if data {value}.number store success score #to_string smelter_internal run data modify storage smelter:smelter internal.to_string_double set from {value}.number"
+            ),
+            format!(
+                "execute if data {value}.number if score #to_string smelter_internal matches 0 run return run data modify {result} set string storage smelter:smelter internal.to_string_integer"
+            ),
+            // Other numbers and bigints drop the type suffix from their SNBT form
+            format!(
+                "execute if data {value}.number run return run data modify {result} set string {value}.number 0 -1"
+            ),
+            format!(
+                "execute if data {value}.bigint run return run data modify {result} set string {value}.bigint 0 -1"
+            ),
+            format!(
+                "execute if data {value}.function run return run data modify {result} set value 'function'"
+            ),
+            format!(
+                "execute if data {value}.array run return run data modify {result} set value '[object Array]'"
+            ),
+            format!("data modify {result} set value '[object Object]'"),
+        ],
+    }
+}
+
+fn compile_is_truthy(context: &CompileContext) -> Mcfunction {
+    let value = "storage smelter:smelter internal.is_truthy_args.value";
+    let falsy_values = [
+        Nbt::compound([("boolean", Nbt::Boolean(false))]),
+        Nbt::compound([("number", Nbt::Double(0.0))]),
+        Nbt::compound([("number", Nbt::Double(-0.0))]),
+        Nbt::compound([("string", Nbt::string(""))]),
+        Nbt::compound([("bigint", Nbt::Long(0))]),
+    ];
+    let mut body = vec![
+        format!("execute unless data {value} run return 0"),
+        format!("execute if data {value}.undefined run return 0"),
+        format!("execute if data {value}.null run return 0"),
+        format!("execute if data {value}.nan run return 0"),
+    ];
+    body.extend(falsy_values.iter().map(|falsy_value| {
+        format!(
+            "execute if data {value}{} run return 0",
+            context.snbt(falsy_value)
+        )
+    }));
+    body.push(String::from("return 1"));
+    Mcfunction {
+        name: String::from("is_truthy"),
+        body,
+    }
+}
+
+fn compile_to_number(context: &CompileContext) -> Mcfunction {
+    let value = "storage smelter:smelter internal.to_number_args.value";
+    let result = "storage smelter:smelter internal.to_number_result";
+    let parsed = "storage smelter:smelter internal.parse_number_result";
+    Mcfunction {
+        name: String::from("to_number"),
+        body: vec![
+            debug_log(String::from("entering to_number")),
+            format!(
+                "execute if data {value}.number run return run data modify {result} set from {value}"
+            ),
+            format!(
+                "execute if data {value}.nan run return run data modify {result} set from {value}"
+            ),
+            format!(
+                "data modify {result} set value {}",
+                context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+            ),
+            // Null, false and the empty string are all 0
+            format!("execute if data {value}.null run return 1"),
+            format!(
+                "execute if data {value}.boolean store result {result}.number double 1 run data get {value}.boolean"
+            ),
+            format!("execute if data {value}.boolean run return 1"),
+            format!(
+                "execute if data {value}.bigint store result {result}.number double 1 run data get {value}.bigint"
+            ),
+            format!("execute if data {value}.bigint run return 1"),
+            format!(
+                "execute if data {value}{} run return 1",
+                context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+            ),
+            // Other strings are parsed as SNBT doubles, which fails (leaving no result) or produces a
+            // string if they aren't plain decimal numbers
+            format!("data remove {parsed}"),
+            format!(
+                "execute if data {value}.string run function smelter:parse_number with {value}"
+            ),
+            format!(
+                "execute store success score #to_number smelter_internal run data get {parsed} 1"
+            ),
+            format!(
+                "execute if score #to_number smelter_internal matches 1 run return run data modify {result}.number set from {parsed}"
+            ),
+            // Everything else is NaN
+            format!(
+                "data modify {result} set value {}",
+                context.snbt(&Nbt::compound([("nan", Nbt::Boolean(true))]))
+            ),
+        ],
+    }
+}
+
+fn compile_number_parsing() -> Mcfunction {
+    Mcfunction {
+        name: String::from("parse_number"),
+        body: vec![String::from(
+            "$data modify storage smelter:smelter internal.parse_number_result set value $(string)d",
+        )],
+    }
+}
+
+fn compile_addition_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    let left = "storage smelter:smelter internal.add_args.left";
+    let right = "storage smelter:smelter internal.add_args.right";
+    let result = "storage smelter:smelter internal.add_result";
+    let to_number = |operand: &str, score: &str| {
+        [
+            format!(
+                "data modify storage smelter:smelter internal.to_number_args.value set from {operand}"
+            ),
+            String::from("function smelter:to_number"),
+            // NaN plus anything is NaN
+            format!(
+                "execute if data storage smelter:smelter internal.to_number_result.nan run return run data modify {result} set from storage smelter:smelter internal.to_number_result"
+            ),
+            format!(
+                "execute store result score {score} smelter_internal run data get storage smelter:smelter internal.to_number_result.number"
+            ),
+        ]
+    };
+    // Adding a string, or an array or function (which convert to strings first), concatenates;
+    // anything else is added as numbers
+    let mut add_body = vec![debug_log(String::from("entering add"))];
+    for operand in [left, right] {
+        for shape in ["string", "array", "function"] {
+            add_body.push(format!(
+                "execute if data {operand}.{shape} run return run function smelter:add_strings"
+            ));
+        }
+    }
+    add_body.extend(to_number(left, "#add_left"));
+    add_body.extend(to_number(right, "#add_right"));
+    add_body.extend([
+        // Scores only hold integers, so fractional parts are rounded down before adding
+        String::from(
+            "scoreboard players operation #add_left smelter_internal += #add_right smelter_internal",
+        ),
+        format!(
+            "data modify {result} set value {}",
+            context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+        ),
+        format!(
+            "execute store result {result}.number double 1 run scoreboard players get #add_left smelter_internal"
+        ),
+    ]);
+
+    let mut add_strings_body = Vec::new();
+    for (operand, key) in [(left, "left"), (right, "right")] {
+        add_strings_body.extend([
+            format!("data modify storage smelter:smelter internal.to_string_args.value set from {operand}"),
+            String::from("function smelter:to_string"),
+            format!(
+                "data modify storage smelter:smelter internal.concat_args.{key} set from storage smelter:smelter internal.to_string_result"
+            ),
+        ]);
+    }
+    add_strings_body.extend([
+        String::from("function smelter:concat"),
+        // `concat` leaves no result if the strings can't be joined, which makes the sum undefined
+        format!(
+            "execute unless data storage smelter:smelter internal.concat_result run return run data modify {result} set value {}",
+            context.undefined_snbt()
+        ),
+        format!(
+            "data modify {result} set value {}",
+            context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+        ),
+        format!(
+            "data modify {result}.string set from storage smelter:smelter internal.concat_result"
+        ),
+    ]);
+
+    vec![
+        Mcfunction {
+            name: String::from("add"),
+            body: add_body,
+        },
+        Mcfunction {
+            name: String::from("add_strings"),
+            body: add_strings_body,
+        },
+        Mcfunction {
+            name: String::from("concat"),
+            // Joins `internal.concat_args.left` and `internal.concat_args.right` into
+            // `internal.concat_result`, which is left unset if that fails
+            body: vec![
+                String::from("data remove storage smelter:smelter internal.concat_result"),
+                String::from(
+                    "function smelter:concat_double_quoted with storage smelter:smelter internal.concat_args",
+                ),
+                String::from(
+                    "execute if data storage smelter:smelter internal.concat_result run return 1",
+                ),
+                // Strings containing a double quote can't be substituted into a double-quoted
+                // literal, but can into a single-quoted one if they don't also contain a single quote
+                String::from(
+                    "function smelter:concat_single_quoted with storage smelter:smelter internal.concat_args",
+                ),
+                String::from(
+                    "execute if data storage smelter:smelter internal.concat_result run return 1",
+                ),
+                debug_log(String::from(
+                    "could not concatenate strings, which may contain both kinds of quotes or a backslash",
+                )),
+                String::from("return fail"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("concat_double_quoted"),
+            body: vec![String::from(
+                "$data modify storage smelter:smelter internal.concat_result set value \"$(left)$(right)\"",
+            )],
+        },
+        Mcfunction {
+            name: String::from("concat_single_quoted"),
+            body: vec![String::from(
+                "$data modify storage smelter:smelter internal.concat_result set value '$(left)$(right)'",
+            )],
+        },
+    ]
+}
+
+fn compile_equality_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    // Both functions compare `internal.equals_args.left` and `internal.equals_args.right`, and
+    // return 1 if they're equal and 0 otherwise
+    let left = "storage smelter:smelter internal.equals_args.left";
+    let right = "storage smelter:smelter internal.equals_args.right";
+    let undefined_snbt = context.undefined_snbt();
+    // Missing operands (e.g. from unsupported expressions) are treated as undefined
+    let default_operands = [left, right].map(|operand| {
+        format!(
+            "execute unless data {operand} run data modify {operand} set value {undefined_snbt}"
+        )
+    });
+
+    // Strict equality compares the stored values directly: they're equal if copying one over the
+    // other changes nothing. Values are copied rather than referenced in storage, so there is no
+    // identity to compare, and arrays and objects are instead deeply compared by contents (`[1] ===
+    // [1]` is true, as are nested NaNs in the same places). Functions are equal if they have the
+    // same name and environment. 0 and -0 are unequal
+    let mut strict_body = vec![debug_log(String::from("entering strict_equals"))];
+    strict_body.extend(default_operands.clone());
+    strict_body.extend([
+        format!("execute if data {left}.nan run return 0"),
+        format!("execute if data {right}.nan run return 0"),
+        format!(
+            "data modify storage smelter:smelter internal.equals_args.compare set from {left}"
+        ),
+        format!(
+            "execute store success score #equals smelter_internal run data modify storage smelter:smelter internal.equals_args.compare set from {right}"
+        ),
+        String::from("execute if score #equals smelter_internal matches 1 run return 0"),
+        String::from("return 1"),
+    ]);
+
+    // Loose equality supports this subset of the coercion rules:
+    // - null and undefined equal each other and nothing else
+    // - values of the same type compare strictly
+    // - arrays, objects and functions compared with anything else convert to strings first
+    // - any other mix (numbers, strings, booleans and bigints) compares as numbers
+    let mut loose_body = vec![debug_log(String::from("entering loose_equals"))];
+    loose_body.extend(default_operands);
+    for operand in [left, right] {
+        loose_body.push(format!(
+            "execute if data {operand}.null run data modify {operand} set value {undefined_snbt}"
+        ));
+    }
+    loose_body.extend([
+        format!("execute if data {left}.undefined if data {right}.undefined run return 1"),
+        format!("execute if data {left}.undefined run return 0"),
+        format!("execute if data {right}.undefined run return 0"),
+        format!("execute if data {left}.nan run return 0"),
+        format!("execute if data {right}.nan run return 0"),
+    ]);
+    let shapes = [
+        "number", "string", "boolean", "bigint", "array", "object", "function",
+    ];
+    for shape in shapes {
+        loose_body.push(format!(
+            "execute if data {left}.{shape} if data {right}.{shape} run return run function smelter:strict_equals"
+        ));
+    }
+    let mut to_primitive_functions = Vec::new();
+    for (operand, key) in [(left, "left"), (right, "right")] {
+        let function_name = format!("loose_equals_{key}_to_string");
+        for shape in ["array", "object", "function"] {
+            loose_body.push(format!(
+                "execute if data {operand}.{shape} run return run function smelter:{function_name}"
+            ));
+        }
+        to_primitive_functions.push(Mcfunction {
+            name: function_name,
+            body: vec![
+                format!(
+                    "data modify storage smelter:smelter internal.to_string_args.value set from {operand}"
+                ),
+                String::from("function smelter:to_string"),
+                format!(
+                    "data modify {operand} set value {}",
+                    context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+                ),
+                format!(
+                    "data modify {operand}.string set from storage smelter:smelter internal.to_string_result"
+                ),
+                String::from("return run function smelter:loose_equals"),
+            ],
+        });
+    }
+    for operand in [left, right] {
+        loose_body.extend([
+            format!(
+                "data modify storage smelter:smelter internal.to_number_args.value set from {operand}"
+            ),
+            String::from("function smelter:to_number"),
+            format!(
+                "data modify {operand} set from storage smelter:smelter internal.to_number_result"
+            ),
+        ]);
+    }
+    loose_body.push(String::from("return run function smelter:strict_equals"));
+
+    [
+        Mcfunction {
+            name: String::from("strict_equals"),
+            body: strict_body,
+        },
+        Mcfunction {
+            name: String::from("loose_equals"),
+            body: loose_body,
+        },
+    ]
+    .into_iter()
+    .chain(to_primitive_functions)
+    .collect()
+}
+
+fn compile_property_functions() -> Vec<Mcfunction> {
+    let args = "storage smelter:smelter internal.in_args";
+    vec![
+        Mcfunction {
+            name: String::from("has_property"),
+            // Returns 1 if `internal.in_args.value` has the property `internal.in_args.key`. Only
+            // arrays and objects have properties; JS would throw a TypeError for anything else
+            body: vec![
+                format!(
+                    "execute if data {args}.value.array run return run function smelter:has_index"
+                ),
+                format!("execute unless data {args}.value.object run return 0"),
+                String::from("data remove storage smelter:smelter internal.to_string_args.value"),
+                format!(
+                    "data modify storage smelter:smelter internal.to_string_args.value set from {args}.key"
+                ),
+                String::from("function smelter:to_string"),
+                format!(
+                    "data modify {args}.key_string set from storage smelter:smelter internal.to_string_result"
+                ),
+                format!("return run function smelter:has_key with {args}"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("has_key"),
+            body: vec![
+                format!("$execute if data {args}.value.object.\"$(key_string)\" run return 1"),
+                format!("$execute if data {args}.value.getters.\"$(key_string)\" run return 1"),
+                String::from("return 0"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("has_index"),
+            // An array has an index if it's within bounds. Fractional indices are rounded down
+            // first, where JS would only accept integers
+            body: vec![
+                String::from("data remove storage smelter:smelter internal.to_number_args.value"),
+                format!(
+                    "data modify storage smelter:smelter internal.to_number_args.value set from {args}.key"
+                ),
+                String::from("function smelter:to_number"),
+                String::from(
+                    "execute unless data storage smelter:smelter internal.to_number_result.number run return 0",
+                ),
+                String::from(
+                    "execute store result score #in_index smelter_internal run data get storage smelter:smelter internal.to_number_result.number",
+                ),
+                String::from(
+                    "execute if score #in_index smelter_internal matches ..-1 run return 0",
+                ),
+                format!(
+                    "execute store result score #in_length smelter_internal run data get {args}.value.array"
+                ),
+                String::from(
+                    "execute if score #in_index smelter_internal >= #in_length smelter_internal run return 0",
+                ),
+                String::from("return 1"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("set_property"),
+            // Sets a property of the object at `evaluations.$(target)` with a key only known at
+            // runtime. Keys containing a double quote can't be substituted into the quoted path
+            body: vec![
+                String::from(
+                    "$data modify storage smelter:smelter current_environment.evaluations.$(target).object.\"$(key)\" set from storage smelter:smelter internal.set_property_args.value",
+                ),
+                String::from(
+                    "$data remove storage smelter:smelter current_environment.evaluations.$(target).getters.\"$(key)\"",
+                ),
+            ],
+        },
+    ]
+}
+
+fn compile_string_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    // Each helper reads `internal.string_method_args` (the receiver's `string` and the arguments as
+    // `argument0` and `argument1`) and leaves a value in `internal.string_method_result`
+    let args = "storage smelter:smelter internal.string_method_args";
+    let result = "storage smelter:smelter internal.string_method_result";
+    let search = "storage smelter:smelter internal.string_search";
+    let slice = "storage smelter:smelter internal.string_slice";
+    let string_result = |source: &str| {
+        [
+            format!(
+                "data modify {result} set value {}",
+                context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+            ),
+            format!("data modify {result}.string set from {source}"),
+        ]
+    };
+    let boolean_result = |value: bool| {
+        format!(
+            "data modify {result} set value {}",
+            context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(value))]))
+        )
+    };
+
+    // Case conversion maps one character at a time through the tables set up by `initialize`, and
+    // leaves characters without an entry unchanged
+    let case_conversion = |name: &str, table: &str| {
+        let mut body = vec![
+            format!(
+                "data modify storage smelter:smelter internal.string_case_args set value {}",
+                context.snbt(&Nbt::compound([("table", Nbt::string(table))]))
+            ),
+            format!(
+                "data modify storage smelter:smelter internal.string_case_args.rest set from {args}.string"
+            ),
+            String::from(
+                "data modify storage smelter:smelter internal.string_case_result set value ''",
+            ),
+            String::from("function smelter:string_case_step"),
+        ];
+        body.extend(string_result(
+            "storage smelter:smelter internal.string_case_result",
+        ));
+        Mcfunction {
+            name: String::from(name),
+            body,
+        }
+    };
+    let case_step = Mcfunction {
+        name: String::from("string_case_step"),
+        body: vec![
+            String::from(
+                "execute if data storage smelter:smelter internal.string_case_args{rest: ''} run return 0",
+            ),
+            String::from(
+                "data modify storage smelter:smelter internal.string_case_args.char set string storage smelter:smelter internal.string_case_args.rest 0 1",
+            ),
+            String::from(
+                "data modify storage smelter:smelter internal.string_case_args.rest set string storage smelter:smelter internal.string_case_args.rest 1",
+            ),
+            String::from(
+                "function smelter:string_case_map with storage smelter:smelter internal.string_case_args",
+            ),
+            String::from(
+                "data modify storage smelter:smelter internal.concat_args.left set from storage smelter:smelter internal.string_case_result",
+            ),
+            String::from(
+                "data modify storage smelter:smelter internal.concat_args.right set from storage smelter:smelter internal.string_case_args.char",
+            ),
+            String::from("function smelter:concat"),
+            String::from(
+                "data modify storage smelter:smelter internal.string_case_result set from storage smelter:smelter internal.concat_result",
+            ),
+            String::from("function smelter:string_case_step"),
+        ],
+    };
+    let case_map = Mcfunction {
+        name: String::from("string_case_map"),
+        // Fails (leaving the character as it is) if the table has no entry for it
+        body: vec![String::from(
+            "$data modify storage smelter:smelter internal.string_case_args.char set from storage smelter:smelter internal.$(table).\"$(char)\"",
+        )],
+    };
+
+    // Searches compare the needle (the first argument converted to a string) with the window of the
+    // same length starting at #search_start, which ranges up to #search_last
+    let search_setup = || {
+        vec![
+            format!("data modify {search} set value {{}}"),
+            format!("data modify {search}.haystack set from {args}.string"),
+            String::from("data remove storage smelter:smelter internal.to_string_args.value"),
+            format!(
+                "data modify storage smelter:smelter internal.to_string_args.value set from {args}.argument0"
+            ),
+            String::from("function smelter:to_string"),
+            format!(
+                "data modify {search}.needle set from storage smelter:smelter internal.to_string_result"
+            ),
+            format!(
+                "execute store result score #search_last smelter_internal run data get {search}.haystack"
+            ),
+            format!(
+                "execute store result score #needle_length smelter_internal run data get {search}.needle"
+            ),
+            String::from(
+                "scoreboard players operation #search_last smelter_internal -= #needle_length smelter_internal",
+            ),
+            boolean_result(false),
+            // The needle can't fit if it's longer than the string
+            String::from(
+                "execute if score #search_last smelter_internal matches ..-1 run return 0",
+            ),
+        ]
+    };
+    let search_found = [
+        String::from(
+            "execute store result score #search_found smelter_internal run function smelter:string_search_at",
+        ),
+        format!(
+            "execute if score #search_found smelter_internal matches 1 run {}",
+            boolean_result(true)
+        ),
+    ];
+    let mut includes_body = search_setup();
+    includes_body.extend([
+        String::from("scoreboard players set #search_start smelter_internal 0"),
+        String::from(
+            "execute store result score #search_found smelter_internal run function smelter:string_includes_step",
+        ),
+        format!(
+            "execute if score #search_found smelter_internal matches 1 run {}",
+            boolean_result(true)
+        ),
+    ]);
+    let mut starts_with_body = search_setup();
+    starts_with_body.push(String::from(
+        "scoreboard players set #search_start smelter_internal 0",
+    ));
+    starts_with_body.extend(search_found.clone());
+    let mut ends_with_body = search_setup();
+    ends_with_body.push(String::from(
+        "scoreboard players operation #search_start smelter_internal = #search_last smelter_internal",
+    ));
+    ends_with_body.extend(search_found);
+
+    // Slice indices are converted to integers and clamped like in JS, with negative indices counting
+    // from the end
+    let mut slice_body = vec![
+        format!("data modify {slice} set value {{}}"),
+        format!("data modify {slice}.string set from {args}.string"),
+        format!(
+            "execute store result score #string_length smelter_internal run data get {slice}.string"
+        ),
+        // A missing or undefined start is 0, and end is the length
+        String::from("scoreboard players set #slice_start smelter_internal 0"),
+        String::from(
+            "scoreboard players operation #slice_end smelter_internal = #string_length smelter_internal",
+        ),
+    ];
+    for (argument, score) in [("argument0", "#slice_start"), ("argument1", "#slice_end")] {
+        slice_body.extend([
+            String::from("data remove storage smelter:smelter internal.to_number_args.value"),
+            format!(
+                "data modify storage smelter:smelter internal.to_number_args.value set from {args}.{argument}"
+            ),
+            String::from("function smelter:to_number"),
+            // NaN has no number to get, so the failed command stores 0
+            format!(
+                "execute if data {args}.{argument} unless data {args}.{argument}.undefined store result score {score} smelter_internal run data get storage smelter:smelter internal.to_number_result.number"
+            ),
+        ]);
+    }
+    for score in ["#slice_start", "#slice_end"] {
+        slice_body.extend([
+            format!(
+                "execute if score {score} smelter_internal matches ..-1 run scoreboard players operation {score} smelter_internal += #string_length smelter_internal"
+            ),
+            format!(
+                "execute if score {score} smelter_internal matches ..-1 run scoreboard players set {score} smelter_internal 0"
+            ),
+            format!(
+                "execute if score {score} smelter_internal > #string_length smelter_internal run scoreboard players operation {score} smelter_internal = #string_length smelter_internal"
+            ),
+        ]);
+    }
+    slice_body.extend([
+        String::from(
+            "execute if score #slice_end smelter_internal < #slice_start smelter_internal run scoreboard players operation #slice_end smelter_internal = #slice_start smelter_internal",
+        ),
+        format!(
+            "execute store result {slice}.start int 1 run scoreboard players get #slice_start smelter_internal"
+        ),
+        format!(
+            "execute store result {slice}.end int 1 run scoreboard players get #slice_end smelter_internal"
+        ),
+        format!("function smelter:string_slice_range with {slice}"),
+    ]);
+    slice_body.extend(string_result(&format!("{slice}.result")));
+
+    vec![
+        case_conversion("string_to_upper_case", "upper_case"),
+        case_conversion("string_to_lower_case", "lower_case"),
+        case_step,
+        case_map,
+        Mcfunction {
+            name: String::from("string_includes"),
+            body: includes_body,
+        },
+        Mcfunction {
+            name: String::from("string_includes_step"),
+            body: vec![
+                String::from(
+                    "execute if score #search_start smelter_internal > #search_last smelter_internal run return 0",
+                ),
+                String::from(
+                    "execute store result score #search_found smelter_internal run function smelter:string_search_at",
+                ),
+                String::from(
+                    "execute if score #search_found smelter_internal matches 1 run return 1",
+                ),
+                String::from("scoreboard players add #search_start smelter_internal 1"),
+                String::from("return run function smelter:string_includes_step"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("string_starts_with"),
+            body: starts_with_body,
+        },
+        Mcfunction {
+            name: String::from("string_ends_with"),
+            body: ends_with_body,
+        },
+        Mcfunction {
+            name: String::from("string_search_at"),
+            // Returns 1 if the needle is at #search_start
+            body: vec![
+                String::from(
+                    "scoreboard players operation #search_end smelter_internal = #search_start smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #search_end smelter_internal += #needle_length smelter_internal",
+                ),
+                format!(
+                    "execute store result {search}.start int 1 run scoreboard players get #search_start smelter_internal"
+                ),
+                format!(
+                    "execute store result {search}.end int 1 run scoreboard players get #search_end smelter_internal"
+                ),
+                format!("function smelter:string_search_window with {search}"),
+                format!(
+                    "execute store success score #search_differs smelter_internal run data modify {search}.window set from {search}.needle"
+                ),
+                String::from(
+                    "execute if score #search_differs smelter_internal matches 1 run return 0",
+                ),
+                String::from("return 1"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("string_search_window"),
+            body: vec![format!(
+                "$data modify {search}.window set string {search}.haystack $(start) $(end)"
+            )],
+        },
+        Mcfunction {
+            name: String::from("string_slice"),
+            body: slice_body,
+        },
+        Mcfunction {
+            name: String::from("string_slice_range"),
+            body: vec![format!(
+                "$data modify {slice}.result set string {slice}.string $(start) $(end)"
+            )],
+        },
+    ]
+}
+
+fn compile_to_array_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    let args = "storage smelter:smelter internal.to_array_args";
+    let result = "storage smelter:smelter internal.to_array_result";
+    vec![
+        Mcfunction {
+            name: String::from("to_array"),
+            body: vec![
+                format!(
+                    "data modify {result} set value {}",
+                    context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+                ),
+                // Arrays are copied
+                format!(
+                    "execute if data {args}.value.array run return run data modify {result}.array set from {args}.value.array"
+                ),
+                // Strings are split into characters
+                format!(
+                    "execute if data {args}.value.string run data modify {args}.rest set from {args}.value.string"
+                ),
+                format!(
+                    "execute if data {args}.value.string run function smelter:to_array_string_step"
+                ),
+            ],
+        },
+        Mcfunction {
+            name: String::from("to_array_string_step"),
+            // Slicing counts UTF-16 code units, so characters outside the BMP are split in two
+            body: vec![
+                format!("execute if data {args}{{rest: ''}} run return 0"),
+                format!(
+                    "data modify {result}.array append value {}",
+                    context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+                ),
+                format!("data modify {result}.array[-1].string set string {args}.rest 0 1"),
+                format!("data modify {args}.rest set string {args}.rest 1"),
+                String::from("function smelter:to_array_string_step"),
+            ],
+        },
+    ]
+}
+
+fn compile_arithmetic_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    // Unlike `+`, the other arithmetic operators always convert their operands to numbers, so
+    // strings are parsed (`"3" * 2` is 6) and anything unparseable makes the result NaN
+    let result = "storage smelter:smelter internal.arithmetic_result";
+    let nan_snbt = context.snbt(&Nbt::compound([("nan", Nbt::Boolean(true))]));
+    let mut prologue = Vec::new();
+    for (key, score) in [("left", "#arithmetic_left"), ("right", "#arithmetic_right")] {
+        prologue.extend([
+            format!(
+                "data modify storage smelter:smelter internal.to_number_args.value set from storage smelter:smelter internal.arithmetic_args.{key}"
+            ),
+            String::from("function smelter:to_number"),
+            format!(
+                "execute if data storage smelter:smelter internal.to_number_result.nan run return run data modify {result} set value {nan_snbt}"
+            ),
+            format!(
+                "execute store result score {score} smelter_internal run data get storage smelter:smelter internal.to_number_result.number"
+            ),
+            // Whether the operand is exactly zero, which its rounded down score can't tell
+            format!(
+                "execute store success score {score}_zero smelter_internal if data storage smelter:smelter internal.to_number_result{}",
+                context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+            ),
+        ]);
+    }
+    // Scores only hold integers, so fractional operands are rounded down first, and so is the
+    // quotient of a division. The compiler warns about both, and `runtime_docs` describes them
+    let operations: [(&str, Vec<String>); 5] = [
+        (
+            "subtract",
+            vec![String::from(
+                "scoreboard players operation #arithmetic_left smelter_internal -= #arithmetic_right smelter_internal",
+            )],
+        ),
+        (
+            "multiply",
+            vec![String::from(
+                "scoreboard players operation #arithmetic_left smelter_internal *= #arithmetic_right smelter_internal",
+            )],
+        ),
+        (
+            "divide",
+            vec![
+                // There are no infinities, so dividing a nonzero number by zero saturates to the
+                // largest finite double like infinite literals do, and 0 / 0 is NaN
+                format!(
+                    "execute if score #arithmetic_right_zero smelter_internal matches 1 if score #arithmetic_left_zero smelter_internal matches 1 run return run data modify {result} set value {nan_snbt}"
+                ),
+                format!(
+                    "execute if score #arithmetic_right_zero smelter_internal matches 1 if score #arithmetic_left smelter_internal matches ..-1 run return run data modify {result} set value {}",
+                    context.snbt(&Nbt::compound([("number", Nbt::Double(f64::MIN))]))
+                ),
+                format!(
+                    "execute if score #arithmetic_right_zero smelter_internal matches 1 run return run data modify {result} set value {}",
+                    context.snbt(&Nbt::compound([("number", Nbt::Double(f64::MAX))]))
+                ),
+                String::from(
+                    "scoreboard players operation #arithmetic_left smelter_internal /= #arithmetic_right smelter_internal",
+                ),
+            ],
+        ),
+        (
+            "remainder",
+            vec![
+                format!(
+                    "execute if score #arithmetic_right smelter_internal matches 0 run return run data modify {result} set value {nan_snbt}"
+                ),
+                String::from(
+                    "scoreboard players operation #arithmetic_remainder smelter_internal = #arithmetic_left smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #arithmetic_remainder smelter_internal %= #arithmetic_right smelter_internal",
+                ),
+                // `%=` takes the sign of the divisor, but JS takes the sign of the dividend, so a
+                // nonzero remainder is moved back by one divisor when their signs differ
+                String::from(
+                    "execute if score #arithmetic_left smelter_internal matches ..-1 if score #arithmetic_right smelter_internal matches 1.. unless score #arithmetic_remainder smelter_internal matches 0 run scoreboard players operation #arithmetic_remainder smelter_internal -= #arithmetic_right smelter_internal",
+                ),
+                String::from(
+                    "execute if score #arithmetic_left smelter_internal matches 1.. if score #arithmetic_right smelter_internal matches ..-1 unless score #arithmetic_remainder smelter_internal matches 0 run scoreboard players operation #arithmetic_remainder smelter_internal -= #arithmetic_right smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #arithmetic_left smelter_internal = #arithmetic_remainder smelter_internal",
+                ),
+            ],
+        ),
+        (
+            "exponentiate",
+            vec![
+                // A negative exponent would need a fractional result, so it gives NaN instead. A
+                // fractional one has already been rounded down with the other operands
+                format!(
+                    "execute if score #arithmetic_right smelter_internal matches ..-1 run {}",
+                    debug_log(String::from("negative exponents are not supported"))
+                ),
+                format!(
+                    "execute if score #arithmetic_right smelter_internal matches ..-1 run return run data modify {result} set value {nan_snbt}"
+                ),
+                String::from(
+                    "scoreboard players operation #arithmetic_base smelter_internal = #arithmetic_left smelter_internal",
+                ),
+                String::from("scoreboard players set #arithmetic_left smelter_internal 1"),
+                String::from("function smelter:exponentiate_step"),
+            ],
+        ),
+    ];
+    // Multiplies #arithmetic_left by #arithmetic_base to the power of #arithmetic_right by squaring,
+    // one step per bit of the exponent. Scores wrap around, so this overflows just like repeated `*`
+    let exponentiate_step = Mcfunction {
+        name: String::from("exponentiate_step"),
+        body: vec![
+            String::from(
+                "execute if score #arithmetic_right smelter_internal matches ..0 run return 0",
+            ),
+            String::from(
+                "scoreboard players operation #arithmetic_bit smelter_internal = #arithmetic_right smelter_internal",
+            ),
+            String::from(
+                "scoreboard players operation #arithmetic_bit smelter_internal %= #2 smelter_internal",
+            ),
+            String::from(
+                "execute if score #arithmetic_bit smelter_internal matches 1 run scoreboard players operation #arithmetic_left smelter_internal *= #arithmetic_base smelter_internal",
+            ),
+            String::from(
+                "scoreboard players operation #arithmetic_base smelter_internal *= #arithmetic_base smelter_internal",
+            ),
+            String::from(
+                "scoreboard players operation #arithmetic_right smelter_internal /= #2 smelter_internal",
+            ),
+            String::from("function smelter:exponentiate_step"),
+        ],
+    };
+    operations
+        .into_iter()
+        .map(|(name, operation)| {
+            let mut body = vec![debug_log(format!("entering {name}"))];
+            body.extend(prologue.iter().cloned());
+            body.extend(operation);
+            body.extend([
+                format!(
+                    "data modify {result} set value {}",
+                    context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+                ),
+                format!(
+                    "execute store result {result}.number double 1 run scoreboard players get #arithmetic_left smelter_internal"
+                ),
+            ]);
+            Mcfunction {
+                name: String::from(name),
+                body,
+            }
+        })
+        .chain(std::iter::once(exponentiate_step))
+        .collect()
+}
+
+fn compile_parse_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    // Both parsers read the string from `internal.parse_args.string` (and `parse_int` the radix
+    // from #parse_radix), consume it from the front as `rest`, and leave a number or NaN in
+    // `internal.parse_result`
+    let args = "storage smelter:smelter internal.parse_args";
+    let result = "storage smelter:smelter internal.parse_result";
+    let char_filter = |c: &str| context.snbt(&Nbt::compound([("char", Nbt::string(c))]));
+    let nan_result = format!(
+        "data modify {result} set value {}",
+        context.snbt(&Nbt::compound([("nan", Nbt::Boolean(true))]))
+    );
+    let number_result = |score: &str| {
+        [
+            format!(
+                "data modify {result} set value {}",
+                context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+            ),
+            format!(
+                "execute store result {result}.number double 1 run scoreboard players get {score} smelter_internal"
+            ),
+        ]
+    };
+    // Leading whitespace is skipped, and then an optional sign is read into #parse_sign
+    let mut prologue = vec![
+        format!("data modify {args}.rest set from {args}.string"),
+        String::from("function smelter:parse_skip_whitespace"),
+        format!("data modify {args}.trimmed set from {args}.rest"),
+        String::from("scoreboard players set #parse_length smelter_internal 0"),
+        String::from("scoreboard players set #parse_digits smelter_internal 0"),
+        String::from("scoreboard players set #parse_sign smelter_internal 1"),
+        String::from("function smelter:parse_read_char"),
+        format!(
+            "execute if data {args}{} run scoreboard players set #parse_sign smelter_internal -1",
+            char_filter("-")
+        ),
+    ];
+    for sign in ["-", "+"] {
+        prologue.push(format!(
+            "execute if data {args}{} run function smelter:parse_consume_char",
+            char_filter(sign)
+        ));
+    }
+    prologue.push(String::from("function smelter:parse_read_char"));
+
+    let mut parse_int_body = vec![debug_log(String::from("entering parse_int"))];
+    parse_int_body.extend(prologue.clone());
+    parse_int_body.extend([
+        // A `0x` prefix means base 16 when the radix is 16 or left out
+        String::from("scoreboard players set #parse_hex_prefix smelter_internal 0"),
+        format!(
+            "execute if score #parse_radix smelter_internal matches 0 if data {args}{} run scoreboard players set #parse_hex_prefix smelter_internal 1",
+            char_filter("0")
+        ),
+        format!(
+            "execute if score #parse_radix smelter_internal matches 16 if data {args}{} run scoreboard players set #parse_hex_prefix smelter_internal 1",
+            char_filter("0")
+        ),
+        format!(
+            "execute if score #parse_hex_prefix smelter_internal matches 1 run data modify {args}.prefix set string {args}.rest 0 2"
+        ),
+        format!(
+            "execute if score #parse_hex_prefix smelter_internal matches 1 unless data {args}{{prefix: '0x'}} unless data {args}{{prefix: '0X'}} run scoreboard players set #parse_hex_prefix smelter_internal 0"
+        ),
+        format!(
+            "execute if score #parse_hex_prefix smelter_internal matches 1 run data modify {args}.rest set string {args}.rest 2"
+        ),
+        String::from(
+            "execute if score #parse_hex_prefix smelter_internal matches 1 run scoreboard players set #parse_radix smelter_internal 16",
+        ),
+        String::from(
+            "execute if score #parse_radix smelter_internal matches 0 run scoreboard players set #parse_radix smelter_internal 10",
+        ),
+        format!(
+            "execute unless score #parse_radix smelter_internal matches 2..36 run return run {nan_result}"
+        ),
+        // Digits are accumulated into a score, so values outside the 32-bit range wrap around
+        String::from("scoreboard players set #parse_value smelter_internal 0"),
+        String::from("function smelter:parse_int_digits"),
+        format!(
+            "execute if score #parse_digits smelter_internal matches 0 run return run {nan_result}"
+        ),
+        String::from(
+            "scoreboard players operation #parse_value smelter_internal *= #parse_sign smelter_internal",
+        ),
+    ]);
+    parse_int_body.extend(number_result("#parse_value"));
+
+    // The longest prefix of the form `123.45` is parsed as an SNBT double, so unlike in JS
+    // exponents and `Infinity` aren't recognised
+    let mut parse_float_body = vec![debug_log(String::from("entering parse_float"))];
+    parse_float_body.extend(prologue);
+    parse_float_body.extend([
+        String::from("function smelter:parse_float_digits"),
+        String::from("function smelter:parse_read_char"),
+        format!(
+            "execute if data {args}{} run function smelter:parse_consume_char",
+            char_filter(".")
+        ),
+        format!(
+            "execute if data {args}{} run function smelter:parse_float_digits",
+            char_filter(".")
+        ),
+        format!(
+            "execute if score #parse_digits smelter_internal matches 0 run return run {nan_result}"
+        ),
+        format!(
+            "execute store result {args}.length int 1 run scoreboard players get #parse_length smelter_internal"
+        ),
+        format!("function smelter:parse_float_prefix with {args}"),
+        String::from("data remove storage smelter:smelter internal.parse_number_result"),
+        format!("function smelter:parse_number with {args}.number_args"),
+        format!(
+            "data modify {result} set value {}",
+            context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+        ),
+        format!(
+            "data modify {result}.number set from storage smelter:smelter internal.parse_number_result"
+        ),
+    ]);
+
+    let mut skip_whitespace_body = vec![String::from("function smelter:parse_read_char")];
+    skip_whitespace_body.extend([" ", "\t"].map(|whitespace| {
+        format!(
+            "execute if data {args}{} run return run function smelter:parse_skip_whitespace_char",
+            char_filter(whitespace)
+        )
+    }));
+
+    vec![
+        Mcfunction {
+            name: String::from("parse_int"),
+            body: parse_int_body,
+        },
+        Mcfunction {
+            name: String::from("parse_float"),
+            body: parse_float_body,
+        },
+        Mcfunction {
+            name: String::from("parse_skip_whitespace"),
+            body: skip_whitespace_body,
+        },
+        Mcfunction {
+            name: String::from("parse_skip_whitespace_char"),
+            body: vec![
+                format!("data modify {args}.rest set string {args}.rest 1"),
+                String::from("function smelter:parse_skip_whitespace"),
+            ],
+        },
+        // Sets `char` to the first character of `rest` (or '' at the end) and `digit` to its
+        // value, which is left out for anything that isn't a digit or letter
+        Mcfunction {
+            name: String::from("parse_read_char"),
+            body: vec![
+                format!("data remove {args}.digit"),
+                format!("data modify {args}.char set value ''"),
+                format!("execute if data {args}{{rest: ''}} run return 0"),
+                format!("data modify {args}.char set string {args}.rest 0 1"),
+                format!("function smelter:parse_read_digit with {args}"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("parse_read_digit"),
+            body: vec![format!(
+                "$data modify {args}.digit set from storage smelter:smelter internal.digit_values.\"$(char)\""
+            )],
+        },
+        Mcfunction {
+            name: String::from("parse_consume_char"),
+            body: vec![
+                format!("data modify {args}.rest set string {args}.rest 1"),
+                String::from("scoreboard players add #parse_length smelter_internal 1"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("parse_int_digits"),
+            body: vec![
+                String::from("function smelter:parse_read_char"),
+                format!("execute unless data {args}.digit run return 0"),
+                format!(
+                    "execute store result score #parse_digit smelter_internal run data get {args}.digit"
+                ),
+                String::from(
+                    "execute if score #parse_digit smelter_internal >= #parse_radix smelter_internal run return 0",
+                ),
+                String::from(
+                    "scoreboard players operation #parse_value smelter_internal *= #parse_radix smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #parse_value smelter_internal += #parse_digit smelter_internal",
+                ),
+                String::from("scoreboard players add #parse_digits smelter_internal 1"),
+                String::from("function smelter:parse_consume_char"),
+                String::from("function smelter:parse_int_digits"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("parse_float_digits"),
+            body: vec![
+                String::from("function smelter:parse_read_char"),
+                format!("execute unless data {args}.digit run return 0"),
+                format!(
+                    "execute store result score #parse_digit smelter_internal run data get {args}.digit"
+                ),
+                String::from(
+                    "execute if score #parse_digit smelter_internal matches 10.. run return 0",
+                ),
+                String::from("scoreboard players add #parse_digits smelter_internal 1"),
+                String::from("function smelter:parse_consume_char"),
+                String::from("function smelter:parse_float_digits"),
+            ],
+        },
+        // Takes the consumed part of the string after the whitespace, which `parse_number` reads
+        // from `number_args.string`
+        Mcfunction {
+            name: String::from("parse_float_prefix"),
+            body: vec![
+                format!("data modify {args}.number_args set value {{}}"),
+                String::from(
+                    "$data modify storage smelter:smelter internal.parse_args.number_args.string set string storage smelter:smelter internal.parse_args.trimmed 0 $(length)",
+                ),
+            ],
+        },
+    ]
+}
+
+fn compile_json_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    // Both directions walk nested values with a stack of frames in `internal.json_stack`, where
+    // the last frame is the value being visited. `json_stringify` writes into `internal.json_output`
+    // piece by piece with a single-quoted macro, which can't write the escapes JSON needs, so
+    // strings and keys are checked one character at a time and any containing `"`, `'` or `\` make
+    // the whole result undefined rather than invalid JSON. `json_parse` lets the SNBT parser read the text, which
+    // accepts JSON apart from escapes, and then wraps the plain NBT in runtime values
+    let frame = "storage smelter:smelter internal.json_stack[-1]";
+    let parent = "storage smelter:smelter internal.json_stack[-2]";
+    let keys = "storage smelter:smelter internal.object_keys";
+    let undefined_snbt = context.undefined_snbt();
+    let quote_filter = |key: &str, c: &str| context.snbt(&Nbt::compound([(key, Nbt::string(c))]));
+    let append = |piece: &str| {
+        [
+            format!(
+                "data modify storage smelter:smelter internal.json_append_args.piece set value {}",
+                context.snbt(&Nbt::string(piece))
+            ),
+            String::from("function smelter:json_append"),
+        ]
+    };
+    let append_from = |source: &str| {
+        [
+            format!(
+                "data modify storage smelter:smelter internal.json_append_args.piece set from {source}"
+            ),
+            String::from("function smelter:json_append"),
+        ]
+    };
+    // Each frame of a nested value is pushed before visiting it and popped after
+    let push_frame =
+        String::from("data modify storage smelter:smelter internal.json_stack append value {}");
+    let pop_frame = String::from("data remove storage smelter:smelter internal.json_stack[-1]");
+
+    let stringify_args = "storage smelter:smelter internal.json_stringify_args";
+    let stringify_result = "storage smelter:smelter internal.json_stringify_result";
+    let check = "storage smelter:smelter internal.json_check";
+    let check_string = |source: &str| {
+        [
+            format!("data modify {check}.rest set from {source}"),
+            String::from("function smelter:json_check_string"),
+        ]
+    };
+    let mut check_string_body = vec![
+        format!("execute if data {check}{{rest: ''}} run return 0"),
+        format!("data modify {check}.char set string {check}.rest 0 1"),
+        format!("data modify {check}.rest set string {check}.rest 1"),
+    ];
+    for c in ["\"", "'", "\\"] {
+        check_string_body.push(format!(
+            "execute if data {check}{} run return run scoreboard players set #json_unwritable smelter_internal 1",
+            context.snbt(&Nbt::compound([("char", Nbt::string(c))]))
+        ));
+    }
+    check_string_body.push(String::from("function smelter:json_check_string"));
+    let mut stringify_string_body = Vec::from(check_string(&format!("{frame}.value.string")));
+    stringify_string_body.extend(append("\""));
+    stringify_string_body.extend(append_from(&format!("{frame}.value.string")));
+    stringify_string_body.extend(append("\""));
+    let mut stringify_array_body = Vec::from(append("["));
+    stringify_array_body.extend([
+        format!("data modify {frame}.rest set from {frame}.value.array"),
+        format!("data modify {frame}.first set value true"),
+        String::from("function smelter:json_stringify_elements"),
+    ]);
+    stringify_array_body.extend(append("]"));
+    let mut stringify_object_body = Vec::from(append("{"));
+    stringify_object_body.extend([
+        format!("data modify {keys}.object set from {frame}.value.object"),
+        String::from("function smelter:object_keys"),
+        format!("data modify {frame}.keys set from {keys}.result"),
+        format!("data modify {frame}.first set value true"),
+        String::from("function smelter:json_stringify_properties"),
+    ]);
+    stringify_object_body.extend(append("}"));
+    let mut stringify_property_body = vec![
+        format!(
+            "execute unless data {parent}{{first: true}} run function smelter:json_write_comma"
+        ),
+        format!("data modify {parent}.first set value false"),
+    ];
+    stringify_property_body.extend(check_string(&format!("{frame}.key")));
+    stringify_property_body.extend(append("\""));
+    stringify_property_body.extend(append_from(&format!("{frame}.key")));
+    stringify_property_body.extend(append("\":"));
+    stringify_property_body.push(String::from("function smelter:json_stringify_value"));
+    let stringify_value_body = vec![
+        debug_log(String::from("entering json_stringify_value")),
+        // Nothing more is written once the result is going to be undefined
+        String::from("execute if score #json_unwritable smelter_internal matches 1 run return 0"),
+        format!(
+            "execute if data {frame}.value.string run return run function smelter:json_stringify_string"
+        ),
+        format!(
+            "execute if data {frame}.value.array run return run function smelter:json_stringify_array"
+        ),
+        format!(
+            "execute if data {frame}.value.object run return run function smelter:json_stringify_object"
+        ),
+        // NaN, null, and undefined or functions inside arrays are all written as null
+        String::from(
+            "data modify storage smelter:smelter internal.json_append_args.piece set value 'null'",
+        ),
+        format!(
+            "execute if data {frame}.value{} run data modify storage smelter:smelter internal.json_append_args.piece set value 'true'",
+            context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(true))]))
+        ),
+        format!(
+            "execute if data {frame}.value{} run data modify storage smelter:smelter internal.json_append_args.piece set value 'false'",
+            context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(false))]))
+        ),
+        // JS throws a TypeError for bigints, which can't happen here, so they're written as null
+        format!(
+            "execute if data {frame}.value.bigint run {}",
+            debug_log(String::from(
+                "TypeError: BigInt value cannot be serialized in JSON"
+            ))
+        ),
+        format!("execute if data {frame}.value.number run function smelter:json_stringify_number"),
+        String::from("function smelter:json_append"),
+    ];
+
+    let parse_args = "storage smelter:smelter internal.json_parse_args";
+    let parse_result = "storage smelter:smelter internal.json_parse_result";
+
+    vec![
+        Mcfunction {
+            name: String::from("json_stringify"),
+            body: vec![
+                debug_log(String::from("entering json_stringify")),
+                // Undefined and functions have no JSON form, so they stringify to undefined
+                format!(
+                    "execute if data {stringify_args}.value.undefined run return run data modify {stringify_result} set value {undefined_snbt}"
+                ),
+                format!(
+                    "execute if data {stringify_args}.value.function run return run data modify {stringify_result} set value {undefined_snbt}"
+                ),
+                String::from(
+                    "data modify storage smelter:smelter internal.json_output set value ''",
+                ),
+                String::from(
+                    "data modify storage smelter:smelter internal.json_stack set value [{}]",
+                ),
+                format!("data modify {frame}.value set from {stringify_args}.value"),
+                String::from("scoreboard players set #json_unwritable smelter_internal 0"),
+                String::from("function smelter:json_stringify_value"),
+                format!(
+                    "execute if score #json_unwritable smelter_internal matches 1 run {}",
+                    debug_log(String::from(
+                        "JSON.stringify cannot write strings containing quotes or backslashes"
+                    ))
+                ),
+                format!(
+                    "execute if score #json_unwritable smelter_internal matches 1 run return run data modify {stringify_result} set value {undefined_snbt}"
+                ),
+                format!(
+                    "data modify {stringify_result} set value {}",
+                    context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+                ),
+                format!(
+                    "data modify {stringify_result}.string set from storage smelter:smelter internal.json_output"
+                ),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_stringify_value"),
+            body: stringify_value_body,
+        },
+        Mcfunction {
+            name: String::from("json_stringify_number"),
+            body: vec![
+                format!(
+                    "data modify storage smelter:smelter internal.to_string_args.value set from {frame}.value"
+                ),
+                String::from("function smelter:to_string"),
+                String::from(
+                    "data modify storage smelter:smelter internal.json_append_args.piece set from storage smelter:smelter internal.to_string_result",
+                ),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_stringify_string"),
+            body: stringify_string_body,
+        },
+        Mcfunction {
+            name: String::from("json_check_string"),
+            body: check_string_body,
+        },
+        Mcfunction {
+            name: String::from("json_stringify_array"),
+            body: stringify_array_body,
+        },
+        Mcfunction {
+            name: String::from("json_stringify_elements"),
+            body: vec![
+                format!("execute unless data {frame}.rest[0] run return 0"),
+                format!(
+                    "execute unless data {frame}{{first: true}} run function smelter:json_write_comma"
+                ),
+                format!("data modify {frame}.first set value false"),
+                push_frame.clone(),
+                format!("data modify {frame}.value set from {parent}.rest[0]"),
+                String::from("function smelter:json_stringify_value"),
+                pop_frame.clone(),
+                format!("data remove {frame}.rest[0]"),
+                String::from("function smelter:json_stringify_elements"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_stringify_object"),
+            body: stringify_object_body,
+        },
+        Mcfunction {
+            name: String::from("json_stringify_properties"),
+            body: vec![
+                format!("execute unless data {frame}.keys[0] run return 0"),
+                push_frame.clone(),
+                format!("data modify {frame}.key set from {parent}.keys[0]"),
+                String::from(
+                    "function smelter:json_read_property with storage smelter:smelter internal.json_stack[-1]",
+                ),
+                // Properties with no JSON form are left out (getters aren't called, so
+                // properties with only a getter are too)
+                format!(
+                    "execute if data {frame}.value unless data {frame}.value.undefined unless data {frame}.value.function run function smelter:json_stringify_property"
+                ),
+                pop_frame.clone(),
+                format!("data remove {frame}.keys[0]"),
+                String::from("function smelter:json_stringify_properties"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_stringify_property"),
+            body: stringify_property_body,
+        },
+        Mcfunction {
+            name: String::from("json_read_property"),
+            body: vec![format!(
+                "$data modify {frame}.value set from {parent}.value.object.\"$(key)\""
+            )],
+        },
+        Mcfunction {
+            name: String::from("json_write_comma"),
+            body: Vec::from(append(",")),
+        },
+        Mcfunction {
+            name: String::from("json_append"),
+            body: vec![
+                String::from(
+                    "data modify storage smelter:smelter internal.json_append_args.output set from storage smelter:smelter internal.json_output",
+                ),
+                String::from(
+                    "function smelter:json_concat with storage smelter:smelter internal.json_append_args",
+                ),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_concat"),
+            // Single quotes, since the output is full of double quotes
+            body: vec![String::from(
+                "$data modify storage smelter:smelter internal.json_output set value '$(output)$(piece)'",
+            )],
+        },
+        Mcfunction {
+            name: String::from("json_parse"),
+            body: vec![
+                debug_log(String::from("entering json_parse")),
+                String::from("data remove storage smelter:smelter internal.json_raw"),
+                format!("function smelter:json_parse_text with {parse_args}"),
+                // Text that fails to parse leaves nothing behind, where JS would throw
+                format!(
+                    "execute unless data storage smelter:smelter internal.json_raw run {}",
+                    debug_log(String::from("SyntaxError: JSON.parse got invalid JSON"))
+                ),
+                format!(
+                    "execute unless data storage smelter:smelter internal.json_raw run return run data modify {parse_result} set value {undefined_snbt}"
+                ),
+                String::from(
+                    "data modify storage smelter:smelter internal.json_stack set value [{}]",
+                ),
+                format!(
+                    "data modify {frame}.raw set from storage smelter:smelter internal.json_raw"
+                ),
+                String::from("function smelter:json_convert"),
+                format!("data modify {parse_result} set from {frame}.value"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_parse_text"),
+            body: vec![String::from(
+                "$data modify storage smelter:smelter internal.json_raw set value $(string)",
+            )],
+        },
+        // Wraps the plain NBT in the last frame's `raw` into a runtime value in its `value`
+        Mcfunction {
+            name: String::from("json_convert"),
+            body: vec![
+                format!(
+                    "execute if data {frame}.raw{{}} run return run function smelter:json_convert_object"
+                ),
+                format!(
+                    "execute if data {frame}.raw[0] run return run function smelter:json_convert_array"
+                ),
+                // An empty list is the only thing that's unchanged by setting it over `[]`
+                format!("data modify {frame}.compare set value []"),
+                format!(
+                    "execute store success score #json_changed smelter_internal run data modify {frame}.compare set from {frame}.raw"
+                ),
+                format!(
+                    "execute if score #json_changed smelter_internal matches 0 run return run data modify {frame}.value set value {}",
+                    context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+                ),
+                // Likewise a string is unchanged by its own string form
+                format!("data modify {frame}.compare set string {frame}.raw"),
+                format!(
+                    "execute store success score #json_changed smelter_internal run data modify {frame}.compare set from {frame}.raw"
+                ),
+                format!(
+                    "execute if score #json_changed smelter_internal matches 0 run return run function smelter:json_convert_string"
+                ),
+                // `true` and `false` are read as bytes
+                format!("data modify {frame}.compare set value 1b"),
+                format!(
+                    "execute store success score #json_changed smelter_internal run data modify {frame}.compare set from {frame}.raw"
+                ),
+                format!(
+                    "execute if score #json_changed smelter_internal matches 0 run return run data modify {frame}.value set value {}",
+                    context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(true))]))
+                ),
+                format!("data modify {frame}.compare set value 0b"),
+                format!(
+                    "execute store success score #json_changed smelter_internal run data modify {frame}.compare set from {frame}.raw"
+                ),
+                format!(
+                    "execute if score #json_changed smelter_internal matches 0 run return run data modify {frame}.value set value {}",
+                    context.snbt(&Nbt::compound([("boolean", Nbt::Boolean(false))]))
+                ),
+                // Anything else is a number. Ints are stored as doubles through a score, which
+                // is exact for them, and other numbers are copied as they are
+                format!(
+                    "data modify {frame}.value set value {}",
+                    context.snbt(&Nbt::compound([("number", Nbt::Double(0.0))]))
+                ),
+                format!(
+                    "execute store result {frame}.value.number double 1 run data get {frame}.raw"
+                ),
+                format!("execute store result {frame}.compare int 1 run data get {frame}.raw"),
+                format!(
+                    "execute store success score #json_changed smelter_internal run data modify {frame}.compare set from {frame}.raw"
+                ),
+                format!(
+                    "execute if score #json_changed smelter_internal matches 1 run data modify {frame}.value.number set from {frame}.raw"
+                ),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_convert_string"),
+            // SNBT reads `null` as a string, so the string 'null' can't be told apart from it
+            body: vec![
+                format!(
+                    "execute if data {frame}{} run return run data modify {frame}.value set value {}",
+                    quote_filter("raw", "null"),
+                    context.snbt(&Nbt::compound([("null", Nbt::Boolean(true))]))
+                ),
+                format!(
+                    "data modify {frame}.value set value {}",
+                    context.snbt(&Nbt::compound([("string", Nbt::string(""))]))
+                ),
+                format!("data modify {frame}.value.string set from {frame}.raw"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_convert_array"),
+            body: vec![
+                format!(
+                    "data modify {frame}.value set value {}",
+                    context.snbt(&Nbt::compound([("array", Nbt::List(Vec::new()))]))
+                ),
+                format!("data modify {frame}.rest set from {frame}.raw"),
+                String::from("function smelter:json_convert_elements"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_convert_elements"),
+            body: vec![
+                format!("execute unless data {frame}.rest[0] run return 0"),
+                push_frame.clone(),
+                format!("data modify {frame}.raw set from {parent}.rest[0]"),
+                String::from("function smelter:json_convert"),
+                format!("data modify {parent}.value.array append from {frame}.value"),
+                pop_frame.clone(),
+                format!("data remove {frame}.rest[0]"),
+                String::from("function smelter:json_convert_elements"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_convert_object"),
+            body: vec![
+                format!(
+                    "data modify {frame}.value set value {}",
+                    context.snbt(&Nbt::compound([("object", Nbt::Compound(Vec::new()))]))
+                ),
+                format!("data modify {keys}.object set from {frame}.raw"),
+                String::from("function smelter:object_keys"),
+                format!("data modify {frame}.keys set from {keys}.result"),
+                String::from("function smelter:json_convert_properties"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_convert_properties"),
+            body: vec![
+                format!("execute unless data {frame}.keys[0] run return 0"),
+                push_frame.clone(),
+                format!("data modify {frame}.key set from {parent}.keys[0]"),
+                String::from(
+                    "function smelter:json_read_raw_property with storage smelter:smelter internal.json_stack[-1]",
+                ),
+                String::from("function smelter:json_convert"),
+                String::from(
+                    "function smelter:json_write_property with storage smelter:smelter internal.json_stack[-1]",
+                ),
+                pop_frame,
+                format!("data remove {frame}.keys[0]"),
+                String::from("function smelter:json_convert_properties"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("json_read_raw_property"),
+            body: vec![format!(
+                "$data modify {frame}.raw set from {parent}.raw.\"$(key)\""
+            )],
+        },
+        Mcfunction {
+            name: String::from("json_write_property"),
+            body: vec![format!(
+                "$data modify {parent}.value.object.\"$(key)\" set from {frame}.value"
+            )],
+        },
+    ]
+}
+
+fn compile_object_keys_functions(context: &CompileContext) -> Vec<Mcfunction> {
+    // There's no command that lists the keys of a compound, so `object_keys` reads them from the
+    // compound's SNBT form: `internal.object_keys.object` is turned into `text`, which is scanned
+    // one character at a time from `rest`, and every key at the top level is appended to `result`.
+    // #keys_position counts the characters read, #keys_depth the brackets open inside the
+    // compound, and a key runs from #keys_key_start up to the `:` that follows it
+    let keys = "storage smelter:smelter internal.object_keys";
+    let char_filter = |c: &str| context.snbt(&Nbt::compound([("char", Nbt::string(c))]));
+    let first_filter = |c: &str| context.snbt(&Nbt::compound([("first", Nbt::string(c))]));
+    let mut step_body = vec![
+        format!("execute if data {keys}{{rest: ''}} run return 0"),
+        format!("data modify {keys}.char set string {keys}.rest 0 1"),
+        format!("data modify {keys}.rest set string {keys}.rest 1"),
+        String::from("scoreboard players add #keys_position smelter_internal 1"),
+        String::from(
+            "execute if score #keys_in_quote smelter_internal matches 1 run return run function smelter:object_keys_quoted_char",
+        ),
+    ];
+    for quote in ["\"", "'"] {
+        step_body.push(format!(
+            "execute if data {keys}{} run function smelter:object_keys_open_quote",
+            char_filter(quote)
+        ));
+    }
+    step_body.push(format!(
+        "execute if score #keys_depth smelter_internal matches 0 if score #keys_in_key smelter_internal matches 1 if data {keys}{} run function smelter:object_keys_end_key",
+        char_filter(":")
+    ));
+    for (bracket, operation) in [("{", "add"), ("[", "add"), ("}", "remove"), ("]", "remove")] {
+        step_body.push(format!(
+            "execute if data {keys}{} run scoreboard players {operation} #keys_depth smelter_internal 1",
+            char_filter(bracket)
+        ));
+    }
+    step_body.extend([
+        format!(
+            "execute if score #keys_depth smelter_internal matches 0 if data {keys}{} run function smelter:object_keys_start_key",
+            char_filter(",")
+        ),
+        String::from("function smelter:object_keys_step"),
+    ]);
+    let mut take_body = vec![
+        format!("$data modify {keys}.key set string {keys}.text $(start) $(end)"),
+        // Keys that need quotes keep them in SNBT (along with any escapes inside)
+        format!("data modify {keys}.first set string {keys}.key 0 1"),
+    ];
+    for quote in ["\"", "'"] {
+        take_body.push(format!(
+            "execute if data {keys}{} run data modify {keys}.key set string {keys}.key 1 -1",
+            first_filter(quote)
+        ));
+    }
+    take_body.push(format!("data modify {keys}.result append from {keys}.key"));
+
+    vec![
+        Mcfunction {
+            name: String::from("object_keys"),
+            body: vec![
+                debug_log(String::from("entering object_keys")),
+                format!("data modify {keys}.result set value []"),
+                format!("data modify {keys}.text set string {keys}.object"),
+                format!("execute if data {keys}{{text: '{{}}'}} run return 0"),
+                // The opening brace is skipped, so the first key starts straight away
+                format!("data modify {keys}.rest set string {keys}.text 1"),
+                String::from("scoreboard players set #keys_position smelter_internal 1"),
+                String::from("scoreboard players set #keys_key_start smelter_internal 1"),
+                String::from("scoreboard players set #keys_depth smelter_internal 0"),
+                String::from("scoreboard players set #keys_in_key smelter_internal 1"),
+                String::from("scoreboard players set #keys_in_quote smelter_internal 0"),
+                String::from("scoreboard players set #keys_escaped smelter_internal 0"),
+                String::from("function smelter:object_keys_step"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("object_keys_step"),
+            body: step_body,
+        },
+        // Inside a quoted string only the closing quote matters, unless it's escaped
+        Mcfunction {
+            name: String::from("object_keys_quoted_char"),
+            body: vec![
+                String::from(
+                    "execute if score #keys_escaped smelter_internal matches 1 run return run function smelter:object_keys_escaped_char",
+                ),
+                format!(
+                    "execute if data {keys}{} run scoreboard players set #keys_escaped smelter_internal 1",
+                    char_filter("\\")
+                ),
+                format!("data modify {keys}.compare set from {keys}.quote"),
+                format!(
+                    "execute store success score #keys_changed smelter_internal run data modify {keys}.compare set from {keys}.char"
+                ),
+                String::from(
+                    "execute if score #keys_changed smelter_internal matches 0 run scoreboard players set #keys_in_quote smelter_internal 0",
+                ),
+                String::from("function smelter:object_keys_step"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("object_keys_escaped_char"),
+            body: vec![
+                String::from("scoreboard players set #keys_escaped smelter_internal 0"),
+                String::from("function smelter:object_keys_step"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("object_keys_open_quote"),
+            body: vec![
+                String::from("scoreboard players set #keys_in_quote smelter_internal 1"),
+                format!("data modify {keys}.quote set from {keys}.char"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("object_keys_start_key"),
+            body: vec![
+                String::from("scoreboard players set #keys_in_key smelter_internal 1"),
+                String::from(
+                    "scoreboard players operation #keys_key_start smelter_internal = #keys_position smelter_internal",
+                ),
+            ],
+        },
+        // The `:` has just been read, so the key ends one character before #keys_position
+        Mcfunction {
+            name: String::from("object_keys_end_key"),
+            body: vec![
+                String::from("scoreboard players set #keys_in_key smelter_internal 0"),
+                format!(
+                    "execute store result {keys}.start int 1 run scoreboard players get #keys_key_start smelter_internal"
+                ),
+                format!(
+                    "execute store result {keys}.end int 1 run scoreboard players remove #keys_position smelter_internal 1"
+                ),
+                String::from("scoreboard players add #keys_position smelter_internal 1"),
+                format!("function smelter:object_keys_take with {keys}"),
+            ],
+        },
+        Mcfunction {
+            name: String::from("object_keys_take"),
+            body: take_body,
+        },
+    ]
+}
+
+fn compile_bitwise_functions() -> Vec<Mcfunction> {
+    // The operands are read from #bitwise_left and #bitwise_right, and the result is left in
+    // #bitwise_result
+    let shifts = [("shift_left", "*="), ("shift_right", "/=")]
+        .into_iter()
+        .flat_map(|(name, operation)| {
+            // Shifting by one is multiplying or (flooring) dividing by 2, repeated for the shift
+            // count modulo 32
+            [
+                Mcfunction {
+                    name: String::from(name),
+                    body: vec![
+                        String::from(
+                            "scoreboard players operation #bitwise_right smelter_internal %= #32 smelter_internal",
+                        ),
+                        String::from(
+                            "scoreboard players operation #bitwise_result smelter_internal = #bitwise_left smelter_internal",
+                        ),
+                        format!("function smelter:{name}_step"),
+                    ],
+                },
+                Mcfunction {
+                    name: format!("{name}_step"),
+                    body: vec![
+                        String::from(
+                            "execute if score #bitwise_right smelter_internal matches ..0 run return 0",
+                        ),
+                        format!(
+                            "scoreboard players operation #bitwise_result smelter_internal {operation} #2 smelter_internal"
+                        ),
+                        String::from("scoreboard players remove #bitwise_right smelter_internal 1"),
+                        format!("function smelter:{name}_step"),
+                    ],
+                },
+            ]
+        });
+    let logical_operations = [
+        (
+            "bitwise_and",
+            vec![
+                "scoreboard players operation #bitwise_left_bit smelter_internal *= #bitwise_right_bit smelter_internal",
+            ],
+        ),
+        (
+            "bitwise_or",
+            vec![
+                "scoreboard players operation #bitwise_left_bit smelter_internal += #bitwise_right_bit smelter_internal",
+                "execute if score #bitwise_left_bit smelter_internal matches 2 run scoreboard players set #bitwise_left_bit smelter_internal 1",
+            ],
+        ),
+        (
+            "bitwise_xor",
+            vec![
+                "scoreboard players operation #bitwise_left_bit smelter_internal += #bitwise_right_bit smelter_internal",
+                "scoreboard players operation #bitwise_left_bit smelter_internal %= #2 smelter_internal",
+            ],
+        ),
+    ];
+    let logicals = logical_operations
+        .into_iter()
+        .flat_map(|(name, combine_bits)| {
+            // Combine the operands one bit at a time from the lowest. Flooring division and modulo
+            // by 2 walk two's complement bits correctly for negative numbers too, and the place
+            // value wraps around to the sign bit on the last step
+            let mut step_body = vec![
+                String::from("execute if score #bitwise_count smelter_internal matches ..0 run return 0"),
+                String::from(
+                    "scoreboard players operation #bitwise_left_bit smelter_internal = #bitwise_left smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #bitwise_left_bit smelter_internal %= #2 smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #bitwise_right_bit smelter_internal = #bitwise_right smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #bitwise_right_bit smelter_internal %= #2 smelter_internal",
+                ),
+            ];
+            step_body.extend(combine_bits.into_iter().map(String::from));
+            step_body.extend([
+                String::from(
+                    "scoreboard players operation #bitwise_left_bit smelter_internal *= #bitwise_place smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #bitwise_result smelter_internal += #bitwise_left_bit smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #bitwise_left smelter_internal /= #2 smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #bitwise_right smelter_internal /= #2 smelter_internal",
+                ),
+                String::from(
+                    "scoreboard players operation #bitwise_place smelter_internal *= #2 smelter_internal",
+                ),
+                String::from("scoreboard players remove #bitwise_count smelter_internal 1"),
+                format!("function smelter:{name}_step"),
+            ]);
+            [
+                Mcfunction {
+                    name: String::from(name),
+                    body: vec![
+                        String::from("scoreboard players set #bitwise_result smelter_internal 0"),
+                        String::from("scoreboard players set #bitwise_place smelter_internal 1"),
+                        String::from("scoreboard players set #bitwise_count smelter_internal 32"),
+                        format!("function smelter:{name}_step"),
+                    ],
+                },
+                Mcfunction {
+                    name: format!("{name}_step"),
+                    body: step_body,
+                },
+            ]
+        });
+    shifts.chain(logicals).collect()
+}
+
+fn reduce_compiled(v: Vec<(Vec<String>, Vec<Mcfunction>)>) -> (Vec<String>, Vec<Mcfunction>) {
+    v.into_iter().fold(
+        (Vec::new(), Vec::new()),
+        |(mut commands_acc, mut subfunctions_acc), (commands, subfunctions)| {
+            commands_acc.extend(commands);
+            subfunctions_acc.extend(subfunctions);
+            (commands_acc, subfunctions_acc)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_options() -> CompileOptions {
+        CompileOptions {
+            compact_nbt: false,
+            explain: false,
+            emit_metadata: false,
+            timestamp: None,
+            core_function_overrides: Vec::new(),
+            trace: false,
+            function_names: NameStrategy::Readable,
+            emit_uninstall: false,
+            reproducible: false,
+        }
+    }
+
+    fn compile_js(source: &str) -> CompiledPack {
+        compile(source, SourceType::mjs(), test_options())
+    }
+
+    fn compile_js_with(source: &str, options: CompileOptions) -> CompiledPack {
+        compile(source, SourceType::mjs(), options)
+    }
+
+    fn function_body(pack: &CompiledPack, name: &str) -> String {
+        pack.functions
+            .iter()
+            .find(|function| function.name == name)
+            .unwrap_or_else(|| panic!("no function named `{name}`"))
+            .body
+            .join("\n")
+    }
+
+    #[test]
+    fn compiled_pack_holds_functions_and_meta() {
+        let pack = compile_js("let x = 1;");
+        assert!(pack.parse_errors.is_empty());
+        assert!(pack.semantic_errors.is_empty());
+        assert!(pack.warnings.is_empty());
+        for name in ["main", "initialize", "resolve", "invoke", "to_string"] {
+            assert!(pack.functions.iter().any(|function| function.name == name));
+        }
+        assert!(function_body(&pack, "main").contains("current_environment.bindings.x"));
+        assert!(pack.pack_mcmeta.contains("\"min_format\":[94,1]"));
+        assert!(pack.pack_mcmeta.contains("\"max_format\":[94,1]"));
+    }
+
+    #[test]
+    fn numeric_literals_emit_plain_doubles() {
+        let pack = compile_js("let a = -5; let b = 1e3; let c = 0.5;");
+        let main = function_body(&pack, "main");
+        for value in ["{number: -5d}", "{number: 1000d}", "{number: 0.5d}"] {
+            assert!(main.contains(value), "missing {value}");
+        }
+        for command in main.lines().filter(|line| line.contains(" set value ")) {
+            let (_, value) = command.split_once(" set value ").unwrap();
+            assert_eq!(nbt::check_snbt(value), Ok(()), "{command}");
+        }
+    }
+
+    #[test]
+    fn explain_annotates_node_kinds() {
+        let options = CompileOptions {
+            explain: true,
+            ..test_options()
+        };
+        let pack = compile_js_with("let x = 1;", options);
+        let main = function_body(&pack, "main");
+        assert!(main.contains("# compiled from VariableDeclaration at offset 0"));
+        assert!(main.contains("# compiled from NumericLiteral at offset 8"));
+        assert!(!compile_js("let x = 1;").functions.iter().any(|function| {
+            function
+                .body
+                .iter()
+                .any(|line| line.starts_with("# compiled from"))
+        }));
+    }
+
+    #[test]
+    fn compiled_pack_reports_parse_errors() {
+        let pack = compile_js("let = ;");
+        assert!(!pack.parse_errors.is_empty());
+    }
+
+    #[test]
+    fn array_is_array_checks_for_an_array_value() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let main = function_body(&compile_js("Array.isArray([]);"), "main");
+        assert!(main.contains(&format!("{evaluations}.expr_14 set value {{array: []}}")));
+        assert!(main.contains(&format!(
+            "execute if data {evaluations}.expr_14.array run data modify {evaluations}.expr_0 set value {{boolean: true}}"
+        )));
+        let main = function_body(&compile_js("Array.isArray(\"x\");"), "main");
+        assert!(main.contains(&format!("{evaluations}.expr_14 set value {{string: 'x'}}")));
+        assert!(main.contains(&format!(
+            "data modify {evaluations}.expr_0 set value {{boolean: false}}"
+        )));
+    }
+
+    #[test]
+    fn call_arguments_are_evaluated_and_appended_in_order() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let main = function_body(&compile_js("f(1, 2, 3);"), "main");
+        let mut previous = 0;
+        for (offset, value) in [(2, 1), (5, 2), (8, 3)] {
+            for command in [
+                format!("{evaluations}.expr_{offset} set value {{number: {value}d}}"),
+                format!("{evaluations}.expr_0_arguments append from {evaluations}.expr_{offset}"),
+            ] {
+                let position = main.find(&command).expect(&command);
+                assert!(position > previous, "{command} is out of order");
+                previous = position;
+            }
+        }
+    }
+
+    #[test]
+    fn console_log_emits_tellraw_of_stringified_arguments() {
+        let main = function_body(&compile_js("let x = 1; console.log(\"hi\", x);"), "main");
+        let appends = main
+            .matches("internal.console_text append from storage smelter:smelter internal.to_string_result")
+            .count();
+        assert_eq!(appends, 2);
+        assert!(main.contains("{string: 'hi'}"));
+        assert!(main.contains(
+            "tellraw @a {type:'nbt',source:'storage',storage:'smelter:smelter',nbt:'internal.console_text[]',separator:' '}"
+        ));
+        assert!(!main.contains("function smelter:invoke"));
+    }
+
+    #[test]
+    fn command_macro_names_are_sanitized_without_collisions() {
+        let pack = compile_js(
+            "function a() { \"smelter My:Say\"; } function b() { \"smelter my_say\"; } function c() { \"smelter my$say\"; }",
+        );
+        let macro_names = pack
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .filter(|name| name.contains("_macro"))
+            .collect::<Vec<&str>>();
+        assert_eq!(macro_names.len(), 3);
+        assert!(macro_names.contains(&"my_say_macro"));
+        for name in macro_names {
+            assert!(name.chars().all(|c| matches!(
+                c,
+                'a'..='z' | '0'..='9' | '/' | '.' | '_' | '-'
+            )));
+        }
+        assert_eq!(sanitize_resource_path("foo_bar"), "foo_bar");
+        assert_ne!(
+            sanitize_resource_path("fooBar"),
+            sanitize_resource_path("foobar")
+        );
+    }
+
+    #[test]
+    fn return_in_if_unwinds_the_enclosing_function() {
+        let pack = compile_js("function f(x){ if (x) return 1; return 2; }");
+        let consequent = function_body(&pack, "if_consequent_15");
+        let flag =
+            "data modify storage smelter:smelter current_environment.returning set value true";
+        assert!(consequent.find(flag).unwrap() < consequent.find("return 1").unwrap());
+        let body = function_body(&pack, "f_0");
+        let call = body.find("run function smelter:if_consequent_15").unwrap();
+        let propagate = body.find(PROPAGATE_RETURN).unwrap();
+        let second_return = body.find("{number: 2d}").unwrap();
+        assert!(call < propagate && propagate < second_return);
+    }
+
+    #[test]
+    fn compact_nbt_matches_spaced_output_without_spaces() {
+        let source = "let x = [1, \"a, b\"];";
+        let spaced = function_body(&compile_js(source), "main");
+        let options = CompileOptions {
+            compact_nbt: true,
+            ..test_options()
+        };
+        let compact = function_body(&compile_js_with(source, options), "main");
+        assert!(spaced.contains("{number: 1d}"));
+        assert!(compact.contains("{number:1d}"));
+        assert!(compact.contains("{string:'a, b'}"));
+        assert_eq!(spaced.lines().count(), compact.lines().count());
+    }
+
+    #[test]
+    fn conversion_calls_use_runtime_helpers() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let main = function_body(&compile_js("Boolean(0);"), "main");
+        let steps = [
+            format!("{evaluations}.expr_8 set value {{number: 0d}}"),
+            String::from(
+                "data modify storage smelter:smelter internal.is_truthy_args set value {}",
+            ),
+            format!("internal.is_truthy_args.value set from {evaluations}.expr_8"),
+            format!("data modify {evaluations}.expr_0 set value {{boolean: false}}"),
+            format!(
+                "execute store result {evaluations}.expr_0.boolean byte 1 run function smelter:is_truthy"
+            ),
+        ];
+        let positions = steps.map(|step| main.find(&step).expect(&step));
+        assert!(positions.is_sorted());
+
+        let main = function_body(&compile_js("Number(\"5\");"), "main");
+        assert!(main.contains(&format!("{evaluations}.expr_7 set value {{string: '5'}}")));
+        assert!(main.contains(&format!(
+            "internal.to_number_args.value set from {evaluations}.expr_7"
+        )));
+        assert!(main.contains(&format!(
+            "data modify {evaluations}.expr_0 set from storage smelter:smelter internal.to_number_result"
+        )));
+
+        let main = function_body(&compile_js("String(5);"), "main");
+        assert!(main.contains(&format!(
+            "internal.to_string_args.value set from {evaluations}.expr_7"
+        )));
+        assert!(main.contains(&format!(
+            "data modify {evaluations}.expr_0.string set from storage smelter:smelter internal.to_string_result"
+        )));
+    }
+
+    #[test]
+    fn bitwise_operators_call_scoreboard_helpers() {
+        let pack = compile_js("let a = 3, b = 5; a << 2; a & b;");
+        let main = function_body(&pack, "main");
+        assert!(main.contains("function smelter:shift_left"));
+        assert!(main.contains("function smelter:bitwise_and"));
+        assert!(pack.warnings.is_empty());
+        // Shifting left multiplies by 2 once per step
+        let step = function_body(&pack, "shift_left_step");
+        assert!(step.contains(
+            "scoreboard players operation #bitwise_result smelter_internal *= #2 smelter_internal"
+        ));
+        let step = function_body(&pack, "bitwise_and_step");
+        assert!(step.contains(
+            "scoreboard players operation #bitwise_left_bit smelter_internal *= #bitwise_right_bit smelter_internal"
+        ));
+        // Negative fractions are truncated toward zero, so `-1.5 | 0` is -1 like in JS
+        for score in ["#bitwise_left", "#bitwise_right"] {
+            assert!(main.contains(&format!(
+                "execute if score {score} smelter_internal matches ..-1 if score #bitwise_fraction smelter_internal matches -1 run scoreboard players add {score} smelter_internal 1"
+            )));
+        }
+    }
+
+    #[test]
+    fn warnings_serialize_to_json_with_code_and_span() {
+        let pack = compile_js("class A {}");
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning");
+        };
+        let json = diagnostic_to_json(warning);
+        assert_eq!(json["severity"], "warning");
+        assert_eq!(json["code"], "smelter(unsupported-class)");
+        assert_eq!(json["labels"][0]["start"], 0);
+        assert_eq!(json["labels"][0]["end"], 10);
+    }
+
+    #[test]
+    fn exports_bind_declarations_and_report_re_exports() {
+        let pack = compile_js("export default function () {} export const x = 1;");
+        let main = function_body(&pack, "main");
+        assert!(main.contains("current_environment.bindings.default set value"));
+        assert!(main.contains("current_environment.bindings.x"));
+        assert!(pack.warnings.is_empty());
+        let pack = compile_js("export { y } from './m';");
+        assert_eq!(pack.warnings.len(), 1);
+        assert!(pack.warnings[0].message.contains("re-exports"));
+    }
+
+    #[test]
+    fn for_loop_runs_init_then_recursive_test_body_update() {
+        let pack = compile_js("let n = 3; for (let i = 0; i < n; i++) {}");
+        let main = function_body(&pack, "main");
+        let init = main.find("current_environment.bindings.i").unwrap();
+        assert!(init < main.find("function smelter:for_11").unwrap());
+        let loop_body = function_body(&pack, "for_11");
+        let steps = [
+            "evaluations.expr_27_truthy byte 1 run function smelter:is_truthy",
+            "current_environment.evaluations{expr_27_truthy: false} run return 0",
+            "function smelter:for_body_11",
+            "current_environment.evaluations.expr_34",
+        ]
+        .map(|step| loop_body.find(step).expect(step));
+        assert!(steps.is_sorted());
+        assert!(loop_body.ends_with("function smelter:for_11"));
+        assert!(
+            pack.functions
+                .iter()
+                .any(|function| function.name == "for_body_11")
+        );
+    }
+
+    #[test]
+    fn array_iteration_methods_dispatch_on_the_receiver_type() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js("let a = [1]; a.map(x => x);");
+        let main = function_body(&pack, "main");
+        assert!(main.contains(&format!(
+            "execute if data {evaluations}.expr_13_receiver.array run function smelter:builtin_method_15"
+        )));
+        assert!(main.contains(&format!(
+            "execute unless data {evaluations}.expr_13_receiver.array run function smelter:method_15"
+        )));
+        // The callback is evaluated once, before either kind of call
+        assert_eq!(main.matches("expr_13_arguments append").count(), 1);
+        let builtin = function_body(&pack, "builtin_method_15");
+        assert!(builtin.contains(&format!(
+            "execute unless data {evaluations}.expr_13_arguments[0].function run return 0"
+        )));
+        assert!(builtin.contains("function smelter:array_map_15"));
+        assert!(function_body(&pack, "array_map_15").contains("function smelter:invoke"));
+        // Anything else calls its own `map` property with `this` bound
+        let method = function_body(&pack, "method_15");
+        assert!(method.contains(&format!(
+            "{evaluations}.expr_13 set from {evaluations}.expr_13_object.object.map"
+        )));
+        assert!(method.contains(&format!(
+            "current_this set from {evaluations}.expr_13_object"
+        )));
+        assert!(method.contains("function smelter:invoke"));
+    }
+
+    #[test]
+    fn metadata_header_has_versions_and_optional_timestamp() {
+        let options = CompileOptions {
+            emit_metadata: true,
+            timestamp: Some(1700000000),
+            ..test_options()
+        };
+        let main = function_body(&compile_js_with("let x = 1;", options), "main");
+        assert!(main.starts_with(&format!(
+            "# Generated by smelter {} ({})\n# Pack format {}.{}\n# Generated at Unix time 1700000000",
+            env!("CARGO_PKG_VERSION"),
+            env!("CARGO_PKG_NAME"),
+            PACK_FORMAT.0,
+            PACK_FORMAT.1
+        )));
+        // `--no-timestamp` compiles with no timestamp, for reproducible output
+        let options = CompileOptions {
+            emit_metadata: true,
+            ..test_options()
+        };
+        let main = function_body(&compile_js_with("let x = 1;", options), "main");
+        assert!(main.starts_with("# Generated by smelter"));
+        assert!(!main.contains("Unix time"));
+    }
+
+    #[test]
+    fn addition_concatenates_when_either_operand_is_a_string() {
+        let pack = compile_js("\"a\" + \"b\"; \"a\" + 1; 1 + 2;");
+        let main = function_body(&pack, "main");
+        assert_eq!(main.matches("function smelter:add\n").count(), 3);
+        for operands in [
+            "{string: 'a'}",
+            "{string: 'b'}",
+            "{number: 1d}",
+            "{number: 2d}",
+        ] {
+            assert!(main.contains(operands), "missing {operands}");
+        }
+        // Strings branch to concatenation before anything is added as a number
+        let add = function_body(&pack, "add");
+        let concat = add
+            .find("internal.add_args.right.string run return run function smelter:add_strings")
+            .unwrap();
+        let numeric = add
+            .find("#add_left smelter_internal += #add_right")
+            .unwrap();
+        assert!(concat < numeric);
+        let add_strings = function_body(&pack, "add_strings");
+        assert!(add_strings.contains("function smelter:concat\n"));
+        assert!(add_strings.contains("internal.add_result.string set from"));
+    }
+
+    #[test]
+    fn failed_concatenation_gives_undefined() {
+        let pack = compile_js("let a = 'x' + 0.5;\nlet b = 0.5 + 1;\nlet c = 1;\nc += 0.25;\n");
+        // Only the numeric additions are warned about
+        let offsets: Vec<usize> = pack
+            .warnings
+            .iter()
+            .map(|warning| warning.labels.as_ref().unwrap()[0].offset())
+            .collect();
+        assert_eq!(offsets, [27, 52]);
+
+        let concat = function_body(&pack, "concat");
+        let steps = [
+            "data remove storage smelter:smelter internal.concat_result",
+            "function smelter:concat_double_quoted with",
+            "function smelter:concat_single_quoted with",
+            "return fail",
+        ];
+        let positions: Vec<usize> = steps
+            .iter()
+            .map(|step| concat.find(step).unwrap())
+            .collect();
+        assert!(positions.is_sorted());
+        assert!(
+            function_body(&pack, "concat_single_quoted").contains("set value '$(left)$(right)'")
+        );
+
+        let add_strings = function_body(&pack, "add_strings");
+        let undefined = add_strings
+            .find("unless data storage smelter:smelter internal.concat_result run return run data modify storage smelter:smelter internal.add_result set value {undefined: true}")
+            .unwrap();
+        assert!(
+            undefined
+                < add_strings
+                    .find("internal.add_result.string set from")
+                    .unwrap()
+        );
+        assert!(runtime_docs().contains("gives undefined"));
+    }
+
+    #[test]
+    fn default_and_rest_parameters_bind_in_order() {
+        let pack = compile_js("function f(a, b = 2, ...rest) {} f(1); f(1, 3); f(1, 3, 4, 5);");
+        let body = function_body(&pack, "f_0");
+        let steps = [
+            "bindings.a set from storage smelter:smelter current_arguments[0]",
+            "data remove storage smelter:smelter current_arguments[0]",
+            "evaluations.default_14 set from storage smelter:smelter current_arguments[0]",
+            "evaluations.default_14.undefined run function smelter:default_14",
+            "bindings.b set from storage smelter:smelter current_environment.evaluations.default_14",
+            "bindings.rest.array set from storage smelter:smelter current_arguments",
+        ];
+        let mut previous = 0;
+        for step in steps {
+            previous += body[previous..].find(step).expect(step);
+        }
+        // The default is only evaluated when the argument is missing or undefined
+        assert!(function_body(&pack, "default_14").contains("{number: 2d}"));
+        // Fewer, as many and more arguments than named parameters
+        let main = function_body(&pack, "main");
+        for (call, count) in [("expr_33", 1), ("expr_39", 2), ("expr_48", 4)] {
+            let appends = format!("evaluations.{call}_arguments append from");
+            assert_eq!(main.matches(&appends).count(), count, "{call}");
+        }
+    }
+
+    #[test]
+    fn logical_assignments_only_evaluate_the_right_side_when_assigning() {
+        let pack = compile_js("let x = 1; x ??= 2; x ||= 3; x &&= 4;");
+        let main = function_body(&pack, "main");
+        assert!(main.contains(
+            "execute if score #nullish smelter_internal matches 1 run function smelter:logical_assign_11"
+        ));
+        assert!(main.contains(
+            "current_environment.evaluations{expr_20_truthy: false} run function smelter:logical_assign_20"
+        ));
+        assert!(main.contains(
+            "current_environment.evaluations{expr_29_truthy: true} run function smelter:logical_assign_29"
+        ));
+        for (offset, value) in [(11, 2), (20, 3), (29, 4)] {
+            let right = format!("{{number: {value}d}}");
+            assert!(!main.contains(&right));
+            let assign = function_body(&pack, &format!("logical_assign_{offset}"));
+            assert!(assign.contains(&right));
+            assert!(assign.contains("current_environment.bindings.x set from"));
+        }
+    }
+
+    #[test]
+    fn compound_assignments_use_the_operator_helpers() {
+        let pack = compile_js("let x = 1; x -= 2; x **= 3; x &= 4; x >>>= 5;");
+        let main = function_body(&pack, "main");
+        for helper in ["subtract", "exponentiate", "bitwise_and"] {
+            assert!(
+                main.contains(&format!("function smelter:{helper}\n")),
+                "{helper}"
+            );
+        }
+        assert!(main.contains(
+            "internal.arithmetic_args.left set from storage smelter:smelter current_environment.evaluations.expr_11_target"
+        ));
+        let updates =
+            "run data modify storage smelter:smelter current_environment.bindings.x set from";
+        assert_eq!(main.matches(updates).count(), 3);
+        // `>>>` has no helper, so only it is reported
+        assert_eq!(pack.warnings.len(), 1);
+        assert!(pack.warnings[0].message.contains(">>>="));
+    }
+
+    #[test]
+    fn output_checks_catch_wrong_directories_and_foreign_packs() {
+        let root = std::env::temp_dir().join(format!("smelter_layout_{}", std::process::id()));
+        let world = root.join("world");
+        let pack = world.join("datapacks").join("pack");
+        std::fs::create_dir_all(&pack).unwrap();
+        std::fs::write(world.join("level.dat"), "").unwrap();
+        let [warning] = &check_output_layout(&world)[..] else {
+            panic!("expected one warning");
+        };
+        assert!(warning.contains("looks like a world folder"));
+        assert_eq!(check_output_layout(&world.join("datapacks")).len(), 1);
+        assert!(check_output_layout(&pack).is_empty());
+
+        assert_eq!(check_existing_pack(&pack), None);
+        std::fs::write(pack.join("pack.mcmeta"), compile_js("").pack_mcmeta).unwrap();
+        assert_eq!(check_existing_pack(&pack), None);
+        std::fs::write(
+            pack.join("pack.mcmeta"),
+            "{\"pack\":{\"description\":\"x\"}}",
+        )
+        .unwrap();
+        assert!(check_existing_pack(&pack).is_some());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn unsupported_statements_and_operators_are_reported() {
+        let pack = compile_js("let x = 1; while (x) {} -x; `${x}`;");
+        let messages: Vec<String> = pack
+            .warnings
+            .iter()
+            .map(|warning| warning.message.to_string())
+            .collect();
+        assert_eq!(messages.len(), 3, "{messages:?}");
+        assert!(messages[0].contains("WhileStatement"), "{messages:?}");
+        assert!(messages[1].contains("operator `-`"), "{messages:?}");
+        assert!(messages[2].contains("TemplateLiteral"), "{messages:?}");
+
+        // Statements with nothing to run aren't worth a warning
+        assert!(compile_js("; debugger;").warnings.is_empty());
+    }
+
+    #[test]
+    fn member_access_invokes_recorded_getters() {
+        let pack = compile_js("let obj = { get x() { return 1; } }; obj.x;");
+        assert!(pack.warnings.is_empty());
+        let main = function_body(&pack, "main");
+        assert!(main.contains("evaluations.expr_10.getters.x set value {function: "));
+        assert!(main.contains(
+            "execute if data storage smelter:smelter current_environment.evaluations.expr_37_object.getters.x run function smelter:getter_41"
+        ));
+        let getter = function_body(&pack, "getter_41");
+        assert!(getter.contains("function smelter:invoke with storage smelter:smelter current_environment.evaluations.expr_37_getter.function"));
+        assert!(getter.ends_with(
+            "data modify storage smelter:smelter current_environment.evaluations.expr_37 set from storage smelter:smelter current_return_value"
+        ));
+        // The getter's body returns the constant
+        assert!(
+            pack.functions
+                .iter()
+                .any(|function| function.name.starts_with("anon_func_")
+                    && function.body.join("\n").contains("{number: 1d}"))
+        );
+    }
+
+    #[test]
+    fn array_from_and_spread_convert_through_to_array() {
+        let pack = compile_js(r#"let a = Array.from([1, 2]); let b = [..."ab"]; Array.from(5);"#);
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        for expected in [
+            // `Array.from([1, 2])` copies the array literal
+            format!(
+                "data modify storage smelter:smelter internal.to_array_args.value set from {evaluations}.expr_19\nfunction smelter:to_array\ndata modify {evaluations}.expr_8 set from storage smelter:smelter internal.to_array_result"
+            ),
+            // `[..."ab"]` appends each character of the string
+            format!("{evaluations}.expr_40 set value {{string: 'ab'}}"),
+            format!(
+                "data modify storage smelter:smelter internal.to_array_args.value set from {evaluations}.expr_40\nfunction smelter:to_array\ndata modify {evaluations}.expr_36.array append from storage smelter:smelter internal.to_array_result.array[]"
+            ),
+        ] {
+            assert!(main.contains(&expected), "missing {expected}");
+        }
+        let to_array = function_body(&pack, "to_array");
+        assert!(to_array.contains("internal.to_array_result.array set from storage smelter:smelter internal.to_array_args.value.array"));
+        assert!(to_array.contains("function smelter:to_array_string_step"));
+        let step = function_body(&pack, "to_array_string_step");
+        assert!(step.contains(
+            "data modify storage smelter:smelter internal.to_array_result.array[-1].string set string storage smelter:smelter internal.to_array_args.rest 0 1"
+        ));
+
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning, got {:?}", pack.warnings);
+        };
+        assert!(warning.message.contains("but this is a number"));
+        assert_eq!(warning.labels.as_ref().unwrap()[0].offset(), 58);
+    }
+
+    #[test]
+    fn fail_on_warning_errors_after_writing_the_pack() {
+        let root = std::env::temp_dir().join(format!("smelter_fail_{}", std::process::id()));
+        let pack = root.join("datapacks").join("pack");
+        std::fs::create_dir_all(&pack).unwrap();
+        let source = root.join("warns.js");
+        std::fs::write(&source, "class A {}").unwrap();
+        let arguments = |extra: &[&str]| {
+            let mut arguments = vec![
+                String::from("smelter"),
+                source.display().to_string(),
+                String::from("--out-dir"),
+                pack.display().to_string(),
+            ];
+            arguments.extend(extra.iter().map(|argument| String::from(*argument)));
+            CliArguments::parse_from(arguments)
+        };
+        assert!(run(arguments(&[])).is_ok());
+        let error = run(arguments(&["--fail-on-warning"])).unwrap_err();
+        assert_eq!(error.to_string(), "1 warning(s) were produced");
+        assert!(pack.join("pack.mcmeta").exists());
+
+        std::fs::write(&source, "let x = 1;").unwrap();
+        assert!(run(arguments(&["--fail-on-warning"])).is_ok());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn tagged_templates_are_reported() {
+        let pack = compile_js("let tag = (s) => s; let x = tag`a${1}b`;");
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning, got {:?}", pack.warnings);
+        };
+        assert_eq!(warning.message, "Not supported: tagged template literals");
+        assert_eq!(warning.labels.as_ref().unwrap()[0].offset(), 28);
+    }
+
+    #[test]
+    fn core_function_overrides_replace_generated_bodies() {
+        let custom = vec![String::from("say custom resolve")];
+        let pack = compile_js_with(
+            "let x = 1;",
+            CompileOptions {
+                core_function_overrides: vec![
+                    (String::from("resolve"), custom.clone()),
+                    (String::from("not_core"), Vec::new()),
+                ],
+                ..test_options()
+            },
+        );
+        let resolve = pack
+            .functions
+            .iter()
+            .find(|function| function.name == "resolve")
+            .unwrap();
+        assert_eq!(resolve.body, custom);
+        assert!(
+            !function_body(&compile_js("let x = 1;"), "resolve").contains("say custom resolve")
+        );
+        // The rest are still generated
+        assert!(!function_body(&pack, "invoke").is_empty());
+
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning, got {:?}", pack.warnings);
+        };
+        assert!(warning.message.contains("`not_core` isn't a core function"));
+        assert!(warning.help.as_ref().unwrap().contains("`resolve`"));
+    }
+
+    #[test]
+    fn command_wrappers_return_the_command_result() {
+        let pack = compile_js("function count() { \"smelter say\"; } let n = count(\"hi\");");
+        let wrapper = function_body(&pack, "count_0");
+        assert!(wrapper.contains(
+            "data modify storage smelter:smelter current_return_value set value {number: 0d}"
+        ));
+        assert!(wrapper.contains(
+            "execute store result storage smelter:smelter current_return_value.number double 1 run function smelter:say_macro with storage smelter:smelter internal.command_args"
+        ));
+        assert!(
+            wrapper.ends_with(
+                "return run data get storage smelter:smelter current_return_value.number"
+            )
+        );
+        assert!(function_body(&pack, "say_macro").ends_with("$return run say $(tail)"));
+    }
+
+    #[test]
+    fn reproducible_compiles_are_byte_identical() {
+        fn read_tree(
+            root: &std::path::Path,
+            directory: &std::path::Path,
+            files: &mut Vec<(std::path::PathBuf, Vec<u8>)>,
+        ) {
+            for entry in std::fs::read_dir(directory).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    read_tree(root, &path, files);
+                } else {
+                    let contents = std::fs::read(&path).unwrap();
+                    files.push((path.strip_prefix(root).unwrap().to_path_buf(), contents));
+                }
+            }
+        }
+
+        let root =
+            std::env::temp_dir().join(format!("smelter_reproducible_{}", std::process::id()));
+        let source = root.join("source.js");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(
+            &source,
+            "function f(a) { if (a) { return [a]; } return { b: a }; } let x = f(1); console.log(x);",
+        )
+        .unwrap();
+        let mut outputs = Vec::new();
+        for name in ["first", "second"] {
+            let out_dir = root.join("datapacks").join(name);
+            run(CliArguments::parse_from([
+                "smelter",
+                source.to_str().unwrap(),
+                "--out-dir",
+                out_dir.to_str().unwrap(),
+                "--reproducible",
+                "--emit-metadata",
+                "--emit-runtime-docs",
+                "--emit-function-list",
+            ]))
+            .unwrap();
+            let mut files = Vec::new();
+            read_tree(&out_dir, &out_dir, &mut files);
+            files.sort();
+            outputs.push(files);
+        }
+        assert!(outputs[0].len() > 10);
+        assert_eq!(outputs[0], outputs[1]);
+
+        // Functions are sorted by `compile` itself, not just when written
+        let options = || CompileOptions {
+            reproducible: true,
+            emit_metadata: true,
+            timestamp: Some(1700000000),
+            ..test_options()
+        };
+        let pack = compile_js_with("function b() {} function a() {}", options());
+        let names = pack
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect::<Vec<&str>>();
+        assert!(names.is_sorted(), "{names:?}");
+        assert!(!function_body(&pack, "main").contains("1700000000"));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn only_clean_removes_stale_functions() {
+        let root = std::env::temp_dir().join(format!("smelter_clean_{}", std::process::id()));
+        let pack = compile_js("let x = 1;");
+        let stale = root.join("data/smelter/function/stale.mcfunction");
+        std::fs::create_dir_all(stale.parent().unwrap()).unwrap();
+        std::fs::write(&stale, "say stale").unwrap();
+        write_pack(&pack, &root, false).unwrap();
+        assert!(stale.exists());
+        write_pack(&pack, &root, true).unwrap();
+        assert!(!stale.exists());
+        assert!(root.join("data/smelter/function/main.mcfunction").exists());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn functions_using_arguments_snapshot_them() {
+        let pack = compile_js("function f(a) { return arguments[0]; } f(5);");
+        let body = function_body(&pack, "f_0");
+        let snapshot = body
+            .find("current_environment.bindings.arguments.array set from storage smelter:smelter current_arguments")
+            .expect("missing the snapshot");
+        let parameter = body
+            .find("current_environment.bindings.a ")
+            .expect("missing the parameter binding");
+        assert!(snapshot < parameter);
+        assert!(body.contains("current_environment.bindings.arguments set value {array: []}"));
+        assert!(body.contains(
+            "set from storage smelter:smelter current_environment.evaluations.expr_23_object.array[0]"
+        ));
+
+        // Functions that don't use it, and arrow functions, don't bind their own
+        let pack = compile_js("function g(a) { return (() => a)(); } g(1);");
+        assert!(
+            !pack
+                .functions
+                .iter()
+                .any(|function| function.body.join("\n").contains("bindings.arguments"))
+        );
+    }
+
+    #[test]
+    fn command_wrappers_with_statements_warn() {
+        let pack = compile_js("function f() { \"smelter say\"; let x = 1; x; }");
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning, got {:?}", pack.warnings);
+        };
+        assert!(warning.message.contains("wraps the `say` command"));
+        let label = &warning.labels.as_ref().unwrap()[0];
+        assert_eq!((label.offset(), label.len()), (30, 13));
+        assert!(!function_body(&pack, "f_0").contains("bindings.x"));
+
+        assert!(
+            compile_js("function f() { \"smelter say\"; }")
+                .warnings
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn numeric_separators_and_bigints_become_doubles_and_longs() {
+        let pack = compile_js(
+            "let a = 1_000; let b = 10n; let c = -9223372036854775808n; let d = 99999999999999999999n;",
+        );
+        let main = function_body(&pack, "main");
+        for value in [
+            "{number: 1000d}",
+            "{bigint: 10l}",
+            "{bigint: -9223372036854775808l}",
+            "{bigint: 9223372036854775807l}",
+        ] {
+            assert!(main.contains(value), "missing {value}");
+        }
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning, got {:?}", pack.warnings);
+        };
+        assert!(warning.message.contains("doesn't fit in 64 bits"));
+    }
+
+    #[test]
+    fn equality_operators_pick_strict_or_loose_helpers() {
+        let pack = compile_js(
+            "let a = 1 === \"1\"; let b = 1 == \"1\"; let c = null == undefined; let d = 1 != 2;",
+        );
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        for (expression_id, helper, negated) in [
+            ("expr_8", "strict_equals", false),
+            ("expr_27", "loose_equals", false),
+            ("expr_45", "loose_equals", false),
+            ("expr_72", "loose_equals", true),
+        ] {
+            let expected = format!(
+                "execute store result score #equals smelter_internal run function smelter:{helper}\ndata modify {evaluations}.{expression_id} set value {{boolean: {negated}}}\nexecute if score #equals smelter_internal matches 1 run data modify {evaluations}.{expression_id} set value {{boolean: {}}}",
+                !negated
+            );
+            assert!(main.contains(&expected), "missing {expected}");
+        }
+
+        // `1 === "1"` is false since the stored values differ in shape
+        let strict = function_body(&pack, "strict_equals");
+        assert!(strict.contains(
+            "execute store success score #equals smelter_internal run data modify storage smelter:smelter internal.equals_args.compare set from storage smelter:smelter internal.equals_args.right"
+        ));
+        let loose = function_body(&pack, "loose_equals");
+        // `null == undefined` is true
+        let null_to_undefined = loose
+            .find("execute if data storage smelter:smelter internal.equals_args.left.null run data modify storage smelter:smelter internal.equals_args.left set value {undefined: true}")
+            .expect("missing the null conversion");
+        let both_undefined = loose
+            .find("execute if data storage smelter:smelter internal.equals_args.left.undefined if data storage smelter:smelter internal.equals_args.right.undefined run return 1")
+            .expect("missing the undefined comparison");
+        assert!(null_to_undefined < both_undefined);
+        // `1 == "1"` is true since mixed primitives compare as numbers
+        assert!(loose.contains(
+            "data modify storage smelter:smelter internal.to_number_args.value set from storage smelter:smelter internal.equals_args.right"
+        ));
+        assert!(loose.ends_with("return run function smelter:strict_equals"));
+    }
+
+    #[test]
+    fn switch_matches_string_cases_strictly() {
+        let pack = compile_js(
+            "let s = \"b\"; let r = 0; switch (s) { case \"a\": r = 1; break; case \"b\": r = 2; break; default: r = 3; }",
+        );
+        assert!(pack.warnings.is_empty());
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        // Each case is compared with the discriminant through `strict_equals` in its own
+        // subfunction, which only runs until a case has matched (or else the default is recorded)
+        for (test_name, test_id, index) in [
+            ("switch_test_37", "expr_42", 0),
+            ("switch_test_61", "expr_66", 1),
+        ] {
+            assert!(main.contains(&format!(
+                "execute unless data {evaluations}.switch_24_case run function smelter:{test_name}"
+            )));
+            let expected = format!(
+                "data modify storage smelter:smelter internal.equals_args.left set from {evaluations}.expr_32\ndata modify storage smelter:smelter internal.equals_args.right set from {evaluations}.{test_id}\nexecute store result score #equals smelter_internal run function smelter:strict_equals\nexecute if score #equals smelter_internal matches 1 run data modify {evaluations}.switch_24_case set value {index}"
+            );
+            let test = function_body(&pack, test_name);
+            assert!(test.contains(&expected), "missing {expected}");
+            assert!(!main.contains(&format!("{evaluations}.{test_id} set value")));
+        }
+        assert!(main.contains(&format!(
+            "execute unless data {evaluations}.switch_24_case run data modify {evaluations}.switch_24_case set value 2"
+        )));
+        assert!(main.contains("function smelter:switch_24"));
+
+        // The matched case runs, and falls through until a `break`
+        let switch = function_body(&pack, "switch_24");
+        for (case_name, index) in [
+            ("switch_case_37", 0),
+            ("switch_case_61", 1),
+            ("switch_case_85", 2),
+        ] {
+            assert!(switch.contains(&format!(
+                "execute if data {evaluations}{{switch_24_case: {index}}} run data modify {evaluations}.switch_24_falling set value true\nexecute if data {evaluations}.switch_24_falling run function smelter:{case_name}"
+            )));
+        }
+        assert!(switch.contains(CONSUME_BREAK));
+        let case_b = function_body(&pack, "switch_case_61");
+        assert!(case_b.contains("{number: 2d}"));
+        assert!(case_b.ends_with("return 1"));
+    }
+
+    #[test]
+    fn spread_works_in_new_array_and_is_reported_in_super_calls() {
+        let pack = compile_js(
+            "let xs = [1, 2]; let a = new Array(...xs); class A extends Object { constructor() { super(...xs); } }",
+        );
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        assert!(main.contains(&format!(
+            "data modify {evaluations}.expr_25 set value {{array: []}}"
+        )));
+        assert!(main.contains(&format!(
+            "data modify storage smelter:smelter internal.to_array_args.value set from {evaluations}.expr_38\nfunction smelter:to_array\ndata modify {evaluations}.expr_25.array append from storage smelter:smelter internal.to_array_result.array[]"
+        )));
+
+        let super_warning = pack
+            .warnings
+            .iter()
+            .find(|warning| warning.message.contains("`super` call"))
+            .expect("missing the super call warning");
+        assert_eq!(super_warning.labels.as_ref().unwrap()[0].offset(), 84);
+        assert!(
+            pack.warnings
+                .iter()
+                .all(|warning| !warning.message.contains("new"))
+        );
+    }
+
+    #[test]
+    fn generator_functions_are_reported_instead_of_compiled() {
+        let pack = compile_js("function* g() { yield 1; } g();");
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning, got {:?}", pack.warnings);
+        };
+        assert_eq!(warning.message, "Not supported: generator functions");
+        let label = &warning.labels.as_ref().unwrap()[0];
+        assert_eq!((label.offset(), label.len()), (0, 13));
+        assert!(!pack.functions.iter().any(|function| function.name == "g_0"));
+    }
+
+    #[test]
+    fn computed_property_keys_are_set_through_a_macro() {
+        let pack = compile_js("let k = \"a\"; let o = { [k]: 1 };");
+        assert!(pack.warnings.is_empty());
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let expected = format!(
+            "data modify storage smelter:smelter internal.to_string_args.value set from {evaluations}.expr_24\nfunction smelter:to_string\ndata modify storage smelter:smelter internal.set_property_args set value {{target: 'expr_21'}}\ndata modify storage smelter:smelter internal.set_property_args.key set from storage smelter:smelter internal.to_string_result\ndata modify storage smelter:smelter internal.set_property_args.value set from {evaluations}.expr_28\nfunction smelter:set_property with storage smelter:smelter internal.set_property_args"
+        );
+        assert!(main.contains(&expected), "missing {expected}");
+        assert!(function_body(&pack, "set_property").starts_with(
+            "$data modify storage smelter:smelter current_environment.evaluations.$(target).object.\"$(key)\" set from storage smelter:smelter internal.set_property_args.value"
+        ));
+    }
+
+    #[test]
+    fn async_functions_and_await_are_reported_once() {
+        let pack = compile_js("async function f() { await g(); } let x = 1; await x;");
+        let warnings = pack
+            .warnings
+            .iter()
+            .map(|warning| {
+                let label = &warning.labels.as_ref().unwrap()[0];
+                (warning.message.to_string(), label.offset(), label.len())
+            })
+            .collect::<Vec<(String, usize, usize)>>();
+        assert_eq!(
+            warnings,
+            vec![
+                (String::from("Not supported: async functions"), 0, 18),
+                (String::from("Not supported: `await`"), 21, 5),
+                (String::from("Not supported: `await`"), 45, 5),
+            ]
+        );
+        assert!(!pack.functions.iter().any(|function| function.name == "f_0"));
+    }
+
+    #[test]
+    fn nested_destructuring_binds_each_name_with_defaults() {
+        let pack = compile_js("let obj = { a: { b: 2 } }; const { a: { b, c = 1 } } = obj;");
+        assert!(pack.warnings.is_empty());
+        let main = function_body(&pack, "main");
+        let source = "storage smelter:smelter current_environment.evaluations.expr_55";
+        assert!(main.contains(&format!(
+            "execute if data {source}.object.a.object.b run data modify storage smelter:smelter current_environment.bindings.b set from {source}.object.a.object.b"
+        )));
+        // `c` is missing, so its default is evaluated into a slot that it's bound from
+        let slot = "storage smelter:smelter current_environment.evaluations.default_43";
+        assert!(main.contains(&format!(
+            "execute if data {source}.object.a.object.c run data modify {slot} set from {source}.object.a.object.c\nexecute if data {slot}.undefined run function smelter:default_43"
+        )));
+        assert!(main.contains(&format!(
+            "execute if data {slot} run data modify storage smelter:smelter current_environment.bindings.c set from {slot}"
+        )));
+        let default = function_body(&pack, "default_43");
+        assert!(default.contains("{number: 1d}"));
+        assert!(default.ends_with(&format!(
+            "data modify {slot} set from storage smelter:smelter current_environment.evaluations.expr_47"
+        )));
+    }
+
+    #[test]
+    fn rest_patterns_exclude_bound_keys_and_capture_the_tail() {
+        let pack = compile_js(
+            "let obj = { a: 1, b: 2 }; let arr = [1, 2, 3]; const { a, ...rest } = obj; const [x, ...tail] = arr;",
+        );
+        assert!(pack.warnings.is_empty());
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        assert!(main.contains(&format!(
+            "execute if data {evaluations}.expr_70.object run data modify {evaluations}.rest_58.object set from {evaluations}.expr_70.object\ndata remove {evaluations}.rest_58.object.a"
+        )));
+        assert!(main.contains(&format!(
+            "data modify storage smelter:smelter current_environment.bindings.rest set from {evaluations}.rest_58"
+        )));
+        assert!(main.contains(&format!(
+            "execute if data {evaluations}.expr_96.array run data modify {evaluations}.rest_85.array set from {evaluations}.expr_96.array\ndata remove {evaluations}.rest_85.array[0]\n"
+        )));
+        assert!(!main.contains(&format!("{evaluations}.rest_85.array[0]\ndata remove")));
+        assert!(main.contains(&format!(
+            "data modify storage smelter:smelter current_environment.bindings.tail set from {evaluations}.rest_85"
+        )));
+    }
+
+    #[test]
+    fn string_methods_dispatch_on_the_receiver_type() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js(
+            "let s = \"abc\"; let u = s.toUpperCase(); let i = s.includes(\"x\"); s.padStart(2);",
+        );
+        let main = function_body(&pack, "main");
+        // Subfunctions are named after the method name's position
+        for (start, method_start) in [(23, 25), (48, 50), (65, 67)] {
+            assert!(main.contains(&format!(
+                "execute if data {evaluations}.expr_{start}_receiver.string run function smelter:builtin_method_{method_start}"
+            )));
+            assert!(main.contains(&format!(
+                "execute unless data {evaluations}.expr_{start}_receiver.string run function smelter:method_{method_start}"
+            )));
+        }
+        assert!(main.contains(&format!(
+            "data modify {evaluations}.expr_48_arguments append from {evaluations}.expr_59"
+        )));
+
+        let upper = function_body(&pack, "builtin_method_25");
+        assert!(upper.contains(&format!(
+            "data modify storage smelter:smelter internal.string_method_args.string set from {evaluations}.expr_23_receiver.string"
+        )));
+        assert!(upper.contains("function smelter:string_to_upper_case"));
+        let includes = function_body(&pack, "builtin_method_50");
+        assert!(includes.contains(&format!(
+            "data modify storage smelter:smelter internal.string_method_args.argument0 set from {evaluations}.expr_48_arguments[0]"
+        )));
+        assert!(includes.contains("function smelter:string_includes"));
+        for helper in ["string_to_upper_case", "string_includes"] {
+            assert!(!function_body(&pack, helper).is_empty());
+        }
+        // Other receivers call their own property, like any method call
+        assert!(
+            function_body(&pack, "method_25")
+                .contains(&format!("{evaluations}.expr_23_object.object.toUpperCase"))
+        );
+
+        // Unsupported string methods are reported, and give undefined on strings at runtime
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning, got {:?}", pack.warnings);
+        };
+        assert!(warning.message.contains("string method `padStart`"));
+        assert!(function_body(&pack, "builtin_method_67").contains(&format!(
+            "data modify {evaluations}.expr_65 set value {{undefined: true}}"
+        )));
+        assert!(function_body(&pack, "method_67").contains("function smelter:invoke"));
+    }
+
+    #[test]
+    fn chained_method_calls_get_their_own_subfunctions() {
+        let pack = compile_js("let s = \"abc\"; s.slice(1).toUpperCase();");
+        let names = pack
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect::<Vec<&str>>();
+        for name in [
+            "builtin_method_17",
+            "method_17",
+            "builtin_method_26",
+            "method_26",
+        ] {
+            assert_eq!(
+                names.iter().filter(|other| **other == name).count(),
+                1,
+                "{name}"
+            );
+        }
+        assert!(
+            function_body(&pack, "builtin_method_17").contains("function smelter:string_slice")
+        );
+        assert!(
+            function_body(&pack, "builtin_method_26")
+                .contains("function smelter:string_to_upper_case")
+        );
+    }
+
+    #[test]
+    fn member_access_on_a_missing_property_gives_undefined() {
+        let pack = compile_js("let a = {}; let x = a.b.c;");
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        // `a.b` is missing, so it's undefined
+        assert!(main.contains(&format!(
+            "execute unless data {evaluations}.expr_20 run data modify {evaluations}.expr_20 set value {{undefined: true}}"
+        )));
+        // and reading `.c` of it gives undefined rather than indexing into it
+        let check = format!(
+            "execute if data {evaluations}.expr_20_object.undefined run data modify {evaluations}.expr_20 set value {{undefined: true}}"
+        );
+        let read = format!(
+            "execute unless data {evaluations}.expr_20 run data modify {evaluations}.expr_20 set from {evaluations}.expr_20_object.object.c"
+        );
+        let check_position = main.find(&check).expect("missing the nullish check");
+        assert!(check_position < main.find(&read).expect("missing the read"));
+        assert!(main.contains("TypeError: cannot read .c of undefined"));
+        assert!(main.contains(&format!(
+            "data modify storage smelter:smelter current_environment.bindings.x set from {evaluations}.expr_20"
+        )));
+
+        // Each access in the chain has its own getter subfunction
+        let mut getters = pack
+            .functions
+            .iter()
+            .filter(|function| function.name.starts_with("getter_"))
+            .map(|function| function.name.as_str())
+            .collect::<Vec<&str>>();
+        getters.sort();
+        assert_eq!(getters, vec!["getter_22", "getter_24"]);
+    }
+
+    #[test]
+    fn conditional_defaults_compile_their_branches() {
+        let pack = compile_js("let c = true; function f(x = c ? 1 : 2) { return x; } f();");
+        assert!(pack.warnings.is_empty());
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        // The default only applies when the argument is missing or undefined
+        let body = function_body(&pack, "f_14");
+        assert!(body.contains(&format!(
+            "execute if data {evaluations}.default_25.undefined run function smelter:default_25"
+        )));
+        assert!(body.contains(&format!(
+            "data modify storage smelter:smelter current_environment.bindings.x set from {evaluations}.default_25"
+        )));
+        // and evaluates the whole conditional, with a subfunction for each branch
+        let default = function_body(&pack, "default_25");
+        for (branch, truthy) in [
+            ("conditional_consequent_29", true),
+            ("conditional_alternate_29", false),
+        ] {
+            assert!(default.contains(&format!(
+                "execute if data {evaluations}{{expr_29_truthy: {truthy}}} run function smelter:{branch}"
+            )));
+        }
+        assert!(default.ends_with(&format!(
+            "data modify {evaluations}.default_25 set from {evaluations}.expr_29"
+        )));
+        assert!(
+            function_body(&pack, "conditional_consequent_29")
+                .contains(&format!("{evaluations}.expr_33 set value {{number: 1d}}"))
+        );
+        assert!(
+            function_body(&pack, "conditional_alternate_29")
+                .contains(&format!("{evaluations}.expr_37 set value {{number: 2d}}"))
+        );
+    }
+
+    #[test]
+    fn in_checks_array_bounds_and_object_keys() {
+        let pack = compile_js("let a = 2 in [1, 2, 3]; let b = 5 in [1, 2, 3];");
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        for (key_id, array_id) in [("expr_8", "expr_13"), ("expr_32", "expr_37")] {
+            assert!(main.contains(&format!(
+                "data modify storage smelter:smelter internal.in_args.key set from {evaluations}.{key_id}\ndata modify storage smelter:smelter internal.in_args.value set from {evaluations}.{array_id}\nexecute store result score #in smelter_internal run function smelter:has_property\ndata modify {evaluations}.{key_id} set value {{boolean: false}}\nexecute if score #in smelter_internal matches 1 run data modify {evaluations}.{key_id} set value {{boolean: true}}"
+            )));
+        }
+        // Arrays check that the index is within bounds (so `2 in [1, 2, 3]` is true and `5 in [1,
+        // 2, 3]` is false), and objects that they have the key
+        assert!(function_body(&pack, "has_property").starts_with(
+            "execute if data storage smelter:smelter internal.in_args.value.array run return run function smelter:has_index"
+        ));
+        let has_index = function_body(&pack, "has_index");
+        assert!(has_index.contains(
+            "execute if score #in_index smelter_internal >= #in_length smelter_internal run return 0"
+        ));
+        assert!(has_index.ends_with("return 1"));
+        assert!(function_body(&pack, "has_key").starts_with(
+            "$execute if data storage smelter:smelter internal.in_args.value.object.\"$(key_string)\" run return 1"
+        ));
+    }
+
+    #[test]
+    fn top_level_environment_is_the_global_one() {
+        let pack = compile_js(
+            "let x = 1; function f() { function g() { y = 2; return globalThis.x; } return g(); } f();",
+        );
+        assert!(pack.warnings.is_empty());
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let bottom = "storage smelter:smelter environment_stack[0]";
+        let g = function_body(&pack, "g_26");
+        // `globalThis` reads the top level's bindings, where `x` was declared, from the bottom of
+        // the stack while a function runs
+        assert!(g.contains(&format!(
+            "execute if data {bottom} run data modify {evaluations}.expr_55.object set from {bottom}.bindings"
+        )));
+        assert!(g.contains(&format!(
+            "execute unless data {bottom} run data modify {evaluations}.expr_55.object set from storage smelter:smelter current_environment.bindings"
+        )));
+        assert!(g.contains(&format!("{evaluations}.expr_55_object.object.x")));
+        // Undeclared variables are bound there too, so `resolve` finds them at the end of any
+        // parent chain
+        assert!(g.contains(&format!(
+            "execute if score #assigned smelter_internal matches 0 if data {bottom} run data modify {bottom}.bindings.y set from {evaluations}.expr_41"
+        )));
+        assert!(function_body(&pack, "main").contains(
+            "data modify storage smelter:smelter current_environment.bindings.x set from"
+        ));
+        assert!(
+            !pack
+                .functions
+                .iter()
+                .any(|function| function.body.join("\n").contains("global_environment"))
+        );
+    }
+
+    #[test]
+    fn arrays_compare_deeply_by_contents() {
+        let pack = compile_js("let a = [1] === [1]; let b = [1] == [1];");
+        assert!(pack.warnings.is_empty());
+        let main = function_body(&pack, "main");
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        for (left_id, right_id, helper) in [
+            ("expr_8", "expr_16", "strict_equals"),
+            ("expr_29", "expr_36", "loose_equals"),
+        ] {
+            assert!(main.contains(&format!(
+                "data modify storage smelter:smelter internal.equals_args.left set from {evaluations}.{left_id}\ndata modify storage smelter:smelter internal.equals_args.right set from {evaluations}.{right_id}\nexecute store result score #equals smelter_internal run function smelter:{helper}"
+            )));
+        }
+        // `[1] === [1]` is true: the whole values are compared, at any depth, rather than references
+        let strict = function_body(&pack, "strict_equals");
+        assert!(strict.ends_with(
+            "execute store success score #equals smelter_internal run data modify storage smelter:smelter internal.equals_args.compare set from storage smelter:smelter internal.equals_args.right\nexecute if score #equals smelter_internal matches 1 run return 0\nreturn 1"
+        ));
+        // and `==` defers to it for two arrays
+        assert!(function_body(&pack, "loose_equals").contains(
+            "execute if data storage smelter:smelter internal.equals_args.left.array if data storage smelter:smelter internal.equals_args.right.array run return run function smelter:strict_equals"
+        ));
+    }
+
+    #[test]
+    fn runtime_docs_cover_every_storage_path() {
+        let docs = runtime_docs();
+        for path in [
+            "environment_stack",
+            "current_environment",
+            "current_arguments",
+            "current_return_value",
+            "internal",
+        ] {
+            assert!(docs.contains(&format!("- `{path}`: ")), "missing {path}");
+        }
+        // Every path the generated functions use is described
+        let pack = compile_js_with(
+            "function f(a) { return this; } let o = { f }; o.f(1); console.log(o);",
+            CompileOptions {
+                trace: true,
+                ..test_options()
+            },
+        );
+        for function in &pack.functions {
+            for command in &function.body {
+                for (index, prefix) in command.match_indices("storage smelter:smelter ") {
+                    let path = command[index + prefix.len()..]
+                        .split(['.', '[', ' ', '{'])
+                        .next()
+                        .unwrap();
+                    assert!(
+                        STORAGE_LAYOUT
+                            .iter()
+                            .any(|(documented, _)| *documented == path),
+                        "`{path}` isn't documented (used by `{}`)",
+                        function.name
+                    );
+                }
+            }
+        }
+
+        let root = std::env::temp_dir().join(format!("smelter_docs_{}", std::process::id()));
+        let source = root.join("source.js");
+        let out_dir = root.join("datapacks").join("pack");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(&source, "let x = 1;").unwrap();
+        run(CliArguments::parse_from([
+            "smelter",
+            source.to_str().unwrap(),
+            "--out-dir",
+            out_dir.to_str().unwrap(),
+            "--emit-runtime-docs",
+        ]))
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(out_dir.join("STORAGE.md")).unwrap(),
+            docs
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn deleting_an_array_element_leaves_a_hole() {
+        let pack = compile_js("let arr = [1, 2, 3]; delete arr[1];");
+        assert!(pack.warnings.is_empty());
+        let main = function_body(&pack, "main");
+        let array = "storage smelter:smelter current_environment.evaluations.expr_28";
+        // The element is replaced rather than removed, so later indices don't shift
+        assert!(main.contains(&format!(
+            "execute if data {array}.array[1] run data modify {array}.array[1] set value {{undefined: true}}"
+        )));
+        assert!(!main.contains(&format!("data remove {array}.array")));
+        assert!(main.contains(&format!(
+            "execute if data storage smelter:smelter current_environment.bindings.arr run data modify storage smelter:smelter current_environment.bindings.arr set from {array}"
+        )));
+        assert!(main.contains(
+            "data modify storage smelter:smelter current_environment.evaluations.expr_21 set value {boolean: true}"
+        ));
+    }
+
+    #[test]
+    fn trace_commands_are_only_emitted_with_trace() {
+        let source = "function f(a) {} f(1);";
+        let has_trace = |pack: &CompiledPack| {
+            function_body(pack, "initialize").contains("trace set value []")
+                && function_body(pack, "invoke").contains("trace append")
+        };
+        let uses_trace = |pack: &CompiledPack| {
+            ["initialize", "invoke"]
+                .iter()
+                .any(|name| function_body(pack, name).contains("smelter:smelter trace"))
+        };
+        assert!(!uses_trace(&compile_js(source)));
+        assert!(has_trace(&compile_js_with(
+            source,
+            CompileOptions {
+                trace: true,
+                ..test_options()
+            },
+        )));
+    }
+
+    #[test]
+    fn comma_separated_for_updates_all_run_each_iteration() {
+        let pack = compile_js("let i = 0, j = 3; for (; i < j; i++, j--) {}");
+        let loop_body = function_body(&pack, "for_18");
+        let steps = [
+            "function smelter:for_body_18",
+            "evaluations.expr_32_incremented set from storage smelter:smelter internal.add_result",
+            "bindings.i set from storage smelter:smelter current_environment.evaluations.expr_32_incremented",
+            "evaluations.expr_37_decremented set from storage smelter:smelter internal.add_result",
+            "bindings.j set from storage smelter:smelter current_environment.evaluations.expr_37_decremented",
+            "function smelter:for_18",
+        ]
+        .map(|step| loop_body.find(step).expect(step));
+        assert!(steps.is_sorted());
+        // The sequence's result is its last part, the old value of `j`
+        assert!(loop_body.contains(
+            "evaluations.expr_32 set from storage smelter:smelter current_environment.evaluations.expr_37"
+        ));
+        assert!(loop_body.contains(
+            "evaluations.expr_37 set from storage smelter:smelter current_environment.evaluations.expr_37_target"
+        ));
+    }
+
+    #[test]
+    fn finally_blocks_run_after_normal_and_returning_try_blocks() {
+        let pack = compile_js(
+            "function f() { try { return 1; } finally { console.log(2); } } try { f(); } finally { f(); }",
+        );
+        for start in [15, 63] {
+            let caller = pack
+                .functions
+                .iter()
+                .find(|function| {
+                    function
+                        .body
+                        .contains(&format!("function smelter:try_block_{start}"))
+                })
+                .unwrap()
+                .body
+                .join("\n");
+            let try_call = caller
+                .find(&format!("function smelter:try_block_{start}"))
+                .unwrap();
+            let finally_call = caller
+                .find(&format!("function smelter:finally_block_{start}"))
+                .unwrap();
+            // Nothing returns early between the try block and the finally block
+            assert!(!caller[try_call..finally_call].contains("run return"));
+            assert!(
+                caller[try_call..finally_call]
+                    .contains("data remove storage smelter:smelter current_environment.returning")
+            );
+            assert!(caller[finally_call..].contains(&format!(
+                "execute if data storage smelter:smelter current_environment.evaluations.finally_block_{start}_pending.return_value run data modify storage smelter:smelter current_environment.returning set value true"
+            )));
+        }
+        // The finally block compiles to its own subfunction
+        assert!(pack.functions.iter().any(|function| {
+            function.name == "finally_block_15"
+                && function
+                    .body
+                    .iter()
+                    .any(|command| command.contains("tellraw"))
+        }));
+    }
+
+    #[test]
+    fn hashed_function_names_are_stable_and_distinct() {
+        let source = "function a() {} function b() {} const c = () => {}; const d = function () {}; const e = { get x() { return 1; } };\nif (c) {} else {} for (;;) {} switch (d) { case 1: } try {} finally {} let g = c ? 1 : 2; g ||= 3; [1].map(c);";
+        let compile_hashed = || {
+            compile_js_with(
+                source,
+                CompileOptions {
+                    function_names: NameStrategy::Hashed,
+                    ..test_options()
+                },
+            )
+        };
+        let names = |pack: &CompiledPack| {
+            pack.functions
+                .iter()
+                .map(|function| function.name.clone())
+                .filter(|name| name.starts_with("f_"))
+                .collect::<Vec<_>>()
+        };
+        let pack = compile_hashed();
+        let hashed = names(&pack);
+        assert_eq!(hashed, names(&compile_hashed()));
+        let mut distinct = hashed.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), hashed.len());
+        for name in &hashed {
+            assert_eq!(name.len(), 14);
+            assert!(name[2..].chars().all(|c| c.is_ascii_hexdigit()));
+        }
+        // Every generated name is hashed, so none of them carry a source offset
+        for function in &pack.functions {
+            match pack.function_kinds[&function.name] {
+                FunctionKind::User | FunctionKind::Subfunction if function.name != "main" => {
+                    assert!(hashed.contains(&function.name), "{}", function.name);
+                }
+                _ => {}
+            }
+        }
+        let readable = compile_js(source);
+        assert!(names(&readable).is_empty());
+        for name in [
+            "a_0",
+            "if_consequent_115",
+            "for_body_133",
+            "switch_case_158",
+            "finally_block_168",
+        ] {
+            assert!(
+                readable
+                    .functions
+                    .iter()
+                    .any(|function| function.name == name),
+                "{name}"
+            );
+        }
+        assert_eq!(readable.functions.len(), pack.functions.len());
+    }
+
+    #[test]
+    fn spreading_a_call_result_appends_its_elements_after_the_call() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js("function g() { return [1, 2]; } f(...g());");
+        let main = function_body(&pack, "main");
+        let steps = [
+            format!("{evaluations}.expr_37 set from storage smelter:smelter current_return_value"),
+            format!(
+                "internal.to_array_args.value set from {evaluations}.expr_37\nfunction smelter:to_array"
+            ),
+            format!(
+                "{evaluations}.expr_32_arguments append from storage smelter:smelter internal.to_array_result.array[]"
+            ),
+            format!("{evaluations}.expr_32 set from storage smelter:smelter current_return_value"),
+        ]
+        .map(|step| main.find(&step).expect(&step));
+        assert!(steps.is_sorted());
+        let g = function_body(&pack, "g_0");
+        assert!(g.contains("{number: 1d}") && g.contains("{number: 2d}"));
+    }
+
+    #[test]
+    fn generated_nbt_is_checked_outside_macro_lines() {
+        let malformed = Mcfunction {
+            name: String::from("broken"),
+            body: vec![
+                String::from("data modify storage smelter:smelter internal set value {}"),
+                String::from("data modify storage smelter:smelter internal.a set value {a: 1"),
+                String::from("$data modify storage smelter:smelter internal.b set value $(b)"),
+                String::from(
+                    "execute if data storage smelter:smelter internal.a run data modify storage smelter:smelter internal.c append value [1 2]",
+                ),
+            ],
+        };
+        assert_eq!(
+            check_generated_nbt(&[malformed]),
+            [
+                "smelter:broken line 2: unclosed `{`",
+                "smelter:broken line 4: expected `,` or `]` but found `2`",
+            ]
+        );
+        // Every core function and the compiled program pass, in both spacings
+        let source =
+            "let o = { a: [1, 'b'], get c() { return null; } }; console.log(o.c, `${o.a}`);";
+        for compact_nbt in [false, true] {
+            let pack = compile_js_with(
+                source,
+                CompileOptions {
+                    compact_nbt,
+                    ..test_options()
+                },
+            );
+            assert_eq!(check_generated_nbt(&pack.functions), Vec::<String>::new());
+        }
+    }
+
+    #[test]
+    fn arithmetic_converts_string_operands_to_numbers() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js("let a = \"3\" * 2; let b = \"x\" * 2;");
+        let main = function_body(&pack, "main");
+        for (start, string, right) in [(8, "3", 14), (25, "x", 31)] {
+            let steps = [
+                format!("{evaluations}.expr_{start} set value {{string: '{string}'}}"),
+                format!("{evaluations}.expr_{right} set value {{number: 2d}}"),
+                format!(
+                    "internal.arithmetic_args.left set from {evaluations}.expr_{start}\ndata modify storage smelter:smelter internal.arithmetic_args.right set from {evaluations}.expr_{right}\nfunction smelter:multiply"
+                ),
+                format!(
+                    "{evaluations}.expr_{start} set from storage smelter:smelter internal.arithmetic_result"
+                ),
+            ]
+            .map(|step| main.find(&step).expect(&step));
+            assert!(steps.is_sorted());
+        }
+        // Both operands go through `to_number`, and a NaN one makes the result NaN
+        let multiply = function_body(&pack, "multiply");
+        let steps = [
+            "internal.to_number_args.value set from storage smelter:smelter internal.arithmetic_args.left",
+            "internal.to_number_result.nan run return run data modify storage smelter:smelter internal.arithmetic_result set value {nan: true}",
+            "internal.to_number_args.value set from storage smelter:smelter internal.arithmetic_args.right",
+            "#arithmetic_left smelter_internal *= #arithmetic_right smelter_internal",
+            "store result storage smelter:smelter internal.arithmetic_result.number double 1",
+        ]
+        .map(|step| multiply.find(step).expect(step));
+        assert!(steps.is_sorted());
+        // Strings that don't parse as numbers are NaN
+        let to_number = function_body(&pack, "to_number");
+        assert!(to_number.contains("function smelter:parse_number with"));
+        assert!(to_number.ends_with(
+            "data modify storage smelter:smelter internal.to_number_result set value {nan: true}"
+        ));
+    }
+
+    #[test]
+    fn inexact_arithmetic_is_warned_about_and_documented() {
+        let pack = compile_js("let a = 7 / 2;\nlet b = 0.5 * 2;\nlet c = 6 - 2;\n");
+        let messages: Vec<String> = pack
+            .warnings
+            .iter()
+            .map(|w| w.message.to_string())
+            .collect();
+        assert_eq!(messages.len(), 2, "{messages:?}");
+        assert!(messages[0].contains("fractional quotients"));
+        assert!(messages[1].contains("fractional operands"));
+        assert_eq!(pack.warnings[1].labels.as_ref().unwrap()[0].offset(), 23);
+
+        let divide = function_body(&pack, "divide");
+        assert!(divide.contains(
+            "execute if score #arithmetic_right_zero smelter_internal matches 1 if score #arithmetic_left_zero smelter_internal matches 1 run"
+        ));
+        assert!(runtime_docs().contains("`/` rounds the quotient down"));
+    }
+
+    #[test]
+    fn uninstall_removes_everything_initialize_sets_up() {
+        let source = "console.log(1);";
+        assert!(
+            !compile_js(source)
+                .functions
+                .iter()
+                .any(|function| function.name == "uninstall")
+        );
+        let pack = compile_js_with(
+            source,
+            CompileOptions {
+                emit_uninstall: true,
+                trace: true,
+                ..test_options()
+            },
+        );
+        let uninstall = function_body(&pack, "uninstall");
+        for command in &pack
+            .functions
+            .iter()
+            .find(|function| function.name == "initialize")
+            .unwrap()
+            .body
+        {
+            if let Some(rest) = command.strip_prefix("data modify storage smelter:smelter ") {
+                let path = rest.split(['.', ' ']).next().unwrap();
+                assert!(
+                    uninstall.contains(&format!("data remove storage smelter:smelter {path}\n")),
+                    "{path} is not removed"
+                );
+            }
+        }
+        assert!(function_body(&pack, "initialize").contains("objectives add smelter_internal"));
+        assert!(uninstall.ends_with("scoreboard objectives remove smelter_internal"));
+    }
+
+    #[test]
+    fn method_calls_bind_this_to_the_receiver() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js("let o = { x: 1, m() { return this.x; } }; o.m();");
+        // The call passes the object `m` was read from
+        let calls_with_receiver = pack
+            .functions
+            .iter()
+            .map(|function| function.body.join("\n"))
+            .find(|body| {
+                body.contains(&format!(
+                    "current_this set from {evaluations}.expr_42_object"
+                ))
+            })
+            .unwrap();
+        assert!(
+            calls_with_receiver.find("current_this set from").unwrap()
+                < calls_with_receiver.find("function smelter:invoke").unwrap()
+        );
+        // The method binds `this` on entry and reads `x` from it
+        let method = pack
+            .functions
+            .iter()
+            .map(|function| function.body.join("\n"))
+            .find(|body| body.contains("evaluating identifier this"))
+            .unwrap();
+        let steps = [
+            "current_environment.bindings.this set from storage smelter:smelter current_this",
+            "evaluations.expr_29 set from storage smelter:smelter current_environment.bindings.this",
+            "evaluations.expr_29_object set from storage smelter:smelter current_environment.evaluations.expr_29",
+            "evaluations.expr_29 set from storage smelter:smelter current_environment.evaluations.expr_29_object.object.x",
+        ]
+        .map(|step| method.find(step).expect(step));
+        assert!(steps.is_sorted());
+        // At the top level, `this` is undefined
+        let main = function_body(&compile_js("let t = this;"), "main");
+        assert!(main.contains(&format!(
+            "execute unless data {evaluations}.expr_8 run data modify {evaluations}.expr_8 set value {{undefined: true}}"
+        )));
+    }
+
+    #[test]
+    fn parse_int_and_parse_float_call_the_parsers() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js(
+            "let a = parseInt(\"42\"); let b = parseInt(\"ff\", 16); let c = parseFloat(\"3.14\");",
+        );
+        let main = function_body(&pack, "main");
+        for (start, argument, string, helper) in [
+            (8, 17, "42", "parse_int"),
+            (32, 41, "ff", "parse_int"),
+            (60, 71, "3.14", "parse_float"),
+        ] {
+            let steps = [
+                format!("{evaluations}.expr_{argument} set value {{string: '{string}'}}"),
+                format!("{evaluations}.expr_{start}_argument set from {evaluations}.expr_{argument}"),
+                format!("to_string_args.value set from {evaluations}.expr_{start}_argument"),
+                format!("function smelter:{helper}"),
+                format!("{evaluations}.expr_{start} set from storage smelter:smelter internal.parse_result"),
+            ]
+            .map(|step| main.find(&step).expect(&step));
+            assert!(steps.is_sorted());
+        }
+        // Only the explicit radix is converted, and the others stay 0 (the default)
+        assert!(main.contains(&format!(
+            "internal.to_number_args.value set from {evaluations}.expr_47\nfunction smelter:to_number\nexecute store result score #parse_radix smelter_internal"
+        )));
+        assert_eq!(main.matches("store result score #parse_radix").count(), 1);
+        assert_eq!(
+            main.matches("scoreboard players set #parse_radix smelter_internal 0")
+                .count(),
+            3
+        );
+        let parse_int = function_body(&pack, "parse_int");
+        assert!(parse_int.contains(
+            "execute if score #parse_radix smelter_internal matches 0 run scoreboard players set #parse_radix smelter_internal 10"
+        ));
+        assert!(
+            parse_int.contains(
+                "unless score #parse_radix smelter_internal matches 2..36 run return run"
+            )
+        );
+        // Digits are looked up in a table covering both cases up to base 36
+        let initialize = function_body(&pack, "initialize");
+        assert!(initialize.contains("internal.digit_values set value {0: 0,"));
+        assert!(initialize.contains("f: 15,") && initialize.contains("F: 15,"));
+    }
+
+    #[test]
+    fn push_and_pop_change_array_variables_in_place() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js("let arr = [1]; arr.push(2); let x = arr.pop(); f().push(3);");
+        let main = function_body(&pack, "main");
+        // Only arrays get the built-in methods, and anything else calls its own property
+        for (start, method) in [(15, 19), (36, 40)] {
+            assert!(main.contains(&format!(
+                "execute if data {evaluations}.expr_{start}_receiver.array run function smelter:builtin_method_{method}"
+            )));
+            assert!(main.contains(&format!(
+                "execute unless data {evaluations}.expr_{start}_receiver.array run function smelter:method_{method}"
+            )));
+        }
+        // `push` appends the arguments, gives the new length and writes the array back
+        let push = function_body(&pack, "builtin_method_19");
+        let steps = [
+            format!("{evaluations}.expr_15_receiver.array append from {evaluations}.expr_15_arguments[]"),
+            format!(
+                "execute store result {evaluations}.expr_15.number double 1 run data get {evaluations}.expr_15_receiver.array"
+            ),
+            format!(
+                "bindings.arr set from storage smelter:smelter current_environment.evaluations.expr_15_receiver"
+            ),
+        ]
+        .map(|step| push.find(&step).expect(&step));
+        assert!(steps.is_sorted());
+        // `pop` gives the last element, or undefined, and removes it
+        let pop = function_body(&pack, "builtin_method_40");
+        let steps = [
+            format!("{evaluations}.expr_36 set value {{undefined: true}}"),
+            format!("{evaluations}.expr_36 set from {evaluations}.expr_36_receiver.array[-1]"),
+            format!("data remove {evaluations}.expr_36_receiver.array[-1]"),
+            format!(
+                "bindings.arr set from storage smelter:smelter current_environment.evaluations.expr_36_receiver"
+            ),
+        ]
+        .map(|step| pop.find(&step).expect(&step));
+        assert!(steps.is_sorted());
+        // A receiver that isn't a variable has nowhere to write the array back
+        let warning = pack
+            .warnings
+            .iter()
+            .find(|warning| {
+                warning
+                    .message
+                    .contains("`push` on an array that isn't a variable")
+            })
+            .unwrap();
+        let label = &warning.labels.as_ref().unwrap()[0];
+        assert_eq!((label.offset(), label.len()), (47, 3));
+    }
+
+    #[test]
+    fn json_stringify_gives_a_string_value() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js("let s = JSON.stringify({ a: 1 }); JSON.parse(s, r);");
+        let main = function_body(&pack, "main");
+        let steps = [
+            "data modify storage smelter:smelter internal.json_stringify_args set value {}",
+            "evaluations.expr_23 set value {object: {}",
+            "internal.json_stringify_args.value set from storage smelter:smelter current_environment.evaluations.expr_23",
+            "function smelter:json_stringify\n",
+            "evaluations.expr_8 set from storage smelter:smelter internal.json_stringify_result",
+        ]
+        .map(|step| main.find(step).expect(step));
+        assert!(steps.is_sorted());
+        // The output is built up in `json_output` and wrapped as a string
+        let stringify = function_body(&pack, "json_stringify");
+        assert!(stringify.ends_with(
+            "data modify storage smelter:smelter internal.json_stringify_result set value {string: ''}\ndata modify storage smelter:smelter internal.json_stringify_result.string set from storage smelter:smelter internal.json_output"
+        ));
+        assert!(
+            function_body(&pack, "json_stringify_value")
+                .contains("run return run function smelter:json_stringify_object")
+        );
+        // `JSON.parse` converts its argument to a string first, and a reviver isn't supported
+        assert!(main.contains(&format!(
+            "internal.to_string_args.value set from {evaluations}.expr_45\nfunction smelter:to_string\ndata modify storage smelter:smelter internal.json_parse_args.string set from storage smelter:smelter internal.to_string_result"
+        )));
+        let [warning] = &pack.warnings[..] else {
+            panic!("expected one warning: {:?}", pack.warnings);
+        };
+        assert!(warning.message.contains("extra arguments to `JSON.parse`"));
+    }
+
+    #[test]
+    fn json_stringify_checks_strings_and_keys_before_writing_them() {
+        let pack = compile_js("JSON.stringify({ 'a\"b': 'c' });");
+        let check = function_body(&pack, "json_check_string");
+        for c in ["{char: '\"'}", "{char: '\\''}", "{char: '\\\\'}"] {
+            assert!(
+                check.contains(&format!(
+                    "execute if data storage smelter:smelter internal.json_check{c} run return run scoreboard players set #json_unwritable smelter_internal 1"
+                )),
+                "{c}"
+            );
+        }
+        for (name, source) in [
+            ("json_stringify_string", "json_stack[-1].value.string"),
+            ("json_stringify_property", "json_stack[-1].key"),
+        ] {
+            let body = function_body(&pack, name);
+            let checked = body
+                .find(&format!("internal.json_check.rest set from storage smelter:smelter internal.{source}\nfunction smelter:json_check_string"))
+                .expect(name);
+            assert!(checked < body.find("function smelter:json_append").unwrap());
+        }
+        // A string that can't be written makes the result undefined instead of invalid JSON
+        let stringify = function_body(&pack, "json_stringify");
+        assert!(stringify.contains(
+            "execute if score #json_unwritable smelter_internal matches 1 run return run data modify storage smelter:smelter internal.json_stringify_result set value {undefined: true}"
+        ));
+        assert!(
+            function_body(&pack, "json_stringify_value").contains(
+                "execute if score #json_unwritable smelter_internal matches 1 run return 0"
+            )
+        );
+    }
+
+    #[test]
+    fn destructuring_assignment_updates_existing_bindings() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let bindings = "storage smelter:smelter current_environment.bindings";
+        let pack = compile_js("let a, b; [a, b] = [1, 2]; function f() { ({ a } = { a: b }); }");
+        let main = function_body(&pack, "main");
+        let steps = [
+            format!("{evaluations}.expr_10 set from {evaluations}.expr_19"),
+            format!(
+                "execute if data {evaluations}.expr_10.array[0] run data modify {evaluations}.destructured_11 set from {evaluations}.expr_10.array[0]"
+            ),
+            format!(
+                "execute if data {bindings}.a run data modify {bindings}.a set from {evaluations}.destructured_11"
+            ),
+            format!(
+                "execute if data {evaluations}.expr_10.array[1] run data modify {evaluations}.destructured_14 set from {evaluations}.expr_10.array[1]"
+            ),
+            format!(
+                "execute if data {bindings}.b run data modify {bindings}.b set from {evaluations}.destructured_14"
+            ),
+        ]
+        .map(|step| main.find(&step).expect(&step));
+        assert!(steps.is_sorted());
+        // Inside a function, the name resolves to the enclosing environment's binding instead of
+        // declaring a new one
+        let f = pack
+            .functions
+            .iter()
+            .find(|function| function.name.starts_with("f_"))
+            .unwrap()
+            .body
+            .join("\n");
+        assert!(f.contains(&format!(
+            "{evaluations}.expr_43 set from {evaluations}.expr_51"
+        )));
+        assert!(f.contains(&format!(
+            "execute unless data {bindings}.a store result score #assigned smelter_internal run function smelter:assign"
+        )));
+        assert!(!f.contains(&format!("data modify {bindings}.a set value")));
+    }
+
+    #[test]
+    fn function_list_gives_each_function_its_kind() {
+        let pack = compile_js(
+            "function count() { \"smelter say\"; } function f(x) { if (x) { return 1; } } f(count(\"hi\"));",
+        );
+        let list: serde_json::Value = serde_json::from_str(&function_list_json(&pack)).unwrap();
+        let kinds = list["functions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|function| {
+                (
+                    function["name"].as_str().unwrap(),
+                    function["kind"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds.len(), pack.functions.len());
+        for expected in [
+            ("smelter:main", "user"),
+            ("smelter:f_36", "user"),
+            ("smelter:if_consequent_52", "subfunction"),
+            ("smelter:count_0", "wrapper"),
+            ("smelter:say_macro", "macro"),
+            ("smelter:initialize", "core"),
+            ("smelter:invoke", "core"),
+            ("smelter:to_string", "core"),
+        ] {
+            assert!(kinds.contains(&expected), "missing {expected:?}");
+        }
+    }
+
+    #[test]
+    fn typeof_gives_the_js_type_of_every_value_shape() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        for (argument, expected) in [
+            ("undefined", "undefined"),
+            ("null", "object"),
+            ("[1]", "object"),
+            ("{}", "object"),
+            ("true", "boolean"),
+            ("1", "number"),
+            ("1n", "bigint"),
+            ("'s'", "string"),
+            ("(() => 1)", "function"),
+        ] {
+            let main = function_body(&compile_js(&format!("let t = typeof {argument};")), "main");
+            let default = format!("{evaluations}.expr_8 set value {{string: 'undefined'}}");
+            assert!(main.contains(&default), "{argument}");
+            // The shape is the key of the value the argument evaluates to, and selects the type
+            let shape = ["expr_15", "expr_16"].iter().find_map(|id| {
+                let prefix = format!("{evaluations}.{id} set value {{");
+                let start = main.find(&prefix)? + prefix.len();
+                main[start..].split(':').next()
+            });
+            match shape {
+                Some(shape) => assert!(
+                    main.contains(&format!(
+                        "execute if data {evaluations}.expr_15.{shape} run data modify {evaluations}.expr_8 set value {{string: '{expected}'}}"
+                    )),
+                    "{argument} has shape {shape}"
+                ),
+                None => assert_eq!(expected, "undefined"),
+            }
+        }
+    }
+
+    #[test]
+    fn exponentiation_multiplies_in_a_loop() {
+        let evaluations = "storage smelter:smelter current_environment.evaluations";
+        let pack = compile_js("let p = 2 ** 8;");
+        let main = function_body(&pack, "main");
+        let steps = [
+            format!("{evaluations}.expr_8 set value {{number: 2d}}"),
+            format!("{evaluations}.expr_13 set value {{number: 8d}}"),
+            format!(
+                "internal.arithmetic_args.left set from {evaluations}.expr_8\ndata modify storage smelter:smelter internal.arithmetic_args.right set from {evaluations}.expr_13\nfunction smelter:exponentiate"
+            ),
+            format!("{evaluations}.expr_8 set from storage smelter:smelter internal.arithmetic_result"),
+        ]
+        .map(|step| main.find(&step).expect(&step));
+        assert!(steps.is_sorted());
+        assert!(pack.warnings.is_empty());
+        // The base is squared once per bit of the exponent, and multiplied into 1 for each set bit
+        let exponentiate = function_body(&pack, "exponentiate");
+        let steps = [
+            "function smelter:to_number",
+            "#arithmetic_right smelter_internal matches ..-1 run return run data modify storage smelter:smelter internal.arithmetic_result set value {nan: true}",
+            "#arithmetic_base smelter_internal = #arithmetic_left smelter_internal",
+            "scoreboard players set #arithmetic_left smelter_internal 1",
+            "function smelter:exponentiate_step",
+            "store result storage smelter:smelter internal.arithmetic_result.number double 1 run scoreboard players get #arithmetic_left smelter_internal",
+        ]
+        .map(|step| exponentiate.find(step).expect(step));
+        assert!(steps.is_sorted());
+        assert_eq!(
+            function_body(&pack, "exponentiate_step"),
+            [
+                "execute if score #arithmetic_right smelter_internal matches ..0 run return 0",
+                "scoreboard players operation #arithmetic_bit smelter_internal = #arithmetic_right smelter_internal",
+                "scoreboard players operation #arithmetic_bit smelter_internal %= #2 smelter_internal",
+                "execute if score #arithmetic_bit smelter_internal matches 1 run scoreboard players operation #arithmetic_left smelter_internal *= #arithmetic_base smelter_internal",
+                "scoreboard players operation #arithmetic_base smelter_internal *= #arithmetic_base smelter_internal",
+                "scoreboard players operation #arithmetic_right smelter_internal /= #2 smelter_internal",
+                "function smelter:exponentiate_step",
+            ]
+            .join("\n")
+        );
+        // Literal exponents that can't give an integer are reported
+        let pack = compile_js("let a = 2 ** -1; let b = 2 ** 0.5;");
+        let offsets = pack
+            .warnings
+            .iter()
+            .map(|warning| {
+                assert!(warning.message.contains("negative or fractional exponents"));
+                warning.labels.as_ref().unwrap()[0].offset()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [13, 30]);
+    }
+
+    #[test]
+    fn mcmeta_extra_fields_are_merged_into_the_written_meta() {
+        let root = std::env::temp_dir().join(format!("smelter_mcmeta_{}", std::process::id()));
+        let pack = root.join("datapacks").join("pack");
+        std::fs::create_dir_all(&pack).unwrap();
+        let source = root.join("main.js");
+        std::fs::write(&source, "let x = 1;").unwrap();
+        let extra = root.join("extra.json");
+        let arguments = || {
+            CliArguments::parse_from([
+                String::from("smelter"),
+                source.display().to_string(),
+                String::from("--out-dir"),
+                pack.display().to_string(),
+                String::from("--mcmeta-extra"),
+                extra.display().to_string(),
+            ])
+        };
+        std::fs::write(
+            &extra,
+            r#"{
+                "overlays": {"entries": [{"formats": [94, 95], "directory": "overlay_95"}]},
+                "filter": {"block": [{"namespace": "minecraft"}]},
+                "pack": {"description": "mine", "max_format": [99, 0], "id": "extra"}
+            }"#,
+        )
+        .unwrap();
+        run(arguments()).unwrap();
+        let meta: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(pack.join("pack.mcmeta")).unwrap())
+                .unwrap();
+        assert_eq!(meta["overlays"]["entries"][0]["directory"], "overlay_95");
+        assert_eq!(meta["filter"]["block"][0]["namespace"], "minecraft");
+        // Fields of `pack` are added, but the description and formats stay generated
+        assert_eq!(meta["pack"]["id"], "extra");
+        assert_eq!(meta["pack"]["description"], "smelter prototype");
+        assert_eq!(
+            meta["pack"]["max_format"],
+            serde_json::json!([PACK_FORMAT.0, PACK_FORMAT.1])
+        );
+
+        // Errors name the extra file
+        std::fs::write(&extra, "[]").unwrap();
+        let error = format!("{:#}", run(arguments()).unwrap_err());
+        assert!(error.contains(&extra.display().to_string()), "{error}");
+        assert!(
+            error.contains("The file should contain a JSON object"),
+            "{error}"
+        );
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
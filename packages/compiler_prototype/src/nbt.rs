@@ -0,0 +1,317 @@
+pub enum Nbt {
+    Boolean(bool),
+    Int(i32),
+    Long(i64),
+    Double(f64),
+    String(String),
+    List(Vec<Nbt>),
+    Compound(Vec<(String, Nbt)>),
+}
+
+impl Nbt {
+    pub fn compound<'a>(entries: impl IntoIterator<Item = (&'a str, Nbt)>) -> Self {
+        Nbt::Compound(
+            entries
+                .into_iter()
+                .map(|(key, value)| (String::from(key), value))
+                .collect(),
+        )
+    }
+
+    pub fn string(value: &str) -> Self {
+        Nbt::String(String::from(value))
+    }
+
+    pub fn to_snbt(&self, compact: bool) -> String {
+        let (colon, comma) = if compact { (":", ",") } else { (": ", ", ") };
+        match self {
+            Nbt::Boolean(value) => value.to_string(),
+            Nbt::Int(value) => value.to_string(),
+            Nbt::Long(value) => format!("{value}l"),
+            Nbt::Double(value) => format_double(*value),
+            Nbt::String(value) => quote_string(value),
+            Nbt::List(elements) => format!(
+                "[{}]",
+                elements
+                    .iter()
+                    .map(|element| element.to_snbt(compact))
+                    .collect::<Vec<String>>()
+                    .join(comma)
+            ),
+            Nbt::Compound(entries) => format!(
+                "{{{}}}",
+                entries
+                    .iter()
+                    .map(|(key, value)| format!(
+                        "{}{colon}{}",
+                        format_key(key),
+                        value.to_snbt(compact)
+                    ))
+                    .collect::<Vec<String>>()
+                    .join(comma)
+            ),
+        }
+    }
+}
+
+pub fn format_double(value: f64) -> String {
+    // SNBT has no form for infinities, so saturate to the largest finite double
+    let value = value.clamp(f64::MIN, f64::MAX);
+    // SNBT doesn't accept exponent notation like `1e3`, and `Display` for `f64` always writes a
+    // plain decimal (unlike `Debug`)
+    format!("{value}d")
+}
+
+pub fn format_path_key(key: &str) -> String {
+    // `.` separates the nodes of an NBT path, so unlike in SNBT it can't appear in an unquoted key
+    if key.contains('.') {
+        quote_string(key)
+    } else {
+        format_key(key)
+    }
+}
+
+fn quote_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn format_key(key: &str) -> String {
+    // Keys made of these characters can be written unquoted
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+    {
+        String::from(key)
+    } else {
+        quote_string(key)
+    }
+}
+
+pub fn check_snbt(text: &str) -> Result<(), String> {
+    // Checks that `text` is a single well-formed SNBT value, as accepted by `data modify ... value`
+    let mut checker = SnbtChecker {
+        chars: text.chars().peekable(),
+    };
+    checker.check_value()?;
+    checker.skip_whitespace();
+    match checker.chars.next() {
+        None => Ok(()),
+        Some(c) => Err(format!("unexpected `{c}` after the value")),
+    }
+}
+
+struct SnbtChecker<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl SnbtChecker<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|c| c.is_whitespace()).is_some() {}
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(format!("expected `{expected}` but found `{c}`")),
+            None => Err(format!("expected `{expected}` but the value ended")),
+        }
+    }
+
+    fn check_value(&mut self) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some('{') => self.check_compound(),
+            Some('[') => self.check_list(),
+            Some('\'' | '"') => self.check_quoted_string(),
+            Some(_) => {
+                let token = self.take_unquoted()?;
+                // Unquoted words are strings, but anything that starts like a number has to be one,
+                // since a typo there would silently become a string
+                let starts_like_number = token
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'));
+                let digits = token.trim_end_matches(|c: char| "bBsSlLfFdD".contains(c));
+                if starts_like_number && digits.parse::<f64>().is_err() {
+                    return Err(format!("`{token}` is not a valid number"));
+                }
+                Ok(())
+            }
+            None => Err(String::from("expected a value but the value ended")),
+        }
+    }
+
+    fn check_compound(&mut self) -> Result<(), String> {
+        self.expect('{')?;
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&'}').is_some() {
+            return Ok(());
+        }
+        loop {
+            self.skip_whitespace();
+            match self.chars.peek() {
+                Some('\'' | '"') => self.check_quoted_string()?,
+                _ => {
+                    self.take_unquoted()?;
+                }
+            }
+            self.expect(':')?;
+            self.check_value()?;
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some('}') => return Ok(()),
+                Some(c) => return Err(format!("expected `,` or `}}` but found `{c}`")),
+                None => return Err(String::from("unclosed `{`")),
+            }
+        }
+    }
+
+    fn check_list(&mut self) -> Result<(), String> {
+        self.expect('[')?;
+        // Byte, int and long arrays start with a type prefix like `I;`
+        let mut lookahead = self.chars.clone();
+        if matches!(lookahead.next(), Some('B' | 'I' | 'L')) && lookahead.next() == Some(';') {
+            self.chars = lookahead;
+        }
+        self.skip_whitespace();
+        if self.chars.next_if_eq(&']').is_some() {
+            return Ok(());
+        }
+        loop {
+            self.check_value()?;
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some(',') => {}
+                Some(']') => return Ok(()),
+                Some(c) => return Err(format!("expected `,` or `]` but found `{c}`")),
+                None => return Err(String::from("unclosed `[`")),
+            }
+        }
+    }
+
+    fn check_quoted_string(&mut self) -> Result<(), String> {
+        let Some(quote) = self.chars.next() else {
+            return Err(String::from("expected a string but the value ended"));
+        };
+        while let Some(c) = self.chars.next() {
+            match c {
+                '\\' => {
+                    if self.chars.next().is_none() {
+                        break;
+                    }
+                }
+                c if c == quote => return Ok(()),
+                _ => {}
+            }
+        }
+        Err(format!("unclosed `{quote}`"))
+    }
+
+    fn take_unquoted(&mut self) -> Result<String, String> {
+        let mut token = String::new();
+        while let Some(c) = self
+            .chars
+            .next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+        {
+            token.push(c);
+        }
+        if token.is_empty() {
+            return Err(match self.chars.peek() {
+                Some(c) => format!("unexpected `{c}`"),
+                None => String::from("expected a value but the value ended"),
+            });
+        }
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_are_plain_decimals() {
+        assert_eq!(format_double(-5.0), "-5d");
+        assert_eq!(format_double(1e3), "1000d");
+        assert_eq!(format_double(0.5), "0.5d");
+        assert_eq!(format_double(f64::INFINITY), format_double(f64::MAX));
+        for value in [-5.0, 1e3, 0.5, 1e21, 1e-7, f64::NEG_INFINITY] {
+            let text = format_double(value);
+            assert!(!text.contains('e'), "{text}");
+            assert_eq!(check_snbt(&text), Ok(()), "{text}");
+        }
+    }
+
+    #[test]
+    fn compact_snbt_drops_separator_spaces() {
+        let value = Nbt::compound([
+            ("parent", Nbt::Int(-1)),
+            ("bindings", Nbt::compound([])),
+            ("text", Nbt::string("a, b: c")),
+            ("list", Nbt::List(vec![Nbt::Boolean(true), Nbt::Long(2)])),
+        ]);
+        assert_eq!(
+            value.to_snbt(false),
+            "{parent: -1, bindings: {}, text: 'a, b: c', list: [true, 2l]}"
+        );
+        assert_eq!(
+            value.to_snbt(true),
+            "{parent:-1,bindings:{},text:'a, b: c',list:[true,2l]}"
+        );
+        assert_eq!(check_snbt(&value.to_snbt(true)), Ok(()));
+    }
+
+    #[test]
+    fn strings_and_keys_are_quoted_only_when_needed() {
+        let value = Nbt::compound([
+            ("plain_key-1.5+", Nbt::string("it's a \\ path")),
+            ("two words", Nbt::Double(-1.5)),
+            ("", Nbt::List(Vec::new())),
+        ]);
+        let text = value.to_snbt(false);
+        assert_eq!(
+            text,
+            "{plain_key-1.5+: 'it\\'s a \\\\ path', 'two words': -1.5d, '': []}"
+        );
+        assert_eq!(check_snbt(&text), Ok(()));
+        assert_eq!(format_path_key("a.b"), "'a.b'");
+        assert_eq!(format_path_key("a_b"), "a_b");
+    }
+
+    #[test]
+    fn check_snbt_accepts_well_formed_values() {
+        for text in [
+            "true",
+            "-1",
+            "2l",
+            "[I; 1, 2, 3]",
+            "[]",
+            "{}",
+            "{number: 1d}",
+            "{'a b': \"x\", c: [{}, {d: 'e\\'f'}]}",
+            "  {a:1b,b:[1s,2s]}  ",
+        ] {
+            assert_eq!(check_snbt(text), Ok(()), "{text}");
+        }
+    }
+
+    #[test]
+    fn check_snbt_rejects_malformed_values() {
+        for (text, message) in [
+            ("", "expected a value but the value ended"),
+            ("{a: 1", "unclosed `{`"),
+            ("[1, 2", "unclosed `[`"),
+            ("{a 1}", "expected `:` but found `1`"),
+            ("[1 2]", "expected `,` or `]` but found `2`"),
+            ("{a: 1}}", "unexpected `}` after the value"),
+            ("'abc", "unclosed `'`"),
+            ("1.2.3d", "`1.2.3d` is not a valid number"),
+            ("{a: ,}", "unexpected `,`"),
+        ] {
+            assert_eq!(check_snbt(text), Err(String::from(message)), "{text}");
+        }
+    }
+}